@@ -0,0 +1,208 @@
+//! Compares the price-level order book against the original `Vec`-backed book.
+//!
+//! Run with `cargo bench -p lobster`.
+mod vec_book;
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId,
+    Criterion,
+};
+use lobster::{Fill, Order, OrderBook, OrderId, Price};
+
+use vec_book::VecOrderBook;
+
+/// Book depths to benchmark, in resting orders per side.
+const DEPTHS: [usize; 3] = [10, 100, 1000];
+
+/// Mid price the benchmark books are quoted around.
+const MID: Price = 5000;
+
+/// The operations shared by both order book implementations.
+trait Book: Default + Clone {
+    fn add(&mut self, order: Order) -> Vec<Fill>;
+    fn remove(&mut self, id: OrderId) -> Option<Order>;
+}
+
+impl Book for OrderBook {
+    fn add(&mut self, order: Order) -> Vec<Fill> {
+        Self::add(self, order, 0, None).fills
+    }
+    fn remove(&mut self, id: OrderId) -> Option<Order> {
+        Self::remove(self, id)
+    }
+}
+
+impl Book for VecOrderBook {
+    fn add(&mut self, order: Order) -> Vec<Fill> {
+        Self::add(self, order)
+    }
+    fn remove(&mut self, id: OrderId) -> Option<Order> {
+        Self::remove(self, id)
+    }
+}
+
+/// Builds a two sided book with `depth` orders per side spread over 100 levels.
+///
+/// Bids get ids `0..depth` and asks get ids `depth..2 * depth`.
+fn build_book<B: Book>(depth: usize) -> B {
+    let mut book = B::default();
+    for i in 0..depth {
+        let offset = Price::try_from(i % 100).unwrap() + 1;
+        let id = OrderId::try_from(i).unwrap();
+        book.add(Order::buy(id, 10, MID - offset));
+    }
+    for i in 0..depth {
+        let offset = Price::try_from(i % 100).unwrap() + 1;
+        let id = OrderId::try_from(depth + i).unwrap();
+        book.add(Order::sell(id, 10, MID + offset));
+    }
+    book
+}
+
+/// Adds a passive order to the back of the book.
+fn bench_add_passive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_passive");
+    for depth in DEPTHS {
+        let order = Order::buy(OrderId::MAX, 10, MID - 100);
+        group.bench_with_input(
+            BenchmarkId::new("price_level", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<OrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.add(black_box(order), 0, None),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vec", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<VecOrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.add(black_box(order)),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Cancels the order at the back of the book, the worst case for the `Vec` book.
+fn bench_cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel");
+    for depth in DEPTHS {
+        let id = OrderId::try_from(depth).unwrap() - 1;
+        group.bench_with_input(
+            BenchmarkId::new("price_level", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<OrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.remove(black_box(id)),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vec", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<VecOrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.remove(black_box(id)),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Sweeps the top ten ask levels with a marketable buy.
+fn bench_match(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match");
+    for depth in DEPTHS {
+        let order = Order::buy(OrderId::MAX, 100, MID + 10);
+        group.bench_with_input(
+            BenchmarkId::new("price_level", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<OrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.add(black_box(order), 0, None),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vec", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<VecOrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| book.add(black_box(order)),
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Replaces a resting quote with a cancel followed by a new order, as market makers do.
+fn bench_cancel_replace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel_replace");
+    for depth in DEPTHS {
+        let id = OrderId::try_from(depth).unwrap() / 2;
+        let order = Order::buy(OrderId::MAX, 10, MID - 1);
+        group.bench_with_input(
+            BenchmarkId::new("price_level", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<OrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| {
+                        book.remove(black_box(id));
+                        book.add(black_box(order), 0, None)
+                    },
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vec", depth),
+            &depth,
+            |b, &depth| {
+                let book = build_book::<VecOrderBook>(depth);
+                b.iter_batched_ref(
+                    || book.clone(),
+                    |book| {
+                        book.remove(black_box(id));
+                        book.add(black_box(order))
+                    },
+                    BatchSize::SmallInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_add_passive,
+    bench_cancel,
+    bench_match,
+    bench_cancel_replace
+);
+criterion_main!(benches);
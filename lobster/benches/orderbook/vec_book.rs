@@ -0,0 +1,141 @@
+//! The original `Vec`-backed order book, kept as a baseline for the benchmarks.
+#![allow(clippy::arithmetic_side_effects)]
+
+use std::cmp::Reverse;
+
+use lobster::{Fill, Order, OrderId, Price, Quantity, Side};
+
+#[derive(Default, Debug, Clone)]
+pub struct VecOrderBook {
+    /// Bids, sorted by price ascending
+    bids: Vec<Order>,
+    /// Asks, sorted by price descending
+    asks: Vec<Order>,
+}
+
+impl VecOrderBook {
+    /// Returns an iterator over the bids from best to worst.
+    pub fn bids(&self) -> impl Iterator<Item = Order> + '_ {
+        self.bids.iter().rev().copied()
+    }
+
+    /// Returns an iterator over the asks from best to worst.
+    pub fn asks(&self) -> impl Iterator<Item = Order> + '_ {
+        self.asks.iter().rev().copied()
+    }
+
+    /// Returns the best bid.
+    #[must_use]
+    pub fn best_bid(&self) -> Option<Order> {
+        self.bids().next()
+    }
+
+    /// Returns the best ask.
+    #[must_use]
+    pub fn best_ask(&self) -> Option<Order> {
+        self.asks().next()
+    }
+
+    /// Adds an order to the order book. Returns a list of fills if the order was marketable.
+    pub fn add(&mut self, order: Order) -> Vec<Fill> {
+        match order.side {
+            Side::Buy => self.buy(order.id, order.quantity, order.price),
+            Side::Sell => self.sell(order.id, order.quantity, order.price),
+        }
+    }
+
+    /// Removes an order by id.
+    pub fn remove(&mut self, id: OrderId) -> Option<Order> {
+        if let Some(i) = self.bids.iter().position(|order| order.id == id) {
+            return Some(self.bids.remove(i));
+        }
+        if let Some(i) = self.asks.iter().position(|order| order.id == id) {
+            return Some(self.asks.remove(i));
+        }
+        None
+    }
+
+    fn buy(
+        &mut self,
+        id: OrderId,
+        mut quantity: Quantity,
+        price: Price,
+    ) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        let mut i = 0;
+        for order in self
+            .asks
+            .iter_mut()
+            .rev()
+            .take_while(|order| order.price <= price)
+        {
+            if quantity >= order.quantity {
+                fills.push(Fill::new(
+                    order.id,
+                    0,
+                    order.quantity,
+                    order.price,
+                    true,
+                ));
+                quantity -= order.quantity;
+                i += 1;
+            } else {
+                fills.push(Fill::new(order.id, 0, quantity, order.price, false));
+                order.quantity -= quantity;
+                quantity = 0;
+                break;
+            }
+        }
+        self.asks.drain(self.asks.len() - i..);
+        if quantity > 0 {
+            self.bids.insert(
+                0,
+                Order::new(id, quantity, price, Side::Buy),
+            );
+            self.bids.sort_by_key(|order| order.price);
+        }
+        fills
+    }
+
+    fn sell(
+        &mut self,
+        id: OrderId,
+        mut quantity: Quantity,
+        price: Price,
+    ) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        let mut i = 0;
+        for order in self
+            .bids
+            .iter_mut()
+            .rev()
+            .take_while(|order| order.price >= price)
+        {
+            if quantity >= order.quantity {
+                fills.push(Fill::new(
+                    order.id,
+                    0,
+                    order.quantity,
+                    order.price,
+                    true,
+                ));
+                quantity -= order.quantity;
+                i += 1;
+            } else {
+                fills.push(Fill::new(order.id, 0, quantity, order.price, false));
+                order.quantity -= quantity;
+                quantity = 0;
+                break;
+            }
+        }
+        self.bids.drain(self.bids.len() - i..);
+        if quantity > 0 {
+            self.asks.insert(
+                0,
+                Order::new(id, quantity, price, Side::Sell),
+            );
+            self.asks.sort_by_key(|order| Reverse(order.price));
+        }
+        fills
+    }
+}
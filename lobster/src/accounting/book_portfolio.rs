@@ -8,6 +8,9 @@ pub struct BookPortfolio {
     /// Should be >= 0
     pub last_exposure: Balance,
     pub position: Position,
+    /// The net balance paid for trades in the book, refunded if it is voided.
+    /// Negative if the user took more out of the book than they put in.
+    pub cost: Balance,
     bid_value: Balance,
     ask_value: Balance,
     bid_quantity: Quantity,
@@ -15,9 +18,10 @@ pub struct BookPortfolio {
 }
 
 impl BookPortfolio {
-    pub fn with_position(position: Position) -> Self {
+    pub fn with_position(position: Position, cost: Balance) -> Self {
         Self {
             position,
+            cost,
             ..Default::default()
         }
     }
@@ -47,6 +51,26 @@ impl BookPortfolio {
         }
     }
 
+    /// Returns the position if every resting order on `side` were filled.
+    pub fn worst_case_position(&self, side: Side) -> i64 {
+        match side {
+            Side::Buy => i64::from(self.position) + i64::from(self.bid_quantity),
+            Side::Sell => i64::from(self.position) - i64::from(self.ask_quantity),
+        }
+    }
+
+    /// Returns the number of long contracts not needed to cover resting asks.
+    pub fn free_long(&self) -> Quantity {
+        let free = i64::from(self.position) - i64::from(self.ask_quantity);
+        Quantity::try_from(free).unwrap_or_default()
+    }
+
+    /// Returns the number of short contracts not needed to cover resting bids.
+    pub fn free_short(&self) -> Quantity {
+        let free = -i64::from(self.position) - i64::from(self.bid_quantity);
+        Quantity::try_from(free).unwrap_or_default()
+    }
+
     fn compute_exposure(&mut self) -> Balance {
         let created = contracts_created(self.position, self.ask_quantity);
         let ask_exposure =
@@ -5,6 +5,6 @@ mod math;
 mod portfolio_manager;
 mod user_portfolio;
 
-pub use math::RESOLVE_PRICE;
+pub use math::{contracts_combined, contracts_created, RESOLVE_PRICE};
 
 pub use portfolio_manager::PortfolioManager;
@@ -6,14 +6,18 @@ use std::collections::HashMap;
 #[derive(Debug, Default)]
 pub struct PortfolioManager {
     users: HashMap<UserId, UserPortfolio>,
+    /// The unresolved markets of each mutually exclusive event, keyed by every
+    /// market in the event.
+    exclusive: HashMap<MarketId, Vec<MarketId>>,
 }
 
 impl PortfolioManager {
-    /// Constructs a new balance tracker from an initial state.
+    /// Constructs a new balance tracker from an initial state. Positions are
+    /// given with their cost basis.
     #[must_use]
     pub fn new(
         balances: &HashMap<UserId, Balance>,
-        positions: &HashMap<(UserId, MarketId), Position>,
+        positions: &HashMap<(UserId, MarketId), (Position, Balance)>,
     ) -> Self {
         let mut users: HashMap<UserId, UserPortfolio> = HashMap::new();
 
@@ -21,14 +25,74 @@ impl PortfolioManager {
             let user = users.entry(user_id).or_default();
             user.add_balance(balance);
 
-            for (&(user_id2, book), &position) in positions {
+            for (&(user_id2, book), &(position, cost)) in positions {
                 if user_id == user_id2 {
                     user.perbook
-                        .insert(book, BookPortfolio::with_position(position));
+                        .insert(book, BookPortfolio::with_position(position, cost));
                 }
             }
         }
-        Self { users }
+        Self {
+            users,
+            exclusive: HashMap::new(),
+        }
+    }
+
+    /// Groups markets into a mutually exclusive event, where exactly one market
+    /// will resolve to `RESOLVE_PRICE`. A complete set of contracts across the
+    /// markets is then worth `RESOLVE_PRICE`.
+    pub fn add_exclusive(&mut self, markets: &[MarketId]) {
+        for &market in markets {
+            self.exclusive.insert(market, markets.to_vec());
+        }
+    }
+
+    /// Returns the unresolved markets of the mutually exclusive event the
+    /// market is part of, or `None` if the market is independent.
+    #[must_use]
+    pub fn exclusive_markets(&self, market: MarketId) -> Option<&[MarketId]> {
+        self.exclusive.get(&market).map(Vec::as_slice)
+    }
+
+    /// Converts complete sets of a user's contracts in a mutually exclusive
+    /// event into balance. A set of longs is worth `RESOLVE_PRICE`, and a set
+    /// of shorts is worth `RESOLVE_PRICE` for every market but the winner.
+    ///
+    /// Contracts covering resting orders are left alone, so exposure is unchanged.
+    /// Call this once an operation has settled, after any orders are re-added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the user does not exist.
+    pub fn merge_complete_sets(&mut self, user: UserId, market: MarketId) {
+        let Some(markets) = self.exclusive.get(&market) else {
+            return;
+        };
+        let user = self.users.get_mut(&user).expect("Invariant");
+        let free = |f: fn(&BookPortfolio) -> Quantity| {
+            markets
+                .iter()
+                .map(|market| user.perbook.get(market).map_or(0, f))
+                .min()
+                .unwrap_or_default()
+        };
+        let long = free(BookPortfolio::free_long);
+        let short = free(BookPortfolio::free_short);
+        if long == 0 && short == 0 {
+            return;
+        }
+
+        let long_position = Position::try_from(long).expect("Invariant");
+        let short_position = Position::try_from(short).expect("Invariant");
+        for market in markets {
+            let perbook = user.perbook.get_mut(market).expect("Invariant");
+            perbook.position += short_position - long_position;
+            user.available -= perbook.compute_change();
+        }
+
+        let losers = Balance::try_from(markets.len() - 1).expect("Invariant");
+        let value = Balance::from(long) + Balance::from(short) * losers;
+        user.add_balance(value * Balance::from(RESOLVE_PRICE));
     }
 
     /// Deposits an amount into a user's account. Creates the user if they don't exist.
@@ -37,6 +101,16 @@ impl PortfolioManager {
         user.add_balance(amount);
     }
 
+    /// Withdraws an amount from a user's account.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the user does not exist or the amount exceeds their available.
+    pub fn withdraw(&mut self, user: UserId, amount: Balance) {
+        let user = self.users.get_mut(&user).expect("Invariant");
+        user.add_balance(-amount);
+    }
+
     /// Returns `true` if placing an order with these arguments would not exceed
     /// the user's available.
     #[must_use]
@@ -108,12 +182,14 @@ impl PortfolioManager {
         let perbook = taker.perbook.entry(book).or_default();
         let cost = trade_cost(perbook.position, quantity, price, side);
         perbook.position += signed_quantity;
+        perbook.cost += cost;
         taker.add_balance(-cost);
 
         let maker = self.users.get_mut(&maker).expect("Invariant");
         let perbook = maker.perbook.entry(book).or_default();
         let cost = trade_cost(perbook.position, quantity, price, !side);
         perbook.position -= signed_quantity;
+        perbook.cost += cost;
         perbook.remove_exposure(quantity, price, !side);
 
         maker.available -= perbook.compute_change();
@@ -121,8 +197,16 @@ impl PortfolioManager {
     }
 
     /// Resolves a book to a specific price. Zeroes out the position and adds winnings
-    /// to users balance.
-    pub fn resolve(&mut self, book: MarketId, price: Price) -> Vec<UserId> {
+    /// to users balance. Returns the users with a position, and their winnings.
+    pub fn resolve(&mut self, book: MarketId, price: Price) -> Vec<(UserId, Balance)> {
+        if let Some(markets) = self.exclusive.remove(&book) {
+            for market in markets.iter().filter(|&&market| market != book) {
+                if let Some(siblings) = self.exclusive.get_mut(market) {
+                    siblings.retain(|&market| market != book);
+                }
+            }
+        }
+
         let mut winners = Vec::new();
         for (&user_id, user) in self.users.iter_mut() {
             let Some(book) = user.perbook.remove(&book) else {
@@ -139,17 +223,73 @@ impl PortfolioManager {
                 Balance::from(RESOLVE_PRICE - price) * -Balance::from(book.position)
             };
             user.add_balance(position_value);
-            winners.push(user_id);
+            winners.push((user_id, position_value));
         }
         winners
     }
 
+    /// Returns whether every user who took more out of the book than they put
+    /// in has the balance available to pay it back if the book is voided.
+    #[must_use]
+    pub fn can_void(&self, book: MarketId) -> bool {
+        self.users.values().all(|user| {
+            user.perbook.get(&book).is_none_or(|perbook| {
+                user.available + perbook.last_exposure + perbook.cost >= 0
+            })
+        })
+    }
+
+    /// Voids a book. Zeroes out the position and refunds each user's cost basis.
+    /// Users who took more out of the book than they put in pay it back.
+    /// Returns the users who had a position or orders in the book, and their refunds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a user can't pay back what they took out. Check with `can_void` first.
+    pub fn void(&mut self, book: MarketId) -> Vec<(UserId, Balance)> {
+        let mut users = Vec::new();
+        for (&user_id, user) in &mut self.users {
+            let Some(book) = user.perbook.remove(&book) else {
+                continue;
+            };
+            user.available += book.last_exposure;
+            let refund = book.cost;
+            user.add_balance(refund);
+            users.push((user_id, refund));
+        }
+        users
+    }
+
     #[allow(dead_code)]
     #[must_use]
     pub fn get_balance(&self, user: UserId) -> Balance {
         self.users.get(&user).map(|x| x.balance).unwrap_or_default()
     }
 
+    /// Returns the balance of every user.
+    pub fn balances(&self) -> impl Iterator<Item = (UserId, Balance)> + '_ {
+        self.users.iter().map(|(&user, portfolio)| (user, portfolio.balance))
+    }
+
+    /// Returns every position that is non-zero or has a cost basis, with its cost basis.
+    pub fn positions(&self) -> impl Iterator<Item = (UserId, MarketId, Position, Balance)> + '_ {
+        self.users.iter().flat_map(|(&user, portfolio)| {
+            portfolio
+                .perbook
+                .iter()
+                .filter(|(_, book)| book.position != 0 || book.cost != 0)
+                .map(move |(&market, book)| (user, market, book.position, book.cost))
+        })
+    }
+
+    /// Returns the unresolved markets of every mutually exclusive event.
+    pub fn exclusive_events(&self) -> impl Iterator<Item = &[MarketId]> + '_ {
+        self.exclusive
+            .iter()
+            .filter(|(market, markets)| markets.first() == Some(market))
+            .map(|(_, markets)| markets.as_slice())
+    }
+
     #[allow(dead_code)]
     #[must_use]
     pub fn get_available(&self, user: UserId) -> Balance {
@@ -159,6 +299,26 @@ impl PortfolioManager {
             .unwrap_or_default()
     }
 
+    /// Returns the user's position in the book if every resting order on `side` were filled.
+    #[must_use]
+    pub fn worst_case_position(&self, user: UserId, book: MarketId, side: Side) -> i64 {
+        self.users
+            .get(&user)
+            .and_then(|x| x.perbook.get(&book))
+            .map(|x| x.worst_case_position(side))
+            .unwrap_or_default()
+    }
+
+    /// Returns the net balance the user paid for trades in the book.
+    #[must_use]
+    pub fn get_cost(&self, user: UserId, book: MarketId) -> Balance {
+        self.users
+            .get(&user)
+            .and_then(|x| x.perbook.get(&book))
+            .map(|x| x.cost)
+            .unwrap_or_default()
+    }
+
     #[allow(dead_code)]
     #[must_use]
     pub fn get_position(&self, user: UserId, book: MarketId) -> Position {
@@ -259,7 +419,7 @@ mod tests {
     #[test]
     fn test_from_wei() {
         let balances = HashMap::from([(MAKER, 100000)]);
-        let positions = HashMap::from([((MAKER, BOOK), 0)]);
+        let positions = HashMap::from([((MAKER, BOOK), (0, 0))]);
         let mut manager = PortfolioManager::new(&balances, &positions);
 
         manager.add_resting_order(MAKER, BOOK, Order::sell(0, 2, 100));
@@ -298,4 +458,93 @@ mod tests {
         assert_eq!(manager.get_available(TAKER), 106000);
         assert_eq!(manager.get_position(TAKER, BOOK), 0);
     }
+
+    #[test]
+    fn test_void() {
+        let mut manager = PortfolioManager::default();
+        manager.deposit(TAKER, 100000);
+        manager.deposit(MAKER, 100000);
+
+        manager.add_resting_order(MAKER, BOOK, Order::sell(0, 5, ASK_PRICE));
+        manager.on_trade(TAKER, MAKER, BOOK, 2, ASK_PRICE, Side::Buy);
+        manager.add_resting_order(TAKER, BOOK, Order::sell(1, 1, BID_PRICE));
+        manager.on_trade(MAKER, TAKER, BOOK, 1, BID_PRICE, Side::Buy);
+        assert_eq!(manager.get_balance(TAKER), 92000);
+
+        let mut users = manager.void(BOOK);
+        users.sort_unstable();
+        assert_eq!(users, vec![(MAKER, 2000), (TAKER, 8000)]);
+        assert_eq!(manager.get_balance(MAKER), 100000);
+        assert_eq!(manager.get_available(MAKER), 100000);
+        assert_eq!(manager.get_position(MAKER, BOOK), 0);
+        assert_eq!(manager.get_balance(TAKER), 100000);
+        assert_eq!(manager.get_available(TAKER), 100000);
+        assert_eq!(manager.get_position(TAKER, BOOK), 0);
+    }
+
+    #[test]
+    fn test_void_needs_proceeds_available() {
+        let mut manager = PortfolioManager::default();
+        manager.deposit(TAKER, 100000);
+        manager.deposit(MAKER, 100000);
+
+        // the maker sells 2 at 7000 and buys them back at 6000
+        manager.add_resting_order(MAKER, BOOK, Order::sell(0, 2, ASK_PRICE));
+        manager.on_trade(TAKER, MAKER, BOOK, 2, ASK_PRICE, Side::Buy);
+        manager.add_resting_order(MAKER, BOOK, Order::buy(1, 2, BID_PRICE));
+        manager.on_trade(TAKER, MAKER, BOOK, 2, BID_PRICE, Side::Sell);
+        assert_eq!(manager.get_balance(MAKER), 102000);
+        manager.add_resting_order(MAKER, 2, Order::buy(2, 20, 5050));
+        assert_eq!(manager.get_available(MAKER), 1000);
+        assert!(!manager.can_void(BOOK));
+
+        manager.deposit(MAKER, 1000);
+        assert!(manager.can_void(BOOK));
+        manager.void(BOOK);
+        assert_eq!(manager.get_balance(MAKER), 101000);
+        assert_eq!(manager.get_available(MAKER), 0);
+        assert_eq!(manager.get_balance(TAKER), 100000);
+        assert_eq!(manager.get_available(TAKER), 100000);
+    }
+
+    #[test]
+    fn test_exclusive_complete_sets_are_merged() {
+        const OTHER: MarketId = 2;
+        let mut manager = PortfolioManager::default();
+        manager.deposit(TAKER, 100000);
+        manager.deposit(MAKER, 100000);
+        manager.add_exclusive(&[BOOK, OTHER]);
+
+        manager.add_resting_order(MAKER, BOOK, Order::sell(0, 2, 6000));
+        manager.on_trade(TAKER, MAKER, BOOK, 2, 6000, Side::Buy);
+        manager.merge_complete_sets(TAKER, BOOK);
+        manager.merge_complete_sets(MAKER, BOOK);
+        assert_eq!(manager.get_balance(TAKER), 88000);
+        assert_eq!(manager.get_balance(MAKER), 92000);
+
+        // the long in BOOK covering this ask is not merged
+        manager.add_resting_order(TAKER, BOOK, Order::sell(1, 1, 9000));
+
+        manager.add_resting_order(MAKER, OTHER, Order::sell(2, 2, 3000));
+        manager.on_trade(TAKER, MAKER, OTHER, 2, 3000, Side::Buy);
+        manager.merge_complete_sets(TAKER, OTHER);
+        manager.merge_complete_sets(MAKER, OTHER);
+        assert_eq!(manager.get_position(TAKER, BOOK), 1);
+        assert_eq!(manager.get_position(TAKER, OTHER), 1);
+        assert_eq!(manager.get_balance(TAKER), 92000);
+        assert_eq!(manager.get_available(TAKER), 92000);
+
+        // shorts in every market are worth RESOLVE_PRICE for all but the winner
+        assert_eq!(manager.get_position(MAKER, BOOK), 0);
+        assert_eq!(manager.get_position(MAKER, OTHER), 0);
+        assert_eq!(manager.get_balance(MAKER), 98000);
+        assert_eq!(manager.get_available(MAKER), 98000);
+
+        manager.remove_order(TAKER, BOOK, Order::sell(1, 1, 9000));
+        manager.merge_complete_sets(TAKER, BOOK);
+        assert_eq!(manager.get_position(TAKER, BOOK), 0);
+        assert_eq!(manager.get_position(TAKER, OTHER), 0);
+        assert_eq!(manager.get_balance(TAKER), 102000);
+        assert_eq!(manager.get_available(TAKER), 102000);
+    }
 }
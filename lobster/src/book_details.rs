@@ -1,14 +1,65 @@
 use crate::{
-    Fill, Order, OrderBook, OrderId, Price, Side, Tick,
+    Execution, MarketState, Order, OrderBook, OrderId, Price, Quantity, SelfTradePrevention,
+    Side, Tick, UserId,
 };
 
 #[derive(Debug, Default)]
 pub struct BookDetails {
     next_tick: Tick,
+    /// The price of the last trade.
+    last_price: Option<Price>,
+    state: MarketState,
     inner: OrderBook,
 }
 
 impl BookDetails {
+    pub fn with_next_tick(next_tick: Tick, last_price: Option<Price>) -> Self {
+        Self {
+            next_tick,
+            last_price,
+            state: MarketState::Open,
+            inner: OrderBook::default(),
+        }
+    }
+
+    pub const fn next_tick(&self) -> Tick {
+        self.next_tick
+    }
+
+    pub const fn last_price(&self) -> Option<Price> {
+        self.last_price
+    }
+
+    pub const fn set_last_price(&mut self, price: Price) {
+        self.last_price = Some(price);
+    }
+
+    pub const fn state(&self) -> MarketState {
+        self.state
+    }
+
+    pub const fn set_state(&mut self, state: MarketState) {
+        self.state = state;
+    }
+
+    /// Returns the price orders are kept near by the price band:
+    /// the last trade price, or the mid price if the book hasn't traded.
+    pub fn reference_price(&self) -> Option<Price> {
+        self.last_price.or_else(|| {
+            let bid = self.inner.best_bid()?.price;
+            let ask = self.inner.best_ask()?.price;
+            Some(bid.midpoint(ask))
+        })
+    }
+
+    pub fn queued(&self) -> Vec<(UserId, Order, Option<SelfTradePrevention>)> {
+        self.inner.queued()
+    }
+
+    pub fn reduce(&mut self, id: OrderId, quantity: Quantity) -> Option<Order> {
+        self.inner.reduce(id, quantity)
+    }
+
     pub fn get_next_tick(&mut self) -> Tick {
         let tick = self.next_tick;
         self.next_tick = self.next_tick.wrapping_add(1);
@@ -23,11 +74,33 @@ impl BookDetails {
         }
     }
 
-    pub fn add(&mut self, order: Order) -> Vec<Fill> {
-        self.inner.add(order)
+    pub fn fillable(
+        &self,
+        order: Order,
+        owner: UserId,
+        stp: Option<SelfTradePrevention>,
+    ) -> Quantity {
+        self.inner.fillable(order, owner, stp)
+    }
+
+    pub fn add(
+        &mut self,
+        order: Order,
+        owner: UserId,
+        stp: Option<SelfTradePrevention>,
+    ) -> Execution {
+        self.inner.add(order, owner, stp)
     }
 
     pub fn remove(&mut self, id: OrderId) -> Option<Order> {
         self.inner.remove(id)
     }
+
+    pub fn get(&self, id: OrderId) -> Option<Order> {
+        self.inner.get(id)
+    }
+
+    pub fn amend(&mut self, order: Order) -> Option<Execution> {
+        self.inner.amend(order)
+    }
 }
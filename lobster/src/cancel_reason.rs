@@ -0,0 +1,12 @@
+/// Why a resting order was removed from the book.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CancelReason {
+    /// The user cancelled it.
+    Requested,
+    /// Its market was closed or halted with orders cancelled.
+    MarketClosed,
+    /// A GTT order reached its `expires_at`.
+    Expired,
+    /// The user's trading session disconnected or stopped sending heartbeats.
+    Disconnected,
+}
@@ -12,22 +12,33 @@
 )]
 mod accounting;
 mod book_details;
+mod cancel_reason;
+mod market_state;
 mod market_update;
 mod order_request;
 mod orderbook;
 mod reject_reason;
+mod risk_limits;
+mod snapshot;
 
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap};
 
 use book_details::BookDetails;
+pub use cancel_reason::CancelReason;
+pub use market_state::MarketState;
 pub use market_update::MarketUpdate;
 
 pub use order_request::{OrderRequest, TimeInForce};
 pub use reject_reason::RejectReason;
+pub use risk_limits::RiskLimits;
+pub use snapshot::{BookSnapshot, Snapshot, SnapshotError, SNAPSHOT_VERSION};
 
-pub use orderbook::{Fill, Order, OrderBook, OrderId, Price, Quantity, Side};
+pub use orderbook::{
+    ClientOrderId, Execution, Fill, Order, OrderBook, OrderId, Price, Quantity,
+    SelfTradePrevention, Side,
+};
 
-pub use accounting::{PortfolioManager, RESOLVE_PRICE};
+pub use accounting::{contracts_combined, contracts_created, PortfolioManager, RESOLVE_PRICE};
 
 /// Balance in basis points.
 pub type Balance = i64;
@@ -53,6 +64,108 @@ pub type MatcherResult = Result<MarketUpdate, RejectReason>;
 struct OrderOwner {
     user_id: UserId,
     market_id: MarketId,
+    expires_at: Option<Timestamp>,
+    client_order_id: Option<ClientOrderId>,
+}
+
+/// The owner of every resting order, how many resting orders each user has,
+/// when the GTT orders among them expire, and the client order ids they have.
+#[derive(Debug, Default)]
+struct OrderOwners {
+    owners: HashMap<OrderId, OrderOwner>,
+    open_orders: HashMap<UserId, usize>,
+    expiries: BTreeSet<(Timestamp, OrderId)>,
+    client_order_ids: HashMap<(UserId, ClientOrderId), OrderId>,
+}
+
+impl OrderOwners {
+    fn get(&self, id: OrderId) -> Option<&OrderOwner> {
+        self.owners.get(&id)
+    }
+
+    fn insert(&mut self, order: Order, user_id: UserId, market_id: MarketId) {
+        let owner = OrderOwner {
+            user_id,
+            market_id,
+            expires_at: order.expires_at,
+            client_order_id: order.client_order_id,
+        };
+        if self.owners.insert(order.id, owner).is_none() {
+            let count = self.open_orders.entry(user_id).or_default();
+            *count = count.saturating_add(1);
+        }
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.insert((expires_at, order.id));
+        }
+        if let Some(client_order_id) = order.client_order_id {
+            self.client_order_ids
+                .insert((user_id, client_order_id), order.id);
+        }
+    }
+
+    fn remove(&mut self, id: OrderId) -> Option<OrderOwner> {
+        let owner = self.owners.remove(&id)?;
+        if let Some(expires_at) = owner.expires_at {
+            self.expiries.remove(&(expires_at, id));
+        }
+        if let Some(client_order_id) = owner.client_order_id {
+            self.client_order_ids
+                .remove(&(owner.user_id, client_order_id));
+        }
+        if let Entry::Occupied(mut entry) = self.open_orders.entry(owner.user_id) {
+            *entry.get_mut() = entry.get().saturating_sub(1);
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+        Some(owner)
+    }
+
+    /// Removes every order in the market.
+    fn remove_market(&mut self, market_id: MarketId) {
+        let ids: Vec<_> = self
+            .owners
+            .iter()
+            .filter(|(_, owner)| owner.market_id == market_id)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    /// Returns the id of the user's resting order with the client order id.
+    fn by_client_order_id(&self, user_id: UserId, client_order_id: ClientOrderId) -> Option<OrderId> {
+        self.client_order_ids
+            .get(&(user_id, client_order_id))
+            .copied()
+    }
+
+    /// Returns the ids of a user's orders, oldest first.
+    fn user_orders(&self, user_id: UserId) -> Vec<OrderId> {
+        let mut ids: Vec<_> = self
+            .owners
+            .iter()
+            .filter(|(_, owner)| owner.user_id == user_id)
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns the orders that expire by `timestamp`, with their owners, soonest first.
+    fn expired(&self, timestamp: Timestamp) -> Vec<(OrderId, UserId)> {
+        self.expiries
+            .iter()
+            .take_while(|&&(expires_at, _)| expires_at <= timestamp)
+            .map(|&(_, id)| (id, self.owners[&id].user_id))
+            .collect()
+    }
+
+    /// Returns the number of resting orders the user has.
+    fn open_orders(&self, user_id: UserId) -> usize {
+        self.open_orders.get(&user_id).copied().unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -60,9 +173,13 @@ pub struct Exchange {
     /// Tracks user balances and positions.
     manager: PortfolioManager,
     orderbooks: HashMap<MarketId, BookDetails>,
-    order_owner: HashMap<OrderId, OrderOwner>,
+    order_owner: OrderOwners,
     /// The order id to assign to the next accepted `Order`.
     next_order_id: OrderId,
+    /// The risk limits of markets and users without their own.
+    default_limits: RiskLimits,
+    market_limits: HashMap<MarketId, RiskLimits>,
+    user_limits: HashMap<UserId, RiskLimits>,
 }
 
 impl Exchange {
@@ -89,7 +206,68 @@ impl Exchange {
         })
     }
 
+    /// Withdraws an amount from a user's account.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::InvalidAmount)` if the amount isn't positive.
+    /// - Returns `Err(RejectReason::InsufficientFunds)` if the amount exceeds the user's available.
+    pub fn withdraw(
+        &mut self,
+        timestamp: Timestamp,
+        user: UserId,
+        amount: Balance,
+    ) -> MatcherResult {
+        if amount <= 0 {
+            return Err(RejectReason::InvalidAmount);
+        }
+        if self.manager.get_available(user) < amount {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        self.manager.withdraw(user, amount);
+
+        Ok(MarketUpdate::Withdrawal {
+            timestamp,
+            user,
+            amount,
+        })
+    }
+
+    /// Adds a signed amount to a user's account, recording why.
+    /// If the user is not present, they are added.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::InvalidAmount)` if the amount is 0.
+    /// - Returns `Err(RejectReason::InsufficientFunds)` if a reduction exceeds the user's available.
+    pub fn adjust_balance(
+        &mut self,
+        timestamp: Timestamp,
+        user: UserId,
+        amount: Balance,
+        memo: String,
+    ) -> MatcherResult {
+        if amount == 0 {
+            return Err(RejectReason::InvalidAmount);
+        }
+        if amount < 0 {
+            let withdrawal = amount.checked_neg().ok_or(RejectReason::InsufficientFunds)?;
+            self.withdraw(timestamp, user, withdrawal)?;
+        } else {
+            self.manager.deposit(user, amount);
+        }
+
+        Ok(MarketUpdate::Adjustment {
+            timestamp,
+            user,
+            amount,
+            memo,
+        })
+    }
+
     /// Constructs an exchange from an initial state.
+    /// Positions are given with their cost basis, and markets with the price
+    /// they last traded at and their state.
     /// Orders must be sorted by order id.
     ///
     /// # Panics
@@ -99,25 +277,30 @@ impl Exchange {
     pub fn from_state(
         next_order_id: OrderId,
         balances: &HashMap<UserId, Balance>,
-        positions: &HashMap<(UserId, MarketId), Position>,
+        positions: &HashMap<(UserId, MarketId), (Position, Balance)>,
         orders: &[(UserId, MarketId, Order)],
-        events: &[MarketId],
+        events: &[(MarketId, Option<Price>, MarketState)],
     ) -> Self {
         let mut tracker = PortfolioManager::new(balances, positions);
 
         let mut orderbooks: HashMap<MarketId, BookDetails> = events
             .iter()
-            .map(|&event_id| (event_id, BookDetails::default()))
+            .map(|&(event_id, last_price, state)| {
+                let mut book = BookDetails::with_next_tick(0, last_price);
+                book.set_state(state);
+                (event_id, book)
+            })
             .collect();
 
-        let mut order_owner = HashMap::new();
+        let mut order_owner = OrderOwners::default();
         for &(user_id, event_id, order) in orders {
             tracker.add_resting_order(user_id, event_id, order);
-            order_owner.insert(order.id, OrderOwner { user_id, market_id: event_id });
+            order_owner.insert(order, user_id, event_id);
             assert!(orderbooks
                 .get_mut(&event_id)
                 .expect("Expected book to exist")
-                .add(order)
+                .add(order, user_id, None)
+                .fills
                 .is_empty());
         }
 
@@ -126,9 +309,228 @@ impl Exchange {
             orderbooks,
             order_owner,
             next_order_id,
+            ..Self::default()
         }
     }
 
+    /// Captures the state of the exchange after `seq` updates.
+    #[must_use]
+    pub fn snapshot(&self, seq: u64) -> Snapshot {
+        let mut balances: Vec<_> = self.manager.balances().collect();
+        balances.sort_unstable();
+        let mut positions: Vec<_> = self.manager.positions().collect();
+        positions.sort_unstable();
+        let mut books: Vec<_> = self
+            .orderbooks
+            .iter()
+            .map(|(&market, book)| BookSnapshot {
+                market,
+                next_tick: book.next_tick(),
+                last_price: book.last_price(),
+                state: book.state(),
+                orders: book.queued(),
+            })
+            .collect();
+        books.sort_unstable_by_key(|book| book.market);
+        let mut exclusive_events: Vec<_> = self
+            .manager
+            .exclusive_events()
+            .map(<[MarketId]>::to_vec)
+            .collect();
+        exclusive_events.sort_unstable();
+
+        Snapshot {
+            seq,
+            next_order_id: self.next_order_id,
+            balances,
+            positions,
+            books,
+            exclusive_events,
+        }
+    }
+
+    /// Restores an exchange from a snapshot.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if there's a marketable order, or balance exceeds exposure
+    #[must_use]
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let balances = snapshot.balances.iter().copied().collect();
+        let positions = snapshot
+            .positions
+            .iter()
+            .map(|&(user, market, position, cost)| ((user, market), (position, cost)))
+            .collect();
+        let mut manager = PortfolioManager::new(&balances, &positions);
+
+        let mut orderbooks = HashMap::new();
+        let mut order_owner = OrderOwners::default();
+        for snapshot in &snapshot.books {
+            let mut book = BookDetails::with_next_tick(snapshot.next_tick, snapshot.last_price);
+            book.set_state(snapshot.state);
+            for &(user_id, order, stp) in &snapshot.orders {
+                manager.add_resting_order(user_id, snapshot.market, order);
+                order_owner.insert(order, user_id, snapshot.market);
+                assert!(book.add(order, user_id, stp).fills.is_empty());
+            }
+            orderbooks.insert(snapshot.market, book);
+        }
+        for markets in &snapshot.exclusive_events {
+            manager.add_exclusive(markets);
+        }
+
+        Self {
+            manager,
+            orderbooks,
+            order_owner,
+            next_order_id: snapshot.next_order_id,
+            ..Self::default()
+        }
+    }
+
+    /// Applies an update emitted by another exchange, without matching.
+    /// Replaying the updates of an exchange from the same starting state gives the same state.
+    ///
+    /// Self-trade prevention of resting orders is not in the feed, so orders added
+    /// here are requeued by amends without it.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::MarketNotFound)` or `Err(RejectReason::OrderNotFound)`
+    ///   if the update refers to something the exchange doesn't have.
+    /// - Returns `Err(RejectReason::InvalidQuantity)` if the fills add up to more than the order.
+    /// - Returns the reason the update would be rejected, for updates without orders.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the rest of an order would trade against the book.
+    pub fn apply(&mut self, update: &MarketUpdate) -> Result<(), RejectReason> {
+        match update {
+            MarketUpdate::AddOrder {
+                market,
+                user,
+                order,
+                fills,
+                cancelled,
+                ..
+            } => {
+                self.apply_execution(*user, *market, order.side, fills, cancelled)?;
+                let traded: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+                let quantity = order
+                    .quantity
+                    .checked_sub(traded)
+                    .ok_or(RejectReason::InvalidQuantity)?;
+                let remaining = Order { quantity, ..*order };
+                let book = self
+                    .orderbooks
+                    .get_mut(market)
+                    .ok_or(RejectReason::MarketNotFound)?;
+                if remaining.quantity > 0 {
+                    assert!(book.add(remaining, *user, None).fills.is_empty());
+                    self.manager.add_resting_order(*user, *market, remaining);
+                    self.order_owner.insert(remaining, *user, *market);
+                }
+                book.get_next_tick();
+                self.next_order_id = self.next_order_id.max(order.id.wrapping_add(1));
+                self.merge_complete_sets(*user, *market, fills);
+            }
+            MarketUpdate::RemoveOrder {
+                user, id, reason, ..
+            } => {
+                self.remove_order(0, *user, *id, *reason)?;
+            }
+            MarketUpdate::AmendOrder {
+                market,
+                user,
+                order,
+                fills,
+                cancelled,
+                ..
+            } => {
+                let book = self
+                    .orderbooks
+                    .get_mut(market)
+                    .ok_or(RejectReason::MarketNotFound)?;
+                let old = book.get(order.id).ok_or(RejectReason::OrderNotFound)?;
+                self.manager.remove_order(*user, *market, old);
+                self.apply_execution(*user, *market, order.side, fills, cancelled)?;
+                let traded: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+                let quantity = order
+                    .quantity
+                    .checked_sub(traded)
+                    .ok_or(RejectReason::InvalidQuantity)?;
+                let remaining = Order::new(order.id, quantity, order.price, order.side);
+                let book = self
+                    .orderbooks
+                    .get_mut(market)
+                    .ok_or(RejectReason::MarketNotFound)?;
+                let execution = book.amend(remaining).ok_or(RejectReason::OrderNotFound)?;
+                assert!(execution.fills.is_empty());
+                if remaining.quantity > 0 {
+                    self.manager.add_resting_order(*user, *market, remaining);
+                } else {
+                    self.order_owner.remove(order.id);
+                }
+                book.get_next_tick();
+                self.merge_complete_sets(*user, *market, fills);
+            }
+            MarketUpdate::ResolveMarket { market, price, .. } => {
+                self.resolve(0, *market, *price)?;
+            }
+            MarketUpdate::VoidMarket { market, .. } => {
+                self.void_market(0, *market)?;
+            }
+            MarketUpdate::SetMarketState { market, state, .. } => {
+                self.set_market_state(0, *market, *state)?;
+            }
+            MarketUpdate::AddMarket { market, .. } => {
+                self.add_event(0, *market)?;
+            }
+            MarketUpdate::AddExclusiveEvent { markets, .. } => {
+                self.add_exclusive_event(0, markets.clone())?;
+            }
+            MarketUpdate::Deposit { user, amount, .. } => {
+                self.deposit(0, *user, *amount)?;
+            }
+            MarketUpdate::Withdrawal { user, amount, .. } => {
+                self.withdraw(0, *user, *amount)?;
+            }
+            MarketUpdate::Adjustment {
+                user, amount, memo, ..
+            } => {
+                self.adjust_balance(0, *user, *amount, memo.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the self-trade cancellations and fills of an update off the book,
+    /// and settles them.
+    fn apply_execution(
+        &mut self,
+        user: UserId,
+        market: MarketId,
+        side: Side,
+        fills: &[Fill],
+        cancelled: &[Order],
+    ) -> Result<(), RejectReason> {
+        let book = self
+            .orderbooks
+            .get_mut(&market)
+            .ok_or(RejectReason::MarketNotFound)?;
+        let reduced = cancelled
+            .iter()
+            .map(|order| (order.id, order.quantity))
+            .chain(fills.iter().map(|fill| (fill.id, fill.quantity)));
+        for (id, quantity) in reduced {
+            book.reduce(id, quantity).ok_or(RejectReason::OrderNotFound)?;
+        }
+        self.release_cancelled(user, market, cancelled);
+        self.apply_fills(user, market, side, fills);
+        Ok(())
+    }
+
     /// Adds a book to the exchange.
     ///
     /// # Errors
@@ -149,14 +551,54 @@ impl Exchange {
         }
     }
 
+    /// Groups markets into a mutually exclusive event, where exactly one market
+    /// resolves to `RESOLVE_PRICE`. Complete sets of contracts across the markets
+    /// are merged into balance, freeing up collateral.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::MarketNotFound)` if a market does not exist.
+    /// - Returns `Err(RejectReason::InvalidExclusiveEvent)` if there are no markets,
+    ///   a market is repeated, or a market is already in a mutually exclusive event.
+    pub fn add_exclusive_event(
+        &mut self,
+        timestamp: Timestamp,
+        markets: Vec<MarketId>,
+    ) -> MatcherResult {
+        if !markets.iter().all(|market| self.orderbooks.contains_key(market)) {
+            return Err(RejectReason::MarketNotFound);
+        }
+        let mut distinct = markets.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.is_empty()
+            || distinct.len() != markets.len()
+            || markets
+                .iter()
+                .any(|&market| self.manager.exclusive_markets(market).is_some())
+        {
+            return Err(RejectReason::InvalidExclusiveEvent);
+        }
+
+        self.manager.add_exclusive(&markets);
+        Ok(MarketUpdate::AddExclusiveEvent { timestamp, markets })
+    }
+
     /// Resolves a book to the specified price. Cancels open orders and zeroes positions.
     ///
+    /// The markets of a mutually exclusive event are resolved one at a time: every
+    /// losing market to 0 first, in any order, and the winning market to
+    /// `RESOLVE_PRICE` last, once it is the only market left unresolved.
+    ///
     /// TODO: this function is inefficient
     ///
     /// # Errors
     ///
     /// - Returns `Err(RejectReason::BookNotFound)` if the book does not exist.
     /// - Returns `Err(RejectReason::InvalidPrice)` if the price is greater than `RESOLVE_PRICE`.
+    /// - Returns `Err(RejectReason::InvalidResolution)` if the book is in a mutually
+    ///   exclusive event, and is resolved to `RESOLVE_PRICE` before the other markets
+    ///   or to anything else as the last market.
     pub fn resolve(
         &mut self,
         timestamp: Timestamp,
@@ -166,14 +608,19 @@ impl Exchange {
         if price > RESOLVE_PRICE {
             return Err(RejectReason::InvalidPrice);
         }
+        if let Some(markets) = self.manager.exclusive_markets(market_id) {
+            let is_last = markets.len() == 1;
+            if is_last && price != RESOLVE_PRICE || !is_last && price != 0 {
+                return Err(RejectReason::InvalidResolution);
+            }
+        }
         let Some(mut book) = self.orderbooks.remove(&market_id) else {
             return Err(RejectReason::MarketNotFound);
         };
 
-        self.order_owner
-            .retain(|_, order| order.market_id != market_id);
+        self.order_owner.remove_market(market_id);
         self.manager.resolve(market_id, price);
-        
+
         let update = MarketUpdate::ResolveMarket {
             timestamp,
             tick: book.get_next_tick(),
@@ -184,6 +631,111 @@ impl Exchange {
         Ok(update)
     }
 
+    /// Cancels a market without resolving it. Its orders are removed, and every
+    /// user is refunded the balance they paid for their trades in it, less what
+    /// they received.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::MarketNotFound)` if the market does not exist.
+    /// - Returns `Err(RejectReason::InvalidResolution)` if the market is in a mutually
+    ///   exclusive event.
+    /// - Returns `Err(RejectReason::InsufficientFunds)` if a user took more out of the
+    ///   market than they put in, and doesn't have the balance available to pay it back.
+    pub fn void_market(&mut self, timestamp: Timestamp, market: MarketId) -> MatcherResult {
+        if self.manager.exclusive_markets(market).is_some() {
+            return Err(RejectReason::InvalidResolution);
+        }
+        if !self.manager.can_void(market) {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        let Some(mut book) = self.orderbooks.remove(&market) else {
+            return Err(RejectReason::MarketNotFound);
+        };
+
+        self.order_owner.remove_market(market);
+        self.manager.void(market);
+
+        Ok(MarketUpdate::VoidMarket {
+            timestamp,
+            tick: book.get_next_tick(),
+            market,
+        })
+    }
+
+    /// Opens, halts or closes a market. Orders can only be placed and amended in open markets.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::MarketNotFound)` if the market does not exist.
+    pub fn set_market_state(
+        &mut self,
+        timestamp: Timestamp,
+        market: MarketId,
+        state: MarketState,
+    ) -> MatcherResult {
+        let book = self
+            .orderbooks
+            .get_mut(&market)
+            .ok_or(RejectReason::MarketNotFound)?;
+        book.set_state(state);
+
+        Ok(MarketUpdate::SetMarketState {
+            timestamp,
+            tick: book.get_next_tick(),
+            market,
+            state,
+        })
+    }
+
+    /// Cancels every resting order in a market, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::MarketNotFound)` if the market does not exist.
+    pub fn cancel_market_orders(
+        &mut self,
+        timestamp: Timestamp,
+        market: MarketId,
+    ) -> Result<Vec<MarketUpdate>, RejectReason> {
+        let mut orders = self
+            .orderbooks
+            .get(&market)
+            .ok_or(RejectReason::MarketNotFound)?
+            .queued();
+        orders.sort_unstable_by_key(|&(_, order, _)| order.id);
+        orders
+            .into_iter()
+            .map(|(user, order, _)| {
+                self.remove_order(timestamp, user, order.id, CancelReason::MarketClosed)
+            })
+            .collect()
+    }
+
+    /// Cancels every GTT order that expires by `timestamp`, soonest first.
+    pub fn expire_orders(&mut self, timestamp: Timestamp) -> Vec<MarketUpdate> {
+        self.order_owner
+            .expired(timestamp)
+            .into_iter()
+            .filter_map(|(id, user)| {
+                self.remove_order(timestamp, user, id, CancelReason::Expired)
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Cancels every resting order of a user whose trading session was lost, oldest first.
+    pub fn cancel_user_orders(&mut self, timestamp: Timestamp, user: UserId) -> Vec<MarketUpdate> {
+        self.order_owner
+            .user_orders(user)
+            .into_iter()
+            .filter_map(|id| {
+                self.remove_order(timestamp, user, id, CancelReason::Disconnected)
+                    .ok()
+            })
+            .collect()
+    }
+
     /// Submits a new order to the exchange.
     ///
     /// Time: O(k) where k is number of orders matched.
@@ -193,6 +745,10 @@ impl Exchange {
     /// - Returns `Err(RejectReason::BookNotFound)` if the book does not exist.
     /// - Returns `Err(RejectReason::InvalidPrice)` if the price is 0 or greater than or equal to `RESOLVE_PRICE`.
     /// - Returns `Err(RejectReason::InvalidQuantity)` if the quantity is 0.
+    /// - Returns `Err(RejectReason::MarketHalted)` if the market is not open.
+    /// - Returns `Err(RejectReason::FOKNotFillable)` if a fill or kill order can't be filled in full.
+    /// - Returns `Err(RejectReason::InvalidExpiry)` if a GTT order doesn't expire after `timestamp`,
+    ///   or another order has an expiry.
     ///
     /// # Panics
     ///
@@ -203,12 +759,16 @@ impl Exchange {
         user_id: UserId,
         order_request: OrderRequest,
     ) -> MatcherResult {
-        self.check_order(user_id, order_request)?;
-        let mut order = self.build_new_order(
-            order_request.quantity,
-            order_request.price,
-            order_request.side,
-        );
+        self.check_order(timestamp, user_id, order_request)?;
+        let mut order = self
+            .build_new_order(
+                order_request.quantity,
+                order_request.price,
+                order_request.side,
+            )
+            .with_all_or_none(order_request.tif == TimeInForce::AON)
+            .with_expiry(order_request.expires_at)
+            .with_client_order_id(order_request.client_order_id);
 
         let event_id = order_request.market;
         let book = self
@@ -216,45 +776,39 @@ impl Exchange {
             .get_mut(&event_id)
             .ok_or(RejectReason::MarketNotFound)?; // infallible
 
-        for fill in book.add(order) {
-            self.manager.on_trade(
-                user_id,
-                self.order_owner[&fill.id].user_id,
-                order_request.market,
-                fill.quantity,
-                fill.price,
-                order_request.side,
-            );
-            order.quantity -= fill.quantity;
-            if fill.done {
-                self.order_owner.remove(&fill.id);
-            }
-        }
+        let execution = book.add(order, user_id, order_request.stp);
+        self.release_cancelled(user_id, event_id, &execution.cancelled);
+        let traded = self.apply_fills(user_id, event_id, order.side, &execution.fills);
+        order.quantity = execution.remaining;
 
         let book = self
             .orderbooks
             .get_mut(&order_request.market)
             .ok_or(RejectReason::MarketNotFound)?; // infallible
 
-        let mut quantity = order_request.quantity; // the quantity to report for the event
-        if order_request.tif == TimeInForce::IOC {
-            quantity = order_request.quantity - order.quantity; // only report the quantity that was filled
+        // the quantity to report for the event, less anything cancelled by self-trade prevention
+        let mut quantity = traded.saturating_add(order.quantity);
+        if order_request.tif.is_immediate() {
+            quantity = traded; // only report the quantity that was filled
             book.remove(order.id);
         } else if order.quantity > 0 {
             self.manager
                 .add_resting_order(user_id, order_request.market, order);
-            self.order_owner
-                .insert(order.id, OrderOwner { user_id, market_id: event_id });
+            self.order_owner.insert(order, user_id, event_id);
         }
 
-        let order = Order::new(order.id, quantity, order.price, order.side);
+        let order = Order { quantity, ..order };
+        let tick = book.get_next_tick();
+        self.merge_complete_sets(user_id, event_id, &execution.fills);
 
         let update = MarketUpdate::AddOrder {
             timestamp,
-            tick: book.get_next_tick(),
+            tick,
             market: order_request.market,
             user: user_id,
             order,
+            fills: execution.fills,
+            cancelled: execution.cancelled,
         };
         Ok(update)
     }
@@ -271,10 +825,40 @@ impl Exchange {
         user: UserId,
         id: OrderId,
     ) -> MatcherResult {
-        let event_id = match self.order_owner.entry(id) {
-            Entry::Occupied(entry) if entry.get().user_id == user => entry.remove().market_id,
+        self.remove_order(timestamp, user, id, CancelReason::Requested)
+    }
+
+    /// Cancels the user's resting order with the client order id.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::OrderNotFound)` if the user has no resting order with the id.
+    pub fn cancel_by_client_order_id(
+        &mut self,
+        timestamp: Timestamp,
+        user: UserId,
+        client_order_id: ClientOrderId,
+    ) -> MatcherResult {
+        let id = self
+            .order_owner
+            .by_client_order_id(user, client_order_id)
+            .ok_or(RejectReason::OrderNotFound)?;
+        self.cancel_order(timestamp, user, id)
+    }
+
+    /// Removes a user's resting order from the book for `reason`.
+    fn remove_order(
+        &mut self,
+        timestamp: Timestamp,
+        user: UserId,
+        id: OrderId,
+        reason: CancelReason,
+    ) -> MatcherResult {
+        let (event_id, client_order_id) = match self.order_owner.get(id) {
+            Some(owner) if owner.user_id == user => (owner.market_id, owner.client_order_id),
             _ => return Err(RejectReason::OrderNotFound),
         };
+        self.order_owner.remove(id);
 
         let book = self
             .orderbooks
@@ -283,28 +867,195 @@ impl Exchange {
         let order = book.remove(id).ok_or(RejectReason::OrderNotFound)?; // infallible
 
         self.manager.remove_order(user, event_id, order);
-        
+        self.manager.merge_complete_sets(user, event_id);
+
         let update = MarketUpdate::RemoveOrder {
             timestamp,
             tick: book.get_next_tick(),
             market: event_id,
             user,
             id,
+            client_order_id,
+            reason,
         };
 
         Ok(update)
     }
 
-    fn check_order(&self, user: UserId, order: OrderRequest) -> Result<(), RejectReason> {
+    /// Changes the price and/or quantity of a resting order, keeping its id.
+    /// Omitted values are left unchanged, and `quantity` is the new remaining quantity.
+    ///
+    /// A quantity decrease at the same price keeps queue priority. Anything else
+    /// requeues the order, and it trades like a new GTC order if it is marketable.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(RejectReason::OrderNotFound)` if the order does not exist or
+    ///   does not belong to the user.
+    /// - Returns `Err(RejectReason::InvalidPrice)` if the price is 0 or greater than or equal to `RESOLVE_PRICE`.
+    /// - Returns `Err(RejectReason::InvalidQuantity)` if the quantity is 0.
+    /// - Returns `Err(RejectReason::InsufficientFunds)` if the user cannot afford the amended order.
+    /// - Returns `Err(RejectReason::MarketHalted)` if the market is not open.
+    pub fn amend_order(
+        &mut self,
+        timestamp: Timestamp,
+        user: UserId,
+        id: OrderId,
+        quantity: Option<Quantity>,
+        price: Option<Price>,
+    ) -> MatcherResult {
+        let market_id = match self.order_owner.get(id) {
+            Some(owner) if owner.user_id == user => owner.market_id,
+            _ => return Err(RejectReason::OrderNotFound),
+        };
+        let book = self
+            .orderbooks
+            .get_mut(&market_id)
+            .ok_or(RejectReason::MarketNotFound)?; // infallible
+        let old = book.get(id).ok_or(RejectReason::OrderNotFound)?; // infallible
+        if book.state() != MarketState::Open {
+            return Err(RejectReason::MarketHalted);
+        }
+
+        let order = Order {
+            quantity: quantity.unwrap_or(old.quantity),
+            price: price.unwrap_or(old.price),
+            ..old
+        };
+        if order.price == 0 || order.price >= RESOLVE_PRICE {
+            return Err(RejectReason::InvalidPrice);
+        }
+        if order.quantity == 0 {
+            return Err(RejectReason::InvalidQuantity);
+        }
+        self.check_limits(user, market_id, order, Some(old))?;
+        let book = self
+            .orderbooks
+            .get_mut(&market_id)
+            .ok_or(RejectReason::MarketNotFound)?; // infallible
+
+        self.manager.remove_order(user, market_id, old);
+        if !self
+            .manager
+            .can_afford(user, market_id, order.quantity, order.price, order.side)
+        {
+            self.manager.add_resting_order(user, market_id, old);
+            return Err(RejectReason::InsufficientFunds);
+        }
+
+        let execution = book.amend(order).ok_or(RejectReason::OrderNotFound)?; // infallible
+        self.release_cancelled(user, market_id, &execution.cancelled);
+        let traded = self.apply_fills(user, market_id, order.side, &execution.fills);
+        if execution.remaining > 0 {
+            let remaining = Order {
+                quantity: execution.remaining,
+                ..order
+            };
+            self.manager.add_resting_order(user, market_id, remaining);
+        } else {
+            self.order_owner.remove(id);
+        }
+        let order = Order {
+            quantity: traded.saturating_add(execution.remaining),
+            ..order
+        };
+        self.merge_complete_sets(user, market_id, &execution.fills);
+
+        let book = self
+            .orderbooks
+            .get_mut(&market_id)
+            .ok_or(RejectReason::MarketNotFound)?; // infallible
+
+        Ok(MarketUpdate::AmendOrder {
+            timestamp,
+            tick: book.get_next_tick(),
+            market: market_id,
+            user,
+            order,
+            fills: execution.fills,
+            cancelled: execution.cancelled,
+        })
+    }
+
+    /// Releases the exposure of a user's resting orders reduced by self-trade prevention.
+    fn release_cancelled(&mut self, user: UserId, market: MarketId, cancelled: &[Order]) {
+        let book = &self.orderbooks[&market];
+        for &order in cancelled {
+            self.manager.remove_order(user, market, order);
+            if book.get(order.id).is_none() {
+                self.order_owner.remove(order.id);
+            }
+        }
+    }
+
+    /// Settles the fills of a taker order against their makers.
+    /// Returns the total quantity traded.
+    /// Merges complete sets in a mutually exclusive event for the taker and makers of a trade.
+    fn merge_complete_sets(&mut self, taker: UserId, market: MarketId, fills: &[Fill]) {
+        self.manager.merge_complete_sets(taker, market);
+        for fill in fills {
+            self.manager.merge_complete_sets(fill.user, market);
+        }
+    }
+
+    fn apply_fills(
+        &mut self,
+        taker: UserId,
+        market: MarketId,
+        side: Side,
+        fills: &[Fill],
+    ) -> Quantity {
+        let mut traded: Quantity = 0;
+        for fill in fills {
+            self.manager.on_trade(
+                taker,
+                fill.user,
+                market,
+                fill.quantity,
+                fill.price,
+                side,
+            );
+            traded = traded.saturating_add(fill.quantity);
+            if fill.done {
+                self.order_owner.remove(fill.id);
+            }
+        }
+        if let (Some(fill), Some(book)) = (fills.last(), self.orderbooks.get_mut(&market)) {
+            book.set_last_price(fill.price);
+        }
+        traded
+    }
+
+    fn check_order(
+        &self,
+        timestamp: Timestamp,
+        user: UserId,
+        order: OrderRequest,
+    ) -> Result<(), RejectReason> {
         if order.price == 0 || order.price >= RESOLVE_PRICE {
             Err(RejectReason::InvalidPrice)?;
         }
         if order.quantity == 0 {
             Err(RejectReason::InvalidQuantity)?;
         }
+        if (order.tif == TimeInForce::GTT) != order.expires_at.is_some()
+            || order.expires_at.is_some_and(|expires_at| expires_at <= timestamp)
+        {
+            Err(RejectReason::InvalidExpiry)?;
+        }
+        if order.client_order_id.is_some_and(|client_order_id| {
+            self.order_owner
+                .by_client_order_id(user, client_order_id)
+                .is_some()
+        }) {
+            Err(RejectReason::DuplicateClientOrderId)?;
+        }
         let Some(book) = self.orderbooks.get(&order.market) else {
             return Err(RejectReason::MarketNotFound);
         };
+        if book.state() != MarketState::Open {
+            Err(RejectReason::MarketHalted)?;
+        }
         if !self
             .manager
             .can_afford(user, order.market, order.quantity, order.price, order.side)
@@ -317,6 +1068,100 @@ impl Exchange {
         {
             Err(RejectReason::IOCNotMarketable)?;
         }
+        let new_order = Order::new(0, order.quantity, order.price, order.side);
+        if order.tif == TimeInForce::FOK
+            && book.fillable(new_order, user, order.stp) < order.quantity
+        {
+            Err(RejectReason::FOKNotFillable)?;
+        }
+        self.check_limits(user, order.market, new_order, None)?;
+        if !order.tif.is_immediate() {
+            let limits = self.limits(user, order.market);
+            if limits
+                .max_open_orders
+                .is_some_and(|max| self.order_owner.open_orders(user) >= max)
+            {
+                Err(RejectReason::MaxOpenOrdersExceeded)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the risk limits of markets and users that don't have their own.
+    pub const fn set_default_limits(&mut self, limits: RiskLimits) {
+        self.default_limits = limits;
+    }
+
+    /// Sets the risk limits of a market, which take precedence over the defaults.
+    pub fn set_market_limits(&mut self, market: MarketId, limits: RiskLimits) {
+        if limits.is_empty() {
+            self.market_limits.remove(&market);
+        } else {
+            self.market_limits.insert(market, limits);
+        }
+    }
+
+    /// Sets the risk limits of a user, which take precedence over market and default limits.
+    pub fn set_user_limits(&mut self, user: UserId, limits: RiskLimits) {
+        if limits.is_empty() {
+            self.user_limits.remove(&user);
+        } else {
+            self.user_limits.insert(user, limits);
+        }
+    }
+
+    /// Returns the risk limits that apply to the user's orders in the market.
+    fn limits(&self, user: UserId, market: MarketId) -> RiskLimits {
+        let user = self.user_limits.get(&user).copied().unwrap_or_default();
+        let market = self.market_limits.get(&market).copied().unwrap_or_default();
+        user.or(market).or(self.default_limits)
+    }
+
+    /// Checks an order against the risk limits, other than the number of open orders.
+    /// `replaced` is the resting order an amended order replaces.
+    fn check_limits(
+        &self,
+        user: UserId,
+        market: MarketId,
+        order: Order,
+        replaced: Option<Order>,
+    ) -> Result<(), RejectReason> {
+        let limits = self.limits(user, market);
+        if limits
+            .max_order_quantity
+            .is_some_and(|max| order.quantity > max)
+        {
+            Err(RejectReason::MaxOrderQuantityExceeded)?;
+        }
+        let notional = Balance::from(order.quantity).saturating_mul(Balance::from(order.price));
+        if limits
+            .max_order_notional
+            .is_some_and(|max| notional > max)
+        {
+            Err(RejectReason::MaxOrderNotionalExceeded)?;
+        }
+        if let Some(band) = limits.price_band {
+            let reference = self
+                .orderbooks
+                .get(&market)
+                .and_then(BookDetails::reference_price);
+            if reference.is_some_and(|reference| order.price.abs_diff(reference) > band) {
+                Err(RejectReason::PriceOutsideBand)?;
+            }
+        }
+        if let Some(max) = limits.max_position {
+            let replaced = replaced.map_or(0, |old| i64::from(old.quantity));
+            let worst = self.manager.worst_case_position(user, market, order.side);
+            let change = i64::from(order.quantity).saturating_sub(replaced);
+            let exceeded = match order.side {
+                Side::Buy => worst.saturating_add(change) > i64::from(max),
+                Side::Sell => worst.saturating_sub(change) < i64::from(max).saturating_neg(),
+            };
+            if exceeded {
+                Err(RejectReason::MaxPositionExceeded)?;
+            }
+        }
 
         Ok(())
     }
@@ -325,7 +1170,8 @@ impl Exchange {
 #[cfg(test)]
 mod tests {
     use crate::{
-        Exchange, MarketId, MarketUpdate, OrderBook, OrderRequest, Price, RejectReason,
+        CancelReason, Exchange, Fill, MarketId, MarketState, MarketUpdate, Order, OrderBook, OrderRequest,
+        Price, RejectReason, RiskLimits, SelfTradePrevention,
         TimeInForce, Timestamp, UserId, RESOLVE_PRICE,
     };
 
@@ -359,25 +1205,112 @@ mod tests {
     }
 
     #[test]
-    fn test_submit_gtc_then_cancel_twice() {
+    fn test_submit_gtc_then_cancel_twice() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 10, BID_PRICE, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(TIME, 0, EVENT, TAKER, 0, 10, BID_PRICE))
+        );
+
+        assert_eq!(exch.manager.get_balance(TAKER), 100000);
+        assert_eq!(exch.manager.get_available(TAKER), 40000);
+
+        let event = exch.cancel_order(TIME, TAKER, 0);
+        assert_eq!(event, Ok(MarketUpdate::remove(TIME, 1, EVENT, TAKER, 0)));
+
+        assert_eq!(exch.manager.get_available(TAKER), 100000);
+
+        let event = exch.cancel_order(TIME, TAKER, 0);
+        assert_eq!(event, Err(RejectReason::OrderNotFound));
+    }
+
+    #[test]
+    fn test_amend_keeps_id_and_priority() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        assert_eq!(exch.manager.get_available(MAKER), 70000);
+
+        let event = exch.amend_order(TIME, MAKER, 0, Some(2), None);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::AmendOrder {
+                timestamp: TIME,
+                tick: 2,
+                market: EVENT,
+                user: MAKER,
+                order: Order::sell(0, 2, ASK_PRICE),
+                fills: vec![],
+                cancelled: vec![],
+            })
+        );
+        assert_eq!(exch.manager.get_available(MAKER), 79000);
+
+        // order 0 is still first in the queue
+        let order = OrderRequest::buy(EVENT, 3, ASK_PRICE, TimeInForce::IOC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert_eq!(
+            exch.cancel_order(TIME, MAKER, 0),
+            Err(RejectReason::OrderNotFound)
+        );
+        assert!(exch.cancel_order(TIME, MAKER, 1).is_ok());
+        assert_eq!(exch.manager.get_position(MAKER, EVENT), -3);
+    }
+
+    #[test]
+    fn test_amend_reprice_trades() {
         let mut exch = setup_default_scenario();
-        let order = OrderRequest::buy(EVENT, 10, BID_PRICE, TimeInForce::GTC);
-        let event = exch.submit_order(TIME, TAKER, order);
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+
+        let event = exch.amend_order(TIME, MAKER, 1, None, Some(BID_PRICE));
         assert_eq!(
             event,
-            Ok(MarketUpdate::buy(TIME, 0, EVENT, TAKER, 0, 10, BID_PRICE))
+            Ok(MarketUpdate::AmendOrder {
+                timestamp: TIME,
+                tick: 2,
+                market: EVENT,
+                user: MAKER,
+                order: Order::sell(1, 5, BID_PRICE),
+                fills: vec![Fill::new(0, TAKER, 2, BID_PRICE, true)],
+                cancelled: vec![],
+            })
         );
+        assert_eq!(exch.manager.get_position(MAKER, EVENT), -2);
+        assert_eq!(exch.manager.get_position(TAKER, EVENT), 2);
+        assert_eq!(exch.manager.get_available(MAKER), 100000 - 8000 - 12000);
 
-        assert_eq!(exch.manager.get_balance(TAKER), 100000);
-        assert_eq!(exch.manager.get_available(TAKER), 40000);
-
-        let event = exch.cancel_order(TIME, TAKER, 0);
-        assert_eq!(event, Ok(MarketUpdate::remove(TIME, 1, EVENT, TAKER, 0)));
+        assert!(exch.cancel_order(TIME, MAKER, 1).is_ok());
+        assert_eq!(exch.manager.get_available(MAKER), 92000);
+    }
 
-        assert_eq!(exch.manager.get_available(TAKER), 100000);
+    #[test]
+    fn test_amend_rejects() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
 
-        let event = exch.cancel_order(TIME, TAKER, 0);
+        let event = exch.amend_order(TIME, MAKER, 0, Some(1), None);
         assert_eq!(event, Err(RejectReason::OrderNotFound));
+        let event = exch.amend_order(TIME, TAKER, 0, Some(0), None);
+        assert_eq!(event, Err(RejectReason::InvalidQuantity));
+        let event = exch.amend_order(TIME, TAKER, 0, None, Some(RESOLVE_PRICE));
+        assert_eq!(event, Err(RejectReason::InvalidPrice));
+        let event = exch.amend_order(TIME, TAKER, 0, Some(1000), None);
+        assert_eq!(event, Err(RejectReason::InsufficientFunds));
+
+        // rejected amends leave the order untouched
+        assert_eq!(exch.manager.get_available(TAKER), 88000);
+        assert_eq!(
+            exch.cancel_order(TIME, TAKER, 0),
+            Ok(MarketUpdate::remove(TIME, 1, EVENT, TAKER, 0))
+        );
     }
 
     #[test]
@@ -397,7 +1330,8 @@ mod tests {
         let event = exch.submit_order(TIME, TAKER, order);
         assert_eq!(
             event,
-            Ok(MarketUpdate::buy(TIME, 1, EVENT, TAKER, 1, 1, ASK_PRICE))
+            Ok(MarketUpdate::buy(TIME, 1, EVENT, TAKER, 1, 1, ASK_PRICE)
+                .with_fills(vec![Fill::new(0, MAKER, 1, ASK_PRICE, true)]))
         );
 
         assert_eq!(exch.manager.get_balance(MAKER), 97000);
@@ -455,6 +1389,166 @@ mod tests {
         assert_eq!(event, Err(RejectReason::IOCNotMarketable));
     }
 
+    #[test]
+    fn test_fill_or_kill() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::sell(EVENT, 3, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let snapshot = exch.snapshot(0);
+
+        let order = OrderRequest::buy(EVENT, 4, ASK_PRICE, TimeInForce::FOK);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::FOKNotFillable));
+        let order = OrderRequest::buy(EVENT, 3, BID_PRICE, TimeInForce::FOK);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::FOKNotFillable));
+        assert_eq!(exch.snapshot(0), snapshot);
+
+        let order = OrderRequest::buy(EVENT, 3, ASK_PRICE, TimeInForce::FOK);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(TIME, 1, EVENT, TAKER, 1, 3, ASK_PRICE)
+                .with_fills(vec![Fill::new(0, MAKER, 3, ASK_PRICE, true)]))
+        );
+    }
+
+    #[test]
+    fn test_all_or_none() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::sell(EVENT, 2, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+
+        // rests without trading until it can be filled in full
+        let order = OrderRequest::buy(EVENT, 3, ASK_PRICE, TimeInForce::AON);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::AddOrder {
+                timestamp: TIME,
+                tick: 1,
+                market: EVENT,
+                user: TAKER,
+                order: Order::buy(1, 3, ASK_PRICE).with_all_or_none(true),
+                fills: Vec::new(),
+                cancelled: Vec::new(),
+            })
+        );
+        assert_eq!(exch.manager.get_available(TAKER), 79000);
+
+        let order = OrderRequest::sell(EVENT, 2, BID_PRICE, TimeInForce::IOC);
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(event, Ok(MarketUpdate::sell(TIME, 2, EVENT, MAKER, 2, 0, BID_PRICE)));
+
+        let order = OrderRequest::sell(EVENT, 3, BID_PRICE, TimeInForce::IOC);
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::sell(TIME, 3, EVENT, MAKER, 3, 3, BID_PRICE)
+                .with_fills(vec![Fill::new(1, TAKER, 3, ASK_PRICE, true)]))
+        );
+        assert_eq!(exch.cancel_order(TIME, TAKER, 1), Err(RejectReason::OrderNotFound));
+    }
+
+    #[test]
+    fn test_good_till_time() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTT);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::InvalidExpiry));
+        let event = exch.submit_order(TIME, TAKER, order.with_expiry(TIME));
+        assert_eq!(event, Err(RejectReason::InvalidExpiry));
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC).with_expiry(10);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::InvalidExpiry));
+
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTT);
+        assert!(exch.submit_order(TIME, TAKER, order.with_expiry(10)).is_ok());
+        assert!(exch.submit_order(TIME, TAKER, order.with_expiry(20)).is_ok());
+        assert!(exch.cancel_order(TIME, TAKER, 1).is_ok());
+        // keeps its expiry when requeued
+        assert!(exch.amend_order(TIME, TAKER, 0, Some(3), None).is_ok());
+        assert_eq!(exch.expire_orders(9), vec![]);
+
+        assert_eq!(
+            exch.expire_orders(20),
+            vec![MarketUpdate::RemoveOrder {
+                timestamp: 20,
+                tick: 4,
+                market: EVENT,
+                user: TAKER,
+                id: 0,
+                client_order_id: None,
+                reason: CancelReason::Expired,
+            }]
+        );
+        assert_eq!(exch.manager.get_available(TAKER), 100_000);
+        assert_eq!(exch.expire_orders(30), vec![]);
+        assert_eq!(exch.cancel_order(TIME, TAKER, 0), Err(RejectReason::OrderNotFound));
+    }
+
+    #[test]
+    fn test_client_order_id() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC).with_client_order_id(7);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert_eq!(
+            exch.submit_order(TIME, TAKER, order),
+            Err(RejectReason::DuplicateClientOrderId)
+        );
+        // unique per user
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+
+        assert_eq!(
+            exch.cancel_by_client_order_id(TIME, TAKER, 7),
+            Ok(MarketUpdate::RemoveOrder {
+                timestamp: TIME,
+                tick: 2,
+                market: EVENT,
+                user: TAKER,
+                id: 0,
+                client_order_id: Some(7),
+                reason: CancelReason::Requested,
+            })
+        );
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+
+        // orders that don't rest don't keep their id, and filled orders free theirs
+        let ioc = OrderRequest::sell(EVENT, 4, BID_PRICE, TimeInForce::IOC).with_client_order_id(8);
+        assert!(exch.submit_order(TIME, MAKER, ioc).is_ok());
+        assert_eq!(
+            exch.cancel_by_client_order_id(TIME, MAKER, 8),
+            Err(RejectReason::OrderNotFound)
+        );
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+    }
+
+    #[test]
+    fn test_cancel_user_orders() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+
+        let remove = |tick, id| MarketUpdate::RemoveOrder {
+            timestamp: TIME,
+            tick,
+            market: EVENT,
+            user: TAKER,
+            id,
+            client_order_id: None,
+            reason: CancelReason::Disconnected,
+        };
+        assert_eq!(
+            exch.cancel_user_orders(TIME, TAKER),
+            vec![remove(3, 0), remove(4, 2)]
+        );
+        assert_eq!(exch.manager.get_available(TAKER), 100_000);
+        assert_eq!(exch.cancel_user_orders(TIME, TAKER), vec![]);
+        assert!(exch.cancel_order(TIME, MAKER, 1).is_ok());
+    }
+
     #[test]
     fn test_self_trade() {
         let mut exch = setup_default_scenario();
@@ -473,7 +1567,11 @@ mod tests {
 
         let order = OrderRequest::buy(book, 2, 4000, TimeInForce::GTC);
         let event = exch.submit_order(time, bob, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 1, book, bob, 1, 2, 4000)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 1, book, bob, 1, 2, 4000)
+                .with_fills(vec![Fill::new(0, bob, 2, 4000, false)]))
+        );
 
         assert_eq!(exch.manager.get_balance(bob), 100000);
         assert_eq!(exch.manager.get_available(bob), 82000);
@@ -482,6 +1580,122 @@ mod tests {
         assert_eq!(exch.manager.get_available(bob), 100000);
     }
 
+    #[test]
+    fn test_self_trade_prevention() {
+        let mut exch = setup_default_scenario();
+        let bob = 1;
+
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, bob, order).is_ok());
+        assert_eq!(exch.manager.get_available(bob), 85000);
+
+        let order = OrderRequest::buy(EVENT, 2, ASK_PRICE, TimeInForce::GTC)
+            .with_stp(SelfTradePrevention::DecrementAndCancel);
+        let event = exch.submit_order(TIME, bob, order);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::AddOrder {
+                timestamp: TIME,
+                tick: 1,
+                market: EVENT,
+                user: bob,
+                order: Order::buy(1, 0, ASK_PRICE),
+                fills: vec![],
+                cancelled: vec![Order::sell(0, 2, ASK_PRICE)],
+            })
+        );
+        assert_eq!(exch.manager.get_position(bob, EVENT), 0);
+        assert_eq!(exch.manager.get_available(bob), 91000);
+
+        let order = OrderRequest::buy(EVENT, 2, ASK_PRICE, TimeInForce::GTC)
+            .with_stp(SelfTradePrevention::CancelOldest);
+        assert!(exch.submit_order(TIME, bob, order).is_ok());
+        assert_eq!(
+            exch.cancel_order(TIME, bob, 0),
+            Err(RejectReason::OrderNotFound)
+        );
+        assert_eq!(exch.manager.get_available(bob), 86000);
+        assert!(exch.cancel_order(TIME, bob, 2).is_ok());
+        assert_eq!(exch.manager.get_available(bob), 100000);
+    }
+
+    #[test]
+    fn test_exclusive_event() {
+        let mut exch = setup_default_scenario();
+        let other = 2;
+        exch.add_event(TIME, other).unwrap();
+
+        assert_eq!(
+            exch.add_exclusive_event(TIME, vec![EVENT, 3]),
+            Err(RejectReason::MarketNotFound)
+        );
+        assert_eq!(
+            exch.add_exclusive_event(TIME, vec![EVENT, EVENT]),
+            Err(RejectReason::InvalidExclusiveEvent)
+        );
+        assert_eq!(
+            exch.add_exclusive_event(TIME, vec![]),
+            Err(RejectReason::InvalidExclusiveEvent)
+        );
+        assert!(exch.add_exclusive_event(TIME, vec![EVENT, other]).is_ok());
+        assert_eq!(
+            exch.add_exclusive_event(TIME, vec![other, EVENT]),
+            Err(RejectReason::InvalidExclusiveEvent)
+        );
+
+        // buying YES in every market costs 9000 for a set worth RESOLVE_PRICE
+        for (market, price) in [(EVENT, 6000), (other, 3000)] {
+            let order = OrderRequest::sell(market, 2, price, TimeInForce::GTC);
+            assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+            let order = OrderRequest::buy(market, 2, price, TimeInForce::IOC);
+            assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        }
+        assert_eq!(exch.manager.get_position(TAKER, EVENT), 0);
+        assert_eq!(exch.manager.get_available(TAKER), 102000);
+        assert_eq!(exch.manager.get_available(MAKER), 98000);
+
+        assert_eq!(
+            exch.resolve(TIME, other, RESOLVE_PRICE),
+            Err(RejectReason::InvalidResolution)
+        );
+        assert_eq!(
+            exch.resolve(TIME, other, 5000),
+            Err(RejectReason::InvalidResolution)
+        );
+        assert!(exch.resolve(TIME, other, 0).is_ok());
+        assert_eq!(
+            exch.resolve(TIME, EVENT, 0),
+            Err(RejectReason::InvalidResolution)
+        );
+        assert!(exch.resolve(TIME, EVENT, RESOLVE_PRICE).is_ok());
+        assert_eq!(exch.manager.get_balance(TAKER), 102000);
+        assert_eq!(exch.manager.get_balance(MAKER), 98000);
+    }
+
+    #[test]
+    fn test_exclusive_winner_resolves_last() {
+        let mut exch = setup_default_scenario();
+        exch.add_event(TIME, 2).unwrap();
+        exch.add_event(TIME, 3).unwrap();
+        assert!(exch.add_exclusive_event(TIME, vec![EVENT, 2, 3]).is_ok());
+
+        // the winner can't be resolved while a loser is unresolved
+        assert_eq!(
+            exch.resolve(TIME, EVENT, RESOLVE_PRICE),
+            Err(RejectReason::InvalidResolution)
+        );
+        assert!(exch.resolve(TIME, 3, 0).is_ok());
+        assert_eq!(
+            exch.resolve(TIME, EVENT, RESOLVE_PRICE),
+            Err(RejectReason::InvalidResolution)
+        );
+        assert!(exch.resolve(TIME, 2, 0).is_ok());
+
+        // nor to anything but RESOLVE_PRICE once it is the last market left
+        assert_eq!(exch.resolve(TIME, EVENT, 0), Err(RejectReason::InvalidResolution));
+        assert!(exch.resolve(TIME, EVENT, RESOLVE_PRICE).is_ok());
+    }
+
     #[test]
     fn trade_multiple_levels() {
         let mut exch = setup_default_scenario();
@@ -519,7 +1733,15 @@ mod tests {
 
         let order = OrderRequest::buy(book, 7, 4300, TimeInForce::GTC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 4, book, cat, 4, 7, 4300)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 4, book, cat, 4, 7, 4300)
+                .with_fills(vec![
+                    Fill::new(0, bob, 3, 4000, true),
+                    Fill::new(2, bob, 3, 4100, true),
+                    Fill::new(3, bob, 1, 4100, false),
+                ]))
+        );
 
         assert_eq!(exch.manager.get_balance(bob), 58400);
         assert_eq!(exch.manager.get_balance(cat), 71600);
@@ -577,7 +1799,15 @@ mod tests {
 
         let order = OrderRequest::sell(book, 7, 39, TimeInForce::GTC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::sell(time, 5, book, cat, 4, 7, 39)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::sell(time, 5, book, cat, 4, 7, 39)
+                .with_fills(vec![
+                    Fill::new(0, bob, 3, 40, true),
+                    Fill::new(2, bob, 3, 40, true),
+                    Fill::new(3, bob, 1, 39, false),
+                ]))
+        );
 
         assert_eq!(exch.manager.get_position(bob, book), 7);
         assert_eq!(exch.manager.get_position(cat, book), -7);
@@ -602,13 +1832,17 @@ mod tests {
 
         let order = OrderRequest::buy(book, 5, 4000, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 1, book, cat, 1, 3, 4000)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 1, book, cat, 1, 3, 4000)
+                .with_fills(vec![Fill::new(0, bob, 3, 4000, true)]))
+        );
 
         assert_eq!(exch.manager.get_position(bob, book), -3);
         assert_eq!(exch.manager.get_position(cat, book), 3);
 
         let event = exch.resolve(time, book, 7000);
-        assert_eq!(event, Ok(MarketUpdate::resolve(time, 2, book, 0, 7000)));
+        assert_eq!(event, Ok(MarketUpdate::resolve(time, 2, book, 7000)));
         assert_eq!(exch.manager.get_balance(bob), 91000);
         assert_eq!(exch.manager.get_balance(cat), 109000);
 
@@ -636,7 +1870,11 @@ mod tests {
         // cat submits order
         let order = OrderRequest::buy(book, 1, 5250, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 1, book, cat, 1, 1, 5250)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 1, book, cat, 1, 1, 5250)
+                .with_fills(vec![Fill::new(0, bob, 1, 5250, false)]))
+        );
 
         // bob places resting order
         let order = OrderRequest::buy(book, 10, 4750, TimeInForce::GTC);
@@ -651,7 +1889,8 @@ mod tests {
         let event = exch.submit_order(time, cat, order);
         assert_eq!(
             event,
-            Ok(MarketUpdate::sell(time, 3, book, cat, 3, 1, 4750))
+            Ok(MarketUpdate::sell(time, 3, book, cat, 3, 1, 4750)
+                .with_fills(vec![Fill::new(2, bob, 1, 4750, false)]))
         );
 
         assert_eq!(exch.manager.get_balance(bob), 100500);
@@ -685,10 +1924,18 @@ mod tests {
 
         let order = OrderRequest::buy(book, 1, 9999, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 2, book, cat, 2, 1, 9999)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 2, book, cat, 2, 1, 9999)
+                .with_fills(vec![Fill::new(0, bob, 1, 7000, false)]))
+        );
         let order = OrderRequest::sell(book, 1, 1, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::sell(time, 3, book, cat, 3, 1, 1)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::sell(time, 3, book, cat, 3, 1, 1)
+                .with_fills(vec![Fill::new(1, bob, 1, 6000, false)]))
+        );
 
         assert_eq!(exch.manager.get_balance(bob), 101000);
         assert_eq!(exch.manager.get_balance(cat), 99000);
@@ -721,7 +1968,11 @@ mod tests {
 
         let order = OrderRequest::buy(book, 1, 9999, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 1, book, cat, 1, 1, 9999)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 1, book, cat, 1, 1, 9999)
+                .with_fills(vec![Fill::new(0, bob, 1, 7000, false)]))
+        );
 
         assert_eq!(exch.manager.get_available(bob), 85000);
         assert_eq!(exch.manager.get_balance(bob), 97000);
@@ -750,7 +2001,11 @@ mod tests {
 
         let order = OrderRequest::sell(book, 1, 1, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::sell(time, 1, book, cat, 1, 1, 1)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::sell(time, 1, book, cat, 1, 1, 1)
+                .with_fills(vec![Fill::new(0, bob, 1, 7000, false)]))
+        );
 
         assert_eq!(exch.manager.get_balance(bob), 93000);
         assert_eq!(exch.manager.get_available(bob), 65000);
@@ -788,7 +2043,11 @@ mod tests {
 
         let order = OrderRequest::buy(book, 1, 9999, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::buy(time, 2, book, cat, 2, 1, 9999)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::buy(time, 2, book, cat, 2, 1, 9999)
+                .with_fills(vec![Fill::new(0, bob, 1, 6000, false)]))
+        );
 
         assert_eq!(exch.manager.get_available(cat), 94000);
         assert_eq!(exch.manager.get_balance(cat), 94000);
@@ -797,7 +2056,11 @@ mod tests {
 
         let order = OrderRequest::sell(book, 3, 1, TimeInForce::IOC);
         let event = exch.submit_order(time, cat, order);
-        assert_eq!(event, Ok(MarketUpdate::sell(time, 3, book, cat, 3, 3, 1)));
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::sell(time, 3, book, cat, 3, 3, 1)
+                .with_fills(vec![Fill::new(1, bob, 3, 5000, false)]))
+        );
 
         assert_eq!(exch.manager.get_balance(cat), 89000);
         assert_eq!(exch.manager.get_available(cat), 89000);
@@ -810,4 +2073,347 @@ mod tests {
 
         assert_eq!(exch.manager.get_available(bob), 91000);
     }
+
+    #[test]
+    fn test_order_size_limits() {
+        let mut exch = setup_default_scenario();
+        exch.set_default_limits(RiskLimits {
+            max_order_quantity: Some(5),
+            max_order_notional: Some(20_000),
+            ..RiskLimits::default()
+        });
+
+        let order = OrderRequest::buy(EVENT, 6, 1000, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOrderQuantityExceeded));
+
+        let order = OrderRequest::buy(EVENT, 4, BID_PRICE, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOrderNotionalExceeded));
+
+        let order = OrderRequest::buy(EVENT, 3, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let event = exch.amend_order(TIME, TAKER, 0, Some(4), None);
+        assert_eq!(event, Err(RejectReason::MaxOrderNotionalExceeded));
+    }
+
+    #[test]
+    fn test_limit_precedence() {
+        let mut exch = setup_default_scenario();
+        let limits = |max| RiskLimits {
+            max_order_quantity: Some(max),
+            ..RiskLimits::default()
+        };
+        exch.set_default_limits(limits(1));
+        exch.set_market_limits(EVENT, limits(2));
+        exch.set_user_limits(TAKER, limits(3));
+
+        let order = OrderRequest::buy(EVENT, 3, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 3, BID_PRICE, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOrderQuantityExceeded));
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+
+        // clearing the market's limits falls back to the defaults
+        exch.set_market_limits(EVENT, RiskLimits::default());
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOrderQuantityExceeded));
+    }
+
+    #[test]
+    fn test_max_open_orders() {
+        let mut exch = setup_default_scenario();
+        exch.set_default_limits(RiskLimits {
+            max_open_orders: Some(2),
+            ..RiskLimits::default()
+        });
+
+        let order = OrderRequest::sell(EVENT, 1, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOpenOrdersExceeded));
+
+        // orders that don't rest are allowed
+        let order = OrderRequest::buy(EVENT, 1, ASK_PRICE, TimeInForce::IOC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+
+        // filled and cancelled orders stop counting
+        let order = OrderRequest::sell(EVENT, 1, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        assert!(exch.cancel_order(TIME, MAKER, 1).is_ok());
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let event = exch.submit_order(TIME, MAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxOpenOrdersExceeded));
+    }
+
+    #[test]
+    fn test_max_position() {
+        let mut exch = setup_default_scenario();
+        exch.set_default_limits(RiskLimits {
+            max_position: Some(10),
+            ..RiskLimits::default()
+        });
+
+        let order = OrderRequest::buy(EVENT, 6, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 5, BID_PRICE, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxPositionExceeded));
+
+        // resting orders count towards the position, and amends replace them
+        assert!(exch.amend_order(TIME, TAKER, 0, Some(10), None).is_ok());
+        let event = exch.amend_order(TIME, TAKER, 0, Some(11), None);
+        assert_eq!(event, Err(RejectReason::MaxPositionExceeded));
+
+        // the other side is checked on its own
+        let order = OrderRequest::sell(EVENT, 10, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::sell(EVENT, 1, ASK_PRICE, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MaxPositionExceeded));
+    }
+
+    #[test]
+    fn test_price_band() {
+        let mut exch = setup_default_scenario();
+        exch.set_default_limits(RiskLimits {
+            price_band: Some(1000),
+            ..RiskLimits::default()
+        });
+
+        // no reference price yet
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 5, 5000, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+
+        // around the mid of 6000
+        let order = OrderRequest::buy(EVENT, 1, RESOLVE_PRICE - 1, TimeInForce::IOC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::PriceOutsideBand));
+        let order = OrderRequest::buy(EVENT, 1, ASK_PRICE, TimeInForce::IOC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+
+        // around the last trade of 7000
+        let order = OrderRequest::sell(EVENT, 1, 5500, TimeInForce::GTC);
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::PriceOutsideBand));
+        let order = OrderRequest::sell(EVENT, 1, 8000, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+    }
+
+    #[test]
+    fn test_market_state() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::sell(EVENT, 2, ASK_PRICE, TimeInForce::GTC);
+        let Ok(MarketUpdate::AddOrder { order: resting, .. }) = exch.submit_order(TIME, MAKER, order)
+        else {
+            panic!("expected order to be added");
+        };
+        let available = exch.manager.get_available(MAKER);
+
+        assert!(exch.set_market_state(TIME, EVENT, MarketState::Halted).is_ok());
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MarketHalted));
+        let event = exch.amend_order(TIME, MAKER, resting.id, Some(1), None);
+        assert_eq!(event, Err(RejectReason::MarketHalted));
+
+        assert!(exch.set_market_state(TIME, EVENT, MarketState::Open).is_ok());
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+
+        // closing leaves orders to be cancelled separately
+        assert!(exch.set_market_state(TIME, EVENT, MarketState::Closed).is_ok());
+        let event = exch.submit_order(TIME, TAKER, order);
+        assert_eq!(event, Err(RejectReason::MarketHalted));
+        let cancelled = exch.cancel_market_orders(TIME, EVENT).unwrap();
+        assert_eq!(cancelled.len(), 2);
+        assert!(matches!(
+            cancelled[0],
+            MarketUpdate::RemoveOrder { user: MAKER, id, .. } if id == resting.id
+        ));
+        assert!(exch.manager.get_available(MAKER) > available);
+        assert_eq!(exch.cancel_market_orders(TIME, EVENT), Ok(Vec::new()));
+
+        let event = exch.set_market_state(TIME, 2, MarketState::Open);
+        assert_eq!(event, Err(RejectReason::MarketNotFound));
+    }
+
+    #[test]
+    fn test_void_market() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::sell(EVENT, 5, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 3, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::sell(EVENT, 1, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert_ne!(exch.manager.get_balance(MAKER), 100000);
+        assert_ne!(exch.manager.get_balance(TAKER), 100000);
+
+        let mut replayed = Exchange::from_snapshot(&exch.snapshot(0));
+        let event = exch.void_market(TIME, EVENT);
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::VoidMarket {
+                timestamp: TIME,
+                tick: 3,
+                market: EVENT,
+            })
+        );
+        for user in [MAKER, TAKER] {
+            assert_eq!(exch.manager.get_balance(user), 100000);
+            assert_eq!(exch.manager.get_available(user), 100000);
+            assert_eq!(exch.manager.get_position(user, EVENT), 0);
+        }
+        assert_eq!(exch.cancel_order(TIME, MAKER, 0), Err(RejectReason::OrderNotFound));
+        assert_eq!(exch.void_market(TIME, EVENT), Err(RejectReason::MarketNotFound));
+
+        assert_eq!(replayed.apply(&event.unwrap()), Ok(()));
+        assert_eq!(replayed.snapshot(0), exch.snapshot(0));
+
+        let mut exch = Exchange::default();
+        assert!(exch.add_event(TIME, EVENT).is_ok());
+        assert!(exch.add_event(TIME, 2).is_ok());
+        assert!(exch.add_exclusive_event(TIME, vec![EVENT, 2]).is_ok());
+        let event = exch.void_market(TIME, EVENT);
+        assert_eq!(event, Err(RejectReason::InvalidResolution));
+    }
+
+    #[test]
+    fn test_void_market_needs_proceeds_available() {
+        let mut exch = setup_default_scenario();
+        // the maker sells 2 at 7000 and buys them back at 6000, then withdraws everything
+        let order = OrderRequest::sell(EVENT, 2, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 2, ASK_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        let order = OrderRequest::buy(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, MAKER, order).is_ok());
+        let order = OrderRequest::sell(EVENT, 2, BID_PRICE, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert!(exch.withdraw(TIME, MAKER, 102_000).is_ok());
+
+        let snapshot = exch.snapshot(0);
+        let event = exch.void_market(TIME, EVENT);
+        assert_eq!(event, Err(RejectReason::InsufficientFunds));
+        assert_eq!(exch.snapshot(0), snapshot);
+
+        assert!(exch.deposit(TIME, MAKER, 2000).is_ok());
+        assert!(exch.void_market(TIME, EVENT).is_ok());
+        assert_eq!(exch.manager.get_balance(MAKER), 0);
+        assert_eq!(exch.manager.get_balance(TAKER), 100_000);
+    }
+
+    #[test]
+    fn test_withdraw_and_adjust() {
+        let mut exch = setup_default_scenario();
+        let order = OrderRequest::buy(EVENT, 10, 5000, TimeInForce::GTC);
+        assert!(exch.submit_order(TIME, TAKER, order).is_ok());
+        assert_eq!(exch.manager.get_available(TAKER), 50000);
+
+        assert_eq!(exch.withdraw(TIME, TAKER, 0), Err(RejectReason::InvalidAmount));
+        assert_eq!(exch.withdraw(TIME, TAKER, 50001), Err(RejectReason::InsufficientFunds));
+        assert_eq!(exch.withdraw(TIME, 9, 1), Err(RejectReason::InsufficientFunds));
+        assert_eq!(
+            exch.withdraw(TIME, TAKER, 20000),
+            Ok(MarketUpdate::Withdrawal {
+                timestamp: TIME,
+                user: TAKER,
+                amount: 20000,
+            })
+        );
+        assert_eq!(exch.manager.get_balance(TAKER), 80000);
+        assert_eq!(exch.manager.get_available(TAKER), 30000);
+
+        let memo = "Mistaken deposit".to_string();
+        assert_eq!(
+            exch.adjust_balance(TIME, TAKER, 0, memo.clone()),
+            Err(RejectReason::InvalidAmount)
+        );
+        assert_eq!(
+            exch.adjust_balance(TIME, TAKER, -30001, memo.clone()),
+            Err(RejectReason::InsufficientFunds)
+        );
+        assert!(exch.adjust_balance(TIME, TAKER, -30000, memo.clone()).is_ok());
+        assert_eq!(exch.manager.get_available(TAKER), 0);
+        let event = exch.adjust_balance(TIME, 9, 500, memo.clone());
+        assert_eq!(
+            event,
+            Ok(MarketUpdate::Adjustment {
+                timestamp: TIME,
+                user: 9,
+                amount: 500,
+                memo,
+            })
+        );
+        assert_eq!(exch.manager.get_balance(9), 500);
+    }
+
+    #[test]
+    fn test_snapshot_and_apply() {
+        let mut exch = Exchange::default();
+        let other = 2;
+        let mut updates = vec![
+            exch.add_event(TIME, EVENT).unwrap(),
+            exch.add_event(TIME, other).unwrap(),
+            exch.add_exclusive_event(TIME, vec![EVENT, other]).unwrap(),
+            exch.deposit(TIME, TAKER, 100_000).unwrap(),
+            exch.deposit(TIME, MAKER, 100_000).unwrap(),
+            exch.withdraw(TIME, MAKER, 1_000).unwrap(),
+            exch.adjust_balance(TIME, TAKER, -500, "Fee refund reversed".to_string()).unwrap(),
+        ];
+        let requests = [
+            (MAKER, OrderRequest::sell(EVENT, 5, 6000, TimeInForce::GTC)),
+            (MAKER, OrderRequest::sell(EVENT, 2, 6000, TimeInForce::GTC)),
+            (MAKER, OrderRequest::buy(EVENT, 3, 4000, TimeInForce::GTC)),
+            (TAKER, OrderRequest::buy(EVENT, 3, 6000, TimeInForce::IOC)),
+            (MAKER, OrderRequest::sell(other, 4, 3000, TimeInForce::GTC)),
+            (TAKER, OrderRequest::buy(other, 2, 3000, TimeInForce::GTC)),
+            (MAKER, OrderRequest::buy(EVENT, 2, 6000, TimeInForce::GTC)
+                .with_stp(SelfTradePrevention::DecrementAndCancel)),
+            (TAKER, OrderRequest::buy(EVENT, 6, 4000, TimeInForce::AON)),
+            (TAKER, OrderRequest::buy(EVENT, 1, 3000, TimeInForce::GTT).with_expiry(5)),
+            (TAKER, OrderRequest::buy(EVENT, 1, 3000, TimeInForce::GTT).with_expiry(10)),
+            (TAKER, OrderRequest::buy(EVENT, 1, 3500, TimeInForce::GTC).with_client_order_id(7)),
+            (TAKER, OrderRequest::buy(EVENT, 1, 3500, TimeInForce::GTC).with_client_order_id(8)),
+        ];
+        for (user, order) in requests {
+            updates.push(exch.submit_order(TIME, user, order).unwrap());
+        }
+        updates.push(exch.amend_order(TIME, MAKER, 2, Some(1), None).unwrap());
+        updates.push(exch.amend_order(TIME, MAKER, 4, None, Some(2500)).unwrap());
+        updates.push(exch.cancel_order(TIME, MAKER, 1).unwrap());
+        updates.push(exch.cancel_by_client_order_id(TIME, TAKER, 8).unwrap());
+        updates.push(exch.set_market_state(TIME, other, MarketState::Halted).unwrap());
+        updates.extend(exch.expire_orders(5));
+
+        let seq = u64::try_from(updates.len()).unwrap();
+        let snapshot = exch.snapshot(seq);
+        assert!(!snapshot.books[0].orders.is_empty());
+        assert!(!snapshot.positions.is_empty());
+        assert_eq!(Exchange::from_snapshot(&snapshot).snapshot(seq), snapshot);
+
+        let mut replayed = Exchange::default();
+        for update in &updates {
+            assert_eq!(replayed.apply(update), Ok(()));
+        }
+        assert_eq!(replayed.snapshot(seq), snapshot);
+
+        // both keep trading the same way
+        let mut restored = Exchange::from_snapshot(&snapshot);
+        let order = OrderRequest::buy(EVENT, 1, 3500, TimeInForce::GTC).with_client_order_id(7);
+        assert_eq!(
+            restored.submit_order(TIME, TAKER, order),
+            Err(RejectReason::DuplicateClientOrderId)
+        );
+        let order = OrderRequest::sell(EVENT, 5, 1000, TimeInForce::GTC);
+        assert_eq!(
+            restored.submit_order(TIME, TAKER, order),
+            exch.submit_order(TIME, TAKER, order)
+        );
+        assert_eq!(restored.snapshot(seq), exch.snapshot(seq));
+    }
 }
@@ -0,0 +1,12 @@
+/// Whether a market is trading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MarketState {
+    /// Orders can be placed and amended.
+    #[default]
+    Open,
+    /// Trading is paused. Orders can only be cancelled.
+    Halted,
+    /// Trading has ended and the market is waiting to be resolved.
+    /// Orders can only be cancelled.
+    Closed,
+}
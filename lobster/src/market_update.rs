@@ -1,8 +1,8 @@
-use crate::{Balance, Order, OrderId, Price};
+use crate::{Balance, CancelReason, ClientOrderId, Fill, Order, OrderId, Price, Quantity};
 
-use crate::{MarketId, Tick, Timestamp, UserId};
+use crate::{MarketId, MarketState, Tick, Timestamp, UserId};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MarketUpdate {
     AddOrder {
         timestamp: Timestamp,
@@ -10,6 +10,11 @@ pub enum MarketUpdate {
         market: MarketId,
         user: UserId,
         order: Order,
+        /// The trades `order` made against resting orders, in the order they happened.
+        fills: Vec<Fill>,
+        /// The user's resting orders reduced by self-trade prevention, applied
+        /// before `order` is matched. `quantity` is the amount taken off each.
+        cancelled: Vec<Order>,
     },
     RemoveOrder {
         timestamp: Timestamp,
@@ -18,6 +23,25 @@ pub enum MarketUpdate {
         user: UserId,
         /// The id of the order to remove
         id: OrderId,
+        /// The id the user gave the order.
+        client_order_id: Option<ClientOrderId>,
+        reason: CancelReason,
+    },
+    /// A resting order's price and/or quantity was changed.
+    ///
+    /// `order` carries the new price and remaining quantity. A quantity decrease
+    /// at the same price keeps queue priority; anything else requeues the order
+    /// and may trade like a new order.
+    AmendOrder {
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+        user: UserId,
+        order: Order,
+        /// The trades the requeued order made, as for `AddOrder`.
+        fills: Vec<Fill>,
+        /// The user's resting orders reduced by self-trade prevention, as for `AddOrder`.
+        cancelled: Vec<Order>,
     },
     ResolveMarket {
         timestamp: Timestamp,
@@ -26,16 +50,149 @@ pub enum MarketUpdate {
         /// The price the market was resolved to.
         price: Price,
     },
+    /// A market was cancelled. Its orders were removed and every user was
+    /// refunded their cost basis in it.
+    VoidMarket {
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+    },
+    /// A market was opened, halted or closed.
+    SetMarketState {
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+        state: MarketState,
+    },
     /// A new event was added.
     AddMarket {
         timestamp: Timestamp,
         tick: Tick,
         market: MarketId,
     },
+    /// Markets were grouped into a mutually exclusive event.
+    AddExclusiveEvent {
+        timestamp: Timestamp,
+        markets: Vec<MarketId>,
+    },
     Deposit {
         timestamp: Timestamp,
         user: UserId,
         amount: Balance,
     },
+    Withdrawal {
+        timestamp: Timestamp,
+        user: UserId,
+        amount: Balance,
+    },
+    /// An admin corrected a user's balance.
+    Adjustment {
+        timestamp: Timestamp,
+        user: UserId,
+        /// Negative if the balance was reduced.
+        amount: Balance,
+        /// Why the balance was adjusted.
+        memo: String,
+    },
 }
 
+
+impl MarketUpdate {
+    #[must_use]
+    pub const fn buy(
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+        user: UserId,
+        id: OrderId,
+        quantity: Quantity,
+        price: Price,
+    ) -> Self {
+        Self::AddOrder {
+            timestamp,
+            tick,
+            market,
+            user,
+            order: Order::buy(id, quantity, price),
+            fills: Vec::new(),
+            cancelled: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn sell(
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+        user: UserId,
+        id: OrderId,
+        quantity: Quantity,
+        price: Price,
+    ) -> Self {
+        Self::AddOrder {
+            timestamp,
+            tick,
+            market,
+            user,
+            order: Order::sell(id, quantity, price),
+            fills: Vec::new(),
+            cancelled: Vec::new(),
+        }
+    }
+
+    /// Sets the fills of an `AddOrder` or `AmendOrder` update.
+    #[must_use]
+    pub fn with_fills(mut self, trades: Vec<Fill>) -> Self {
+        if let Self::AddOrder { fills, .. } | Self::AmendOrder { fills, .. } = &mut self {
+            *fills = trades;
+        }
+        self
+    }
+
+    #[must_use]
+    pub const fn remove(
+        timestamp: Timestamp,
+        tick: Tick,
+        market: MarketId,
+        user: UserId,
+        id: OrderId,
+    ) -> Self {
+        Self::RemoveOrder {
+            timestamp,
+            tick,
+            market,
+            user,
+            id,
+            client_order_id: None,
+            reason: CancelReason::Requested,
+        }
+    }
+
+    #[must_use]
+    pub const fn resolve(timestamp: Timestamp, tick: Tick, market: MarketId, price: Price) -> Self {
+        Self::ResolveMarket {
+            timestamp,
+            tick,
+            market,
+            price,
+        }
+    }
+
+    /// Returns the market and tick of an update to a single market.
+    #[must_use]
+    pub const fn market_tick(&self) -> Option<(MarketId, Tick)> {
+        match *self {
+            Self::AddOrder { market, tick, .. }
+            | Self::RemoveOrder { market, tick, .. }
+            | Self::AmendOrder { market, tick, .. }
+            | Self::ResolveMarket { market, tick, .. }
+            | Self::VoidMarket { market, tick, .. }
+            | Self::SetMarketState { market, tick, .. }
+            | Self::AddMarket { market, tick, .. } => Some((market, tick)),
+            Self::AddExclusiveEvent { .. }
+            | Self::Deposit { .. }
+            | Self::Withdrawal { .. }
+            | Self::Adjustment { .. } => None,
+        }
+    }
+}
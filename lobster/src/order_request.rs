@@ -1,5 +1,5 @@
-use crate::MarketId;
-use crate::{Price, Quantity, Side};
+use crate::{MarketId, Timestamp};
+use crate::{ClientOrderId, Price, Quantity, SelfTradePrevention, Side};
 
 /// Time in force for the order.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,6 +12,22 @@ pub enum TimeInForce {
     /// Post-only. Order is not added to the book if it is marketable.
     /// Implies it is also GTC.
     POST,
+    /// Fill or kill. Order is rejected unless it can be filled in full immediately.
+    FOK,
+    /// All or none. Order only trades for its whole remaining quantity.
+    /// Implies it is also GTC.
+    AON,
+    /// Good till time. Order is cancelled at `expires_at` if it is still resting.
+    GTT,
+}
+
+impl TimeInForce {
+    /// Returns `true` if what's left of the order after matching is cancelled
+    /// instead of resting.
+    #[must_use]
+    pub const fn is_immediate(self) -> bool {
+        matches!(self, Self::IOC | Self::FOK)
+    }
 }
 
 /// Request for a new order.
@@ -27,6 +43,13 @@ pub struct OrderRequest {
     pub side: Side,
     /// Order type.
     pub tif: TimeInForce,
+    /// What to do if the order would trade against the user's own resting orders.
+    /// Self-trades are allowed if `None`.
+    pub stp: Option<SelfTradePrevention>,
+    /// When a GTT order expires. Must be `None` for other orders.
+    pub expires_at: Option<Timestamp>,
+    /// An id for the order, unique among the user's resting orders.
+    pub client_order_id: Option<ClientOrderId>,
 }
 
 impl OrderRequest {
@@ -45,6 +68,9 @@ impl OrderRequest {
             price,
             side,
             tif,
+            stp: None,
+            expires_at: None,
+            client_order_id: None,
         }
     }
 
@@ -56,6 +82,9 @@ impl OrderRequest {
             price,
             side: Side::Buy,
             tif,
+            stp: None,
+            expires_at: None,
+            client_order_id: None,
         }
     }
 
@@ -67,6 +96,36 @@ impl OrderRequest {
             price,
             side: Side::Sell,
             tif,
+            stp: None,
+            expires_at: None,
+            client_order_id: None,
+        }
+    }
+
+    /// Sets when a GTT order expires.
+    #[must_use]
+    pub const fn with_expiry(self, expires_at: Timestamp) -> Self {
+        Self {
+            expires_at: Some(expires_at),
+            ..self
+        }
+    }
+
+    /// Sets the id the user gives the order.
+    #[must_use]
+    pub const fn with_client_order_id(self, client_order_id: ClientOrderId) -> Self {
+        Self {
+            client_order_id: Some(client_order_id),
+            ..self
+        }
+    }
+
+    /// Sets the self-trade prevention mode of the order.
+    #[must_use]
+    pub const fn with_stp(self, stp: SelfTradePrevention) -> Self {
+        Self {
+            stp: Some(stp),
+            ..self
         }
     }
 }
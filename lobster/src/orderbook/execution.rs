@@ -0,0 +1,14 @@
+use super::{Fill, Order, Quantity};
+
+/// The result of adding an order to the book.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Execution {
+    /// Trades against resting orders, in the order they happened.
+    pub fills: Vec<Fill>,
+    /// Resting orders of the same user reduced by self-trade prevention.
+    /// `quantity` is the amount taken off the order, which is removed from
+    /// the book if nothing is left.
+    pub cancelled: Vec<Order>,
+    /// The quantity of the order left resting on the book.
+    pub remaining: Quantity,
+}
@@ -1,9 +1,12 @@
 use super::{OrderId, Price, Quantity};
+use crate::UserId;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Fill {
     /// The order id of the maker order.
     pub id: OrderId,
+    /// The user that placed the maker order.
+    pub user: UserId,
     /// The number of contracts matched.
     pub quantity: Quantity,
     /// The price the order was matched at.
@@ -15,9 +18,16 @@ pub struct Fill {
 impl Fill {
     /// Constructs a new fill.
     #[must_use]
-    pub const fn new(id: OrderId, quantity: Quantity, price: Price, done: bool) -> Self {
+    pub const fn new(
+        id: OrderId,
+        user: UserId,
+        quantity: Quantity,
+        price: Price,
+        done: bool,
+    ) -> Self {
         Self {
             id,
+            user,
             quantity,
             price,
             done,
@@ -1,35 +1,66 @@
 #![allow(clippy::arithmetic_side_effects)]
 
+mod execution;
 mod fill;
 mod order;
+mod price_level;
+mod self_trade;
 mod side;
 
-use std::cmp::Reverse;
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::ops::Bound;
 
-pub use self::{fill::Fill, order::Order, side::Side};
+use crate::UserId;
+
+use self::price_level::PriceLevel;
+pub use self::{
+    execution::Execution, fill::Fill, order::Order, self_trade::SelfTradePrevention, side::Side,
+};
 
 /// Globally unique order id.
 pub type OrderId = i64;
 
+/// An id a user gives their order, unique among their resting orders.
+pub type ClientOrderId = i64;
+
 /// Represents a number of contracts.
 pub type Quantity = u32;
 
 /// Price in basis points.
 pub type Price = u16;
 
+/// A resting order and the sequence number of its entry in the price level queue.
+#[derive(Debug, Clone, Copy)]
+struct Resting {
+    order: Order,
+    /// The user that placed the order.
+    owner: UserId,
+    /// Self-trade prevention to apply if the order is requeued by an amend.
+    stp: Option<SelfTradePrevention>,
+    seq: u64,
+}
+
+/// Price-time priority order book.
+///
+/// Orders are queued per price level and indexed by id, so cancels don't need to
+/// search the book and matching only touches the levels it trades through.
 #[derive(Default, Debug, Clone)]
 pub struct OrderBook {
-    /// Bids, sorted by price ascending
-    bids: Vec<Order>,
-    /// Asks, sorted by price descending
-    asks: Vec<Order>,
+    /// All resting orders, by id.
+    orders: HashMap<OrderId, Resting>,
+    /// Bid levels, keyed by price.
+    bids: BTreeMap<Price, PriceLevel>,
+    /// Ask levels, keyed by price.
+    asks: BTreeMap<Price, PriceLevel>,
+    /// Sequence number to assign to the next queued order.
+    next_seq: u64,
 }
 
-impl FromIterator<Order> for OrderBook {
-    fn from_iter<I: IntoIterator<Item = Order>>(iter: I) -> Self {
+impl FromIterator<(UserId, Order)> for OrderBook {
+    fn from_iter<I: IntoIterator<Item = (UserId, Order)>>(iter: I) -> Self {
         let mut book = Self::default();
-        for order in iter {
-            assert!(book.add(order).is_empty());
+        for (owner, order) in iter {
+            assert!(book.add(order, owner, None).fills.is_empty());
         }
         book
     }
@@ -39,7 +70,7 @@ impl OrderBook {
     /// Returns the number of open orders in the book.
     #[must_use]
     pub fn len(&self) -> usize {
-        self.bids.len() + self.asks.len()
+        self.orders.len()
     }
 
     /// Returns `true` if the book contains no open orders.
@@ -50,12 +81,19 @@ impl OrderBook {
 
     /// Returns an iterator over the bids from best to worst.
     pub fn bids(&self) -> impl Iterator<Item = Order> + '_ {
-        self.bids.iter().rev().copied()
+        self.bids
+            .values()
+            .rev()
+            .flat_map(|level| level.iter(&self.orders))
+            .map(|resting| resting.order)
     }
 
     /// Returns an iterator over the asks from best to worst.
     pub fn asks(&self) -> impl Iterator<Item = Order> + '_ {
-        self.asks.iter().rev().copied()
+        self.asks
+            .values()
+            .flat_map(|level| level.iter(&self.orders))
+            .map(|resting| resting.order)
     }
 
     /// Returns the best bid.
@@ -70,107 +108,319 @@ impl OrderBook {
         self.asks().next()
     }
 
-    /// Adds an order to the order book. Returns a list of fills if the order was marketable.
-    pub fn add(&mut self, order: Order) -> Vec<Fill> {
-        match order.side {
-            Side::Buy => self.buy(order.id, order.quantity, order.price),
-            Side::Sell => self.sell(order.id, order.quantity, order.price),
-        }
+    /// Returns a resting order by id.
+    #[must_use]
+    pub fn get(&self, id: OrderId) -> Option<Order> {
+        self.orders.get(&id).map(|resting| resting.order)
     }
 
-    /// Removes an order by id.
-    pub fn remove(&mut self, id: OrderId) -> Option<Order> {
-        if let Some(i) = self.bids.iter().position(|order| order.id == id) {
-            return Some(self.bids.remove(i));
-        }
-        if let Some(i) = self.asks.iter().position(|order| order.id == id) {
-            return Some(self.asks.remove(i));
-        }
-        None
+    /// Returns the resting orders with their owner and self-trade prevention,
+    /// in the order they were queued. Adding them to an empty book in this
+    /// order restores the same priority.
+    pub(crate) fn queued(&self) -> Vec<(UserId, Order, Option<SelfTradePrevention>)> {
+        let mut resting: Vec<&Resting> = self.orders.values().collect();
+        resting.sort_unstable_by_key(|resting| resting.seq);
+        resting
+            .into_iter()
+            .map(|resting| (resting.owner, resting.order, resting.stp))
+            .collect()
     }
 
-    fn buy(&mut self, id: OrderId, mut quantity: Quantity, price: Price) -> Vec<Fill> {
-        let mut fills = Vec::new();
-        let mut i = 0;
-        for order in self
-            .asks
-            .iter_mut()
-            .rev()
-            .take_while(|order| order.price <= price)
-        {
-            if quantity >= order.quantity {
-                fills.push(Fill::new(order.id, order.quantity, order.price, true));
-                quantity -= order.quantity;
-                i += 1;
-            } else {
-                fills.push(Fill::new(order.id, quantity, order.price, false));
-                order.quantity -= quantity;
-                quantity = 0;
+    /// Returns the quantity of an order placed by `owner` that would trade if
+    /// it were added now, without changing the book.
+    ///
+    /// Time: O(k + m) where k is the number of levels and m the number of orders
+    /// looked at.
+    #[must_use]
+    pub fn fillable(
+        &self,
+        order: Order,
+        owner: UserId,
+        stp: Option<SelfTradePrevention>,
+    ) -> Quantity {
+        let levels: Box<dyn Iterator<Item = &PriceLevel>> = match order.side {
+            Side::Buy => Box::new(self.asks.range(..=order.price).map(|(_, level)| level)),
+            Side::Sell => Box::new(self.bids.range(order.price..).rev().map(|(_, level)| level)),
+        };
+        let resting = levels.flat_map(|level| level.iter(&self.orders));
+
+        let mut quantity = order.quantity;
+        let mut filled = 0;
+        for resting in resting {
+            if quantity == 0 {
                 break;
             }
+            if let Some(stp) = stp.filter(|_| resting.owner == owner) {
+                match stp {
+                    SelfTradePrevention::CancelOldest => continue,
+                    SelfTradePrevention::CancelNewest | SelfTradePrevention::CancelBoth => break,
+                    SelfTradePrevention::DecrementAndCancel => {
+                        quantity -= quantity.min(resting.order.quantity);
+                        continue;
+                    }
+                }
+            }
+            if resting.order.all_or_none && quantity < resting.order.quantity {
+                continue;
+            }
+            let traded = quantity.min(resting.order.quantity);
+            filled += traded;
+            quantity -= traded;
         }
-        self.asks.drain(self.asks.len() - i..);
-        if quantity > 0 {
-            self.bids.insert(
-                0,
+        filled
+    }
+
+    /// Adds an order placed by `owner` to the order book. Returns the fills if
+    /// the order was marketable, and any resting orders of the same owner that
+    /// were cancelled by `stp` instead of being traded against.
+    ///
+    /// An all-or-none order that can't be filled in full rests without trading.
+    ///
+    /// Time: O(k + m) where k is the number of levels traded through and m the
+    /// number of orders matched.
+    pub fn add(
+        &mut self,
+        order: Order,
+        owner: UserId,
+        stp: Option<SelfTradePrevention>,
+    ) -> Execution {
+        let execution =
+            if order.all_or_none && self.fillable(order, owner, stp) < order.quantity {
+                Execution {
+                    remaining: order.quantity,
+                    ..Execution::default()
+                }
+            } else {
+                self.match_order(order, owner, stp)
+            };
+        if execution.remaining > 0 {
+            self.insert(
                 Order {
-                    id,
-                    quantity,
-                    price,
-                    side: Side::Buy,
+                    quantity: execution.remaining,
+                    ..order
                 },
+                owner,
+                stp,
             );
-            self.bids.sort_by_key(|order| order.price);
         }
-        fills
+        execution
     }
 
-    fn sell(&mut self, id: OrderId, mut quantity: Quantity, price: Price) -> Vec<Fill> {
-        let mut fills = Vec::new();
-        let mut i = 0;
-        for order in self
-            .bids
-            .iter_mut()
-            .rev()
-            .take_while(|order| order.price >= price)
+    /// Removes an order by id.
+    ///
+    /// Time: O(1), plus O(log l) in the number of levels if the level is emptied.
+    pub fn remove(&mut self, id: OrderId) -> Option<Order> {
+        let Resting { order, .. } = self.orders.remove(&id)?;
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        if let btree_map::Entry::Occupied(mut entry) = levels.entry(order.price) {
+            entry.get_mut().cancel(&self.orders);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+        Some(order)
+    }
+
+    /// Changes the price and quantity of a resting order.
+    ///
+    /// A quantity decrease at the same price keeps the order's place in the queue.
+    /// Anything else removes the order and adds it again, which may trade.
+    /// The order keeps whether it is all-or-none and when it expires.
+    /// Returns `None` if the order is not in the book.
+    pub fn amend(&mut self, order: Order) -> Option<Execution> {
+        let resting = self.orders.get_mut(&order.id)?;
+        if order.quantity == 0 {
+            self.remove(order.id);
+            return Some(Execution::default());
+        }
+        if resting.order.price == order.price
+            && resting.order.side == order.side
+            && order.quantity <= resting.order.quantity
         {
-            if quantity >= order.quantity {
-                fills.push(Fill::new(order.id, order.quantity, order.price, true));
-                quantity -= order.quantity;
-                i += 1;
-            } else {
-                fills.push(Fill::new(order.id, quantity, order.price, false));
-                order.quantity -= quantity;
-                quantity = 0;
+            resting.order.quantity = order.quantity;
+            return Some(Execution {
+                remaining: order.quantity,
+                ..Execution::default()
+            });
+        }
+        let Resting { owner, stp, .. } = *resting;
+        let order = Order {
+            quantity: order.quantity,
+            price: order.price,
+            side: order.side,
+            ..resting.order
+        };
+        self.remove(order.id);
+        Some(self.add(order, owner, stp))
+    }
+
+    /// Takes `quantity` off a resting order, keeping its place in the queue.
+    /// The order is removed if nothing is left. Returns what is left of the order.
+    pub fn reduce(&mut self, id: OrderId, quantity: Quantity) -> Option<Order> {
+        let mut order = self.get(id)?;
+        order.quantity = order.quantity.saturating_sub(quantity);
+        self.amend(order);
+        Some(order)
+    }
+
+    /// Queues an order at the back of its price level without matching it.
+    fn insert(&mut self, order: Order, owner: UserId, stp: Option<SelfTradePrevention>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(order.price).or_default().push(order.id, seq);
+        self.orders.insert(
+            order.id,
+            Resting {
+                order,
+                owner,
+                stp,
+                seq,
+            },
+        );
+    }
+
+    /// Matches an incoming order against the opposite side of the book.
+    /// Returns the fills, self-trade cancellations and the quantity left over.
+    fn match_order(
+        &mut self,
+        order: Order,
+        owner: UserId,
+        stp: Option<SelfTradePrevention>,
+    ) -> Execution {
+        let mut fills = Vec::new();
+        let mut cancelled = Vec::new();
+        let mut quantity = order.quantity;
+        // the last level matched against, which may still hold all-or-none
+        // orders that were passed over
+        let mut last = None;
+        while quantity > 0 {
+            let after = last.map_or(Bound::Unbounded, Bound::Excluded);
+            let price = match order.side {
+                Side::Buy => self.asks.range((after, Bound::Unbounded)).next(),
+                Side::Sell => self.bids.range((Bound::Unbounded, after)).next_back(),
+            };
+            let Some((&price, _)) = price else {
                 break;
+            };
+            let is_marketable = match order.side {
+                Side::Buy => price <= order.price,
+                Side::Sell => price >= order.price,
+            };
+            if !is_marketable {
+                break;
+            }
+            last = Some(price);
+
+            let levels = match order.side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let level = levels.get_mut(&price).expect("Invariant");
+            // the last all-or-none order passed over for being too big to fill
+            let mut skipped = None;
+            while quantity > 0 {
+                let next = match skipped {
+                    None => level.front(&self.orders),
+                    Some(seq) => level.next_after(&self.orders, seq),
+                };
+                let Some(id) = next else {
+                    break;
+                };
+                let resting = self.orders.get_mut(&id).expect("Invariant");
+                if let Some(stp) = stp.filter(|_| resting.owner == owner) {
+                    let overlap = quantity.min(resting.order.quantity);
+                    let (maker, taker) = match stp {
+                        SelfTradePrevention::CancelNewest => (0, quantity),
+                        SelfTradePrevention::CancelOldest => (resting.order.quantity, 0),
+                        SelfTradePrevention::CancelBoth => (resting.order.quantity, quantity),
+                        SelfTradePrevention::DecrementAndCancel => (overlap, overlap),
+                    };
+                    quantity -= taker;
+                    if maker == 0 {
+                        continue;
+                    }
+                    cancelled.push(Order {
+                        quantity: maker,
+                        ..resting.order
+                    });
+                    if maker == resting.order.quantity {
+                        take(level, &mut self.orders, id, skipped.is_none());
+                    } else {
+                        resting.order.quantity -= maker;
+                    }
+                    continue;
+                }
+                if resting.order.all_or_none && quantity < resting.order.quantity {
+                    skipped = Some(resting.seq);
+                    continue;
+                }
+                let traded = quantity.min(resting.order.quantity);
+                let done = traded == resting.order.quantity;
+                fills.push(Fill::new(id, resting.owner, traded, price, done));
+                quantity -= traded;
+                if done {
+                    take(level, &mut self.orders, id, skipped.is_none());
+                } else {
+                    resting.order.quantity -= traded;
+                }
+            }
+            if level.is_empty() {
+                levels.remove(&price);
             }
         }
-        self.bids.drain(self.bids.len() - i..);
-        if quantity > 0 {
-            self.asks.insert(
-                0,
-                Order {
-                    id,
-                    quantity,
-                    price,
-                    side: Side::Sell,
-                },
-            );
-            self.asks.sort_by_key(|order| Reverse(order.price));
+        Execution {
+            fills,
+            cancelled,
+            remaining: quantity,
         }
-        fills
+    }
+}
+
+/// Takes a matched order off its level. Orders behind an all-or-none order that
+/// was passed over are not at the front of the queue.
+fn take(
+    level: &mut PriceLevel,
+    orders: &mut HashMap<OrderId, Resting>,
+    id: OrderId,
+    is_front: bool,
+) {
+    orders.remove(&id);
+    if is_front {
+        level.pop_front();
+    } else {
+        level.cancel(orders);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Fill, Order, OrderBook, Price, Quantity, Side};
+    use super::{Execution, Fill, Order, OrderBook, Price, Quantity, SelfTradePrevention};
+    use crate::UserId;
+
+    const ALICE: UserId = 1;
+    const BOB: UserId = 2;
+
+    /// Adds an order without self-trade prevention and returns its fills.
+    fn add(book: &mut OrderBook, order: Order) -> Vec<Fill> {
+        book.add(order, ALICE, None).fills
+    }
+
+    fn amend(book: &mut OrderBook, order: Order) -> Option<Vec<Fill>> {
+        book.amend(order).map(|execution| execution.fills)
+    }
 
     #[test]
     fn add_then_remove() {
         let mut book = OrderBook::default();
         let id = 1;
-        book.add(Order::buy(id, 1, 2));
+        add(&mut book, Order::buy(id, 1, 2));
         assert_eq!(book.len(), 1);
         assert!(book.remove(id).is_some());
         assert_eq!(book.len(), 0);
@@ -180,123 +430,403 @@ mod tests {
     #[test]
     fn multiple_fills_with_cancel() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 2, 5));
-        book.add(Order::sell(1, 3, 6));
-        book.add(Order::sell(2, 4, 7));
+        add(&mut book, Order::sell(0, 2, 5));
+        add(&mut book, Order::sell(1, 3, 6));
+        add(&mut book, Order::sell(2, 4, 7));
         book.remove(0);
-        let fills = book.add(Order::buy(3, 6, 6));
-        assert_eq!(fills, vec![Fill::new(1, 3, 6, true)])
+        let fills = add(&mut book, Order::buy(3, 6, 6));
+        assert_eq!(fills, vec![Fill::new(1, ALICE, 3, 6, true)])
     }
 
     #[test]
     fn fire_for_order_that_was_filled_exactly() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 2, 23));
-        let fills = book.add(Order::buy(1, 2, 23));
-        assert_eq!(fills, vec![Fill::new(0, 2, 23, true)]);
-        let fills = book.add(Order::buy(2, 2, 23));
+        add(&mut book, Order::sell(0, 2, 23));
+        let fills = add(&mut book, Order::buy(1, 2, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 2, 23, true)]);
+        let fills = add(&mut book, Order::buy(2, 2, 23));
         assert_eq!(fills, vec![]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, 2, 23));
-        let fills = book.add(Order::sell(1, 2, 23));
-        assert_eq!(fills, vec![Fill::new(0, 2, 23, true)]);
-        let fills = book.add(Order::sell(2, 2, 23));
+        add(&mut book, Order::buy(0, 2, 23));
+        let fills = add(&mut book, Order::sell(1, 2, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 2, 23, true)]);
+        let fills = add(&mut book, Order::sell(2, 2, 23));
         assert_eq!(fills, vec![]);
     }
 
     #[test]
     fn fire_for_order_that_was_filled_excessively() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 1, 23));
-        let fills = book.add(Order::buy(1, 2, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, true)]);
-        let fills = book.add(Order::buy(2, 1, 23));
+        add(&mut book, Order::sell(0, 1, 23));
+        let fills = add(&mut book, Order::buy(1, 2, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, true)]);
+        let fills = add(&mut book, Order::buy(2, 1, 23));
         assert_eq!(fills, vec![]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, 1, 23));
-        let fills = book.add(Order::sell(1, 2, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, true)]);
-        let fills = book.add(Order::sell(2, 1, 23));
+        add(&mut book, Order::buy(0, 1, 23));
+        let fills = add(&mut book, Order::sell(1, 2, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, true)]);
+        let fills = add(&mut book, Order::sell(2, 1, 23));
         assert_eq!(fills, vec![]);
     }
 
     #[test]
     fn trade_twice_with_resting_order() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 2, 23));
-        let fills = book.add(Order::buy(1, 1, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, false)]);
-        let fills = book.add(Order::buy(2, 1, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, true)]);
+        add(&mut book, Order::sell(0, 2, 23));
+        let fills = add(&mut book, Order::buy(1, 1, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, false)]);
+        let fills = add(&mut book, Order::buy(2, 1, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, true)]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, 2, 23));
-        let fills = book.add(Order::sell(1, 1, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, false)]);
-        let fills = book.add(Order::sell(2, 1, 23));
-        assert_eq!(fills, vec![Fill::new(0, 1, 23, true)]);
+        add(&mut book, Order::buy(0, 2, 23));
+        let fills = add(&mut book, Order::sell(1, 1, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, false)]);
+        let fills = add(&mut book, Order::sell(2, 1, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, 23, true)]);
     }
 
     #[test]
     fn test_quantity_limits() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, Quantity::MAX, 23));
-        let fills = book.add(Order::buy(1, Quantity::MAX, 23));
-        assert_eq!(fills, vec![Fill::new(0, Quantity::MAX, 23, true)]);
+        add(&mut book, Order::sell(0, Quantity::MAX, 23));
+        let fills = add(&mut book, Order::buy(1, Quantity::MAX, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, Quantity::MAX, 23, true)]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, Quantity::MAX, 23));
-        let fills = book.add(Order::sell(1, Quantity::MAX, 23));
-        assert_eq!(fills, vec![Fill::new(0, Quantity::MAX, 23, true)]);
+        add(&mut book, Order::buy(0, Quantity::MAX, 23));
+        let fills = add(&mut book, Order::sell(1, Quantity::MAX, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, Quantity::MAX, 23, true)]);
     }
 
     #[test]
     fn trade_twice_with_resting_order_price_limits() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 2, Price::MIN));
-        let fills = book.add(Order::buy(1, 1, Price::MIN));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MIN, false)]);
-        let fills = book.add(Order::buy(2, 1, Price::MIN));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MIN, true)]);
+        add(&mut book, Order::sell(0, 2, Price::MIN));
+        let fills = add(&mut book, Order::buy(1, 1, Price::MIN));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MIN, false)]);
+        let fills = add(&mut book, Order::buy(2, 1, Price::MIN));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MIN, true)]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, 2, Price::MIN));
-        let fills = book.add(Order::sell(1, 1, Price::MIN));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MIN, false)]);
-        let fills = book.add(Order::sell(2, 1, Price::MIN));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MIN, true)]);
+        add(&mut book, Order::buy(0, 2, Price::MIN));
+        let fills = add(&mut book, Order::sell(1, 1, Price::MIN));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MIN, false)]);
+        let fills = add(&mut book, Order::sell(2, 1, Price::MIN));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MIN, true)]);
 
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 2, Price::MAX));
-        let fills = book.add(Order::buy(1, 1, Price::MAX));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MAX, false)]);
-        let fills = book.add(Order::buy(2, 1, Price::MAX));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MAX, true)]);
+        add(&mut book, Order::sell(0, 2, Price::MAX));
+        let fills = add(&mut book, Order::buy(1, 1, Price::MAX));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MAX, false)]);
+        let fills = add(&mut book, Order::buy(2, 1, Price::MAX));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MAX, true)]);
 
         let mut book = OrderBook::default();
-        book.add(Order::buy(0, 2, Price::MAX));
-        let fills = book.add(Order::sell(1, 1, Price::MAX));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MAX, false)]);
-        let fills = book.add(Order::sell(2, 1, Price::MAX));
-        assert_eq!(fills, vec![Fill::new(0, 1, Price::MAX, true)]);
+        add(&mut book, Order::buy(0, 2, Price::MAX));
+        let fills = add(&mut book, Order::sell(1, 1, Price::MAX));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MAX, false)]);
+        let fills = add(&mut book, Order::sell(2, 1, Price::MAX));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 1, Price::MAX, true)]);
     }
 
     #[test]
     fn test_queue_priority() {
         let mut book = OrderBook::default();
-        book.add(Order::sell(0, 1, 23));
-        book.add(Order::sell(1, 1, 23));
-        book.add(Order::sell(2, 1, 23));
-        let fills = book.add(Order::buy(3, 3, 23));
+        add(&mut book, Order::sell(0, 1, 23));
+        add(&mut book, Order::sell(1, 1, 23));
+        add(&mut book, Order::sell(2, 1, 23));
+        let fills = add(&mut book, Order::buy(3, 3, 23));
         assert_eq!(
             fills,
             vec![
-                Fill::new(0, 1, 23, true),
-                Fill::new(1, 1, 23, true),
-                Fill::new(2, 1, 23, true)
+                Fill::new(0, ALICE, 1, 23, true),
+                Fill::new(1, ALICE, 1, 23, true),
+                Fill::new(2, ALICE, 1, 23, true)
             ]
         );
     }
+
+    #[test]
+    fn test_price_time_priority_across_levels() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 1, 25));
+        add(&mut book, Order::sell(1, 1, 23));
+        add(&mut book, Order::sell(2, 1, 24));
+        add(&mut book, Order::sell(3, 1, 23));
+        let fills = add(&mut book, Order::buy(4, 3, 24));
+        assert_eq!(
+            fills,
+            vec![
+                Fill::new(1, ALICE, 1, 23, true),
+                Fill::new(3, ALICE, 1, 23, true),
+                Fill::new(2, ALICE, 1, 24, true)
+            ]
+        );
+        assert_eq!(book.best_ask(), Some(Order::sell(0, 1, 25)));
+        assert_eq!(book.best_bid(), None);
+    }
+
+    #[test]
+    fn test_iterators_best_to_worst() {
+        let book: OrderBook = [
+            Order::buy(0, 1, 20),
+            Order::buy(1, 2, 22),
+            Order::buy(2, 3, 20),
+            Order::sell(3, 1, 30),
+            Order::sell(4, 2, 28),
+            Order::sell(5, 3, 30),
+        ]
+        .into_iter()
+        .map(|order| (ALICE, order))
+        .collect();
+
+        let bids: Vec<_> = book.bids().map(|order| order.id).collect();
+        let asks: Vec<_> = book.asks().map(|order| order.id).collect();
+        assert_eq!(bids, vec![1, 0, 2]);
+        assert_eq!(asks, vec![4, 3, 5]);
+        assert_eq!(book.len(), 6);
+    }
+
+    #[test]
+    fn test_cancel_head_of_level() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::buy(0, 1, 20));
+        add(&mut book, Order::buy(1, 2, 20));
+        assert_eq!(book.remove(0), Some(Order::buy(0, 1, 20)));
+        assert_eq!(book.best_bid(), Some(Order::buy(1, 2, 20)));
+
+        let fills = add(&mut book, Order::sell(2, 5, 20));
+        assert_eq!(fills, vec![Fill::new(1, ALICE, 2, 20, true)]);
+        assert_eq!(book.best_ask(), Some(Order::sell(2, 3, 20)));
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn test_amend_decrease_keeps_priority() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 5, 23));
+        add(&mut book, Order::sell(1, 5, 23));
+        assert_eq!(amend(&mut book, Order::sell(0, 2, 23)), Some(vec![]));
+
+        let fills = add(&mut book, Order::buy(2, 3, 23));
+        assert_eq!(
+            fills,
+            vec![Fill::new(0, ALICE, 2, 23, true), Fill::new(1, ALICE, 1, 23, false)]
+        );
+    }
+
+    #[test]
+    fn test_amend_increase_or_reprice_requeues() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 5, 23));
+        add(&mut book, Order::sell(1, 5, 23));
+        assert_eq!(amend(&mut book, Order::sell(0, 6, 23)), Some(vec![]));
+        let asks: Vec<_> = book.asks().map(|order| order.id).collect();
+        assert_eq!(asks, vec![1, 0]);
+
+        assert_eq!(amend(&mut book, Order::sell(1, 5, 24)), Some(vec![]));
+        let asks: Vec<_> = book.asks().map(|order| order.id).collect();
+        assert_eq!(asks, vec![0, 1]);
+
+        assert_eq!(amend(&mut book, Order::sell(3, 5, 24)), None);
+    }
+
+    #[test]
+    fn test_amend_to_marketable_price_trades() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::buy(0, 2, 20));
+        add(&mut book, Order::sell(1, 5, 23));
+        let fills = amend(&mut book, Order::sell(1, 5, 20));
+        assert_eq!(fills, Some(vec![Fill::new(0, ALICE, 2, 20, true)]));
+        assert_eq!(book.best_ask(), Some(Order::sell(1, 3, 20)));
+    }
+
+    #[test]
+    fn test_readd_cancelled_id_is_not_duplicated() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 1, 23));
+        add(&mut book, Order::sell(1, 1, 23));
+        book.remove(0);
+        add(&mut book, Order::sell(0, 1, 23));
+
+        let asks: Vec<_> = book.asks().map(|order| order.id).collect();
+        assert_eq!(asks, vec![1, 0]);
+        let fills = add(&mut book, Order::buy(2, 5, 23));
+        assert_eq!(
+            fills,
+            vec![Fill::new(1, ALICE, 1, 23, true), Fill::new(0, ALICE, 1, 23, true)]
+        );
+    }
+
+    #[test]
+    fn test_fillable() {
+        let mut book = OrderBook::default();
+        book.add(Order::sell(0, 2, 23), ALICE, None);
+        book.add(Order::sell(1, 5, 24).with_all_or_none(true), BOB, None);
+        book.add(Order::sell(2, 3, 24), BOB, None);
+
+        assert_eq!(book.fillable(Order::buy(3, 10, 24), BOB, None), 10);
+        assert_eq!(book.fillable(Order::buy(3, 6, 24), BOB, None), 5);
+        assert_eq!(book.fillable(Order::buy(3, 10, 23), BOB, None), 2);
+        assert_eq!(book.fillable(Order::buy(3, 10, 22), BOB, None), 0);
+        let stp = Some(SelfTradePrevention::CancelOldest);
+        assert_eq!(book.fillable(Order::buy(3, 10, 24), ALICE, stp), 8);
+        let stp = Some(SelfTradePrevention::DecrementAndCancel);
+        assert_eq!(book.fillable(Order::buy(3, 10, 24), ALICE, stp), 8);
+        assert_eq!(book.fillable(Order::buy(3, 6, 24), ALICE, stp), 3);
+        let stp = Some(SelfTradePrevention::CancelNewest);
+        assert_eq!(book.fillable(Order::buy(3, 10, 24), ALICE, stp), 0);
+        assert_eq!(book.len(), 3);
+    }
+
+    #[test]
+    fn test_all_or_none_rests_until_filled_in_full() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 2, 23));
+        let order = Order::buy(1, 3, 23).with_all_or_none(true);
+        assert_eq!(
+            book.add(order, BOB, None),
+            Execution {
+                remaining: 3,
+                ..Execution::default()
+            }
+        );
+        assert_eq!(book.best_bid(), Some(order));
+
+        assert_eq!(add(&mut book, Order::sell(2, 2, 22)), vec![]);
+        let fills = add(&mut book, Order::sell(3, 3, 22));
+        assert_eq!(fills, vec![Fill::new(1, BOB, 3, 23, true)]);
+        assert_eq!(book.best_bid(), None);
+    }
+
+    #[test]
+    fn test_all_or_none_is_passed_over() {
+        let mut book = OrderBook::default();
+        add(&mut book, Order::sell(0, 5, 23).with_all_or_none(true));
+        add(&mut book, Order::sell(1, 2, 23));
+        add(&mut book, Order::sell(2, 2, 24));
+        let fills = add(&mut book, Order::buy(3, 3, 24));
+        assert_eq!(
+            fills,
+            vec![Fill::new(1, ALICE, 2, 23, true), Fill::new(2, ALICE, 1, 24, false)]
+        );
+        let asks: Vec<_> = book.asks().map(|order| order.id).collect();
+        assert_eq!(asks, vec![0, 2]);
+
+        // keeps its priority, and stays all-or-none when amended
+        amend(&mut book, Order::sell(0, 6, 23));
+        assert_eq!(book.get(0), Some(Order::sell(0, 6, 23).with_all_or_none(true)));
+        let fills = add(&mut book, Order::buy(4, 6, 23));
+        assert_eq!(fills, vec![Fill::new(0, ALICE, 6, 23, true)]);
+    }
+
+    /// Alice rests 2 @ 23 and Bob rests 2 @ 23 behind her.
+    fn setup_self_trade() -> OrderBook {
+        let mut book = OrderBook::default();
+        book.add(Order::sell(0, 2, 23), ALICE, None);
+        book.add(Order::sell(1, 2, 23), BOB, None);
+        book
+    }
+
+    #[test]
+    fn test_stp_cancel_newest() {
+        let mut book = setup_self_trade();
+        let execution = book.add(
+            Order::buy(2, 3, 23),
+            ALICE,
+            Some(SelfTradePrevention::CancelNewest),
+        );
+        assert_eq!(execution, Execution::default());
+        assert_eq!(book.len(), 2);
+    }
+
+    #[test]
+    fn test_stp_cancel_oldest() {
+        let mut book = setup_self_trade();
+        let execution = book.add(
+            Order::buy(2, 3, 23),
+            ALICE,
+            Some(SelfTradePrevention::CancelOldest),
+        );
+        assert_eq!(
+            execution,
+            Execution {
+                fills: vec![Fill::new(1, BOB, 2, 23, true)],
+                cancelled: vec![Order::sell(0, 2, 23)],
+                remaining: 1,
+            }
+        );
+        assert_eq!(book.best_bid(), Some(Order::buy(2, 1, 23)));
+        assert_eq!(book.best_ask(), None);
+    }
+
+    #[test]
+    fn test_stp_cancel_both() {
+        let mut book = setup_self_trade();
+        let execution = book.add(
+            Order::buy(2, 3, 23),
+            ALICE,
+            Some(SelfTradePrevention::CancelBoth),
+        );
+        assert_eq!(
+            execution,
+            Execution {
+                cancelled: vec![Order::sell(0, 2, 23)],
+                ..Execution::default()
+            }
+        );
+        assert_eq!(book.best_ask(), Some(Order::sell(1, 2, 23)));
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn test_stp_decrement_and_cancel() {
+        let mut book = setup_self_trade();
+        let execution = book.add(
+            Order::buy(2, 3, 23),
+            ALICE,
+            Some(SelfTradePrevention::DecrementAndCancel),
+        );
+        assert_eq!(
+            execution,
+            Execution {
+                fills: vec![Fill::new(1, BOB, 1, 23, false)],
+                cancelled: vec![Order::sell(0, 2, 23)],
+                remaining: 0,
+            }
+        );
+
+        // The smaller taker is cancelled and the maker keeps its priority.
+        book.add(Order::sell(3, 5, 23), ALICE, None);
+        let execution = book.add(
+            Order::buy(4, 2, 23),
+            ALICE,
+            Some(SelfTradePrevention::DecrementAndCancel),
+        );
+        assert_eq!(
+            execution,
+            Execution {
+                fills: vec![Fill::new(1, BOB, 1, 23, true)],
+                cancelled: vec![Order::sell(3, 1, 23)],
+                remaining: 0,
+            }
+        );
+        assert_eq!(book.best_ask(), Some(Order::sell(3, 4, 23)));
+    }
+
+    #[test]
+    fn test_stp_only_applies_to_same_owner() {
+        let mut book = setup_self_trade();
+        let execution = book.add(
+            Order::buy(2, 3, 23),
+            BOB,
+            Some(SelfTradePrevention::CancelNewest),
+        );
+        assert_eq!(execution.fills, vec![Fill::new(0, ALICE, 2, 23, true)]);
+        assert_eq!(execution.remaining, 0);
+    }
 }
@@ -1,4 +1,5 @@
-use super::{OrderId, Price, Quantity, Side};
+use super::{ClientOrderId, OrderId, Price, Quantity, Side};
+use crate::Timestamp;
 
 /// An order in the order book.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -11,6 +12,13 @@ pub struct Order {
     pub price: Price,
     /// The side of this order.
     pub side: Side,
+    /// Only trade for the whole remaining quantity. Incoming orders that can't
+    /// fill it pass over it, and it rests without trading if it can't fill.
+    pub all_or_none: bool,
+    /// When the order is cancelled if it is still resting.
+    pub expires_at: Option<Timestamp>,
+    /// The id the user gave the order.
+    pub client_order_id: Option<ClientOrderId>,
 }
 
 impl Order {
@@ -21,6 +29,9 @@ impl Order {
             quantity,
             price,
             side,
+            all_or_none: false,
+            expires_at: None,
+            client_order_id: None,
         }
     }
 
@@ -31,6 +42,9 @@ impl Order {
             quantity,
             price,
             side: Side::Buy,
+            all_or_none: false,
+            expires_at: None,
+            client_order_id: None,
         }
     }
 
@@ -41,6 +55,33 @@ impl Order {
             quantity,
             price,
             side: Side::Sell,
+            all_or_none: false,
+            expires_at: None,
+            client_order_id: None,
+        }
+    }
+
+    /// Sets whether the order only trades for its whole remaining quantity.
+    #[must_use]
+    pub const fn with_all_or_none(self, all_or_none: bool) -> Self {
+        Self {
+            all_or_none,
+            ..self
+        }
+    }
+
+    /// Sets when the order is cancelled if it is still resting.
+    #[must_use]
+    pub const fn with_expiry(self, expires_at: Option<Timestamp>) -> Self {
+        Self { expires_at, ..self }
+    }
+
+    /// Sets the id the user gave the order.
+    #[must_use]
+    pub const fn with_client_order_id(self, client_order_id: Option<ClientOrderId>) -> Self {
+        Self {
+            client_order_id,
+            ..self
         }
     }
 }
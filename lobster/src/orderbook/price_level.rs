@@ -0,0 +1,81 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{OrderId, Resting};
+
+/// A FIFO queue of orders resting at the same price.
+///
+/// Cancelled orders are not removed from the queue immediately. Their entries
+/// are skipped when they reach the front, and the queue is compacted once
+/// stale entries outnumber live ones.
+#[derive(Default, Debug, Clone)]
+pub struct PriceLevel {
+    /// Order ids and the sequence number they were queued with.
+    queue: VecDeque<(OrderId, u64)>,
+    /// The number of live orders in the queue.
+    count: usize,
+}
+
+fn is_live(orders: &HashMap<OrderId, Resting>, id: OrderId, seq: u64) -> bool {
+    orders.get(&id).is_some_and(|resting| resting.seq == seq)
+}
+
+impl PriceLevel {
+    /// Returns `true` if there are no live orders at this level.
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Appends an order to the back of the queue.
+    pub fn push(&mut self, id: OrderId, seq: u64) {
+        self.queue.push_back((id, seq));
+        self.count += 1;
+    }
+
+    /// Returns the id of the first live order, discarding stale entries in front of it.
+    pub fn front(
+        &mut self,
+        orders: &HashMap<OrderId, Resting>,
+    ) -> Option<OrderId> {
+        while let Some(&(id, seq)) = self.queue.front() {
+            if is_live(orders, id, seq) {
+                return Some(id);
+            }
+            self.queue.pop_front();
+        }
+        None
+    }
+
+    /// Returns the id of the first live order queued after the order with
+    /// sequence number `seq`.
+    pub fn next_after(&self, orders: &HashMap<OrderId, Resting>, seq: u64) -> Option<OrderId> {
+        let start = self.queue.partition_point(|&(_, queued)| queued <= seq);
+        self.queue
+            .range(start..)
+            .find(|&&(id, seq)| is_live(orders, id, seq))
+            .map(|&(id, _)| id)
+    }
+
+    /// Removes the first live order. Must be preceded by a call to `front`.
+    pub fn pop_front(&mut self) {
+        self.queue.pop_front();
+        self.count -= 1;
+    }
+
+    /// Marks an order that was already removed from `orders` as cancelled.
+    pub fn cancel(&mut self, orders: &HashMap<OrderId, Resting>) {
+        self.count -= 1;
+        if self.queue.len() > 2 * self.count + 8 {
+            self.queue.retain(|&(id, seq)| is_live(orders, id, seq));
+        }
+    }
+
+    /// Returns an iterator over the live orders from first to last in the queue.
+    pub fn iter<'a>(
+        &'a self,
+        orders: &'a HashMap<OrderId, Resting>,
+    ) -> impl Iterator<Item = &'a Resting> + 'a {
+        self.queue.iter().filter_map(|&(id, seq)| {
+            orders.get(&id).filter(|resting| resting.seq == seq)
+        })
+    }
+}
@@ -0,0 +1,12 @@
+/// What to do when an order would trade against a resting order of the same user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel the remainder of the incoming order.
+    CancelNewest,
+    /// Cancel the resting order and keep matching.
+    CancelOldest,
+    /// Cancel both the resting order and the remainder of the incoming order.
+    CancelBoth,
+    /// Reduce both orders by the smaller quantity, cancelling whichever has none left.
+    DecrementAndCancel,
+}
@@ -9,6 +9,31 @@ pub enum RejectReason {
     /// Book does not exist or already resolved.
     MarketNotFound,
     InsufficientFunds,
+    /// A withdrawal that isn't positive, or an adjustment of 0.
+    InvalidAmount,
     IOCNotMarketable,
+    /// A fill or kill order that can't be filled in full.
+    FOKNotFillable,
+    /// A GTT order without an expiry in the future, or an expiry on another time in force.
+    InvalidExpiry,
+    /// The user already has a resting order with the same client order id.
+    DuplicateClientOrderId,
     MarketAlreadyExists,
+    /// No markets, a duplicate market, or a market already in a mutually exclusive event.
+    InvalidExclusiveEvent,
+    /// Markets of a mutually exclusive event resolve to 0 until one is left,
+    /// which resolves to `RESOLVE_PRICE`. They can't be voided.
+    InvalidResolution,
+    /// Quantity above the `max_order_quantity` risk limit.
+    MaxOrderQuantityExceeded,
+    /// Quantity times price above the `max_order_notional` risk limit.
+    MaxOrderNotionalExceeded,
+    /// Would take the user's position past the `max_position` risk limit.
+    MaxPositionExceeded,
+    /// The user already has the `max_open_orders` risk limit of resting orders.
+    MaxOpenOrdersExceeded,
+    /// Price further from the last trade or mid than the `price_band` risk limit.
+    PriceOutsideBand,
+    /// The market is halted or closed, so orders can only be cancelled.
+    MarketHalted,
 }
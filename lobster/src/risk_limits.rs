@@ -0,0 +1,50 @@
+use crate::{Balance, Position, Price, Quantity};
+
+/// Pre-trade limits orders are checked against. Unset limits don't apply.
+///
+/// The exchange has default limits, which markets and users can override.
+/// A user's limit takes precedence over the market's, which takes precedence
+/// over the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RiskLimits {
+    /// The largest quantity of a single order.
+    pub max_order_quantity: Option<Quantity>,
+    /// The largest quantity times price of a single order.
+    pub max_order_notional: Option<Balance>,
+    /// The largest absolute position a user can reach in a market,
+    /// counting their resting orders on the same side as filled.
+    pub max_position: Option<Position>,
+    /// The most resting orders a user can have, across all markets.
+    pub max_open_orders: Option<usize>,
+    /// How far an order's price can be from the last trade price, or from the
+    /// mid price if the market hasn't traded.
+    pub price_band: Option<Price>,
+}
+
+impl RiskLimits {
+    /// Returns these limits, with unset ones taken from `fallback`.
+    #[must_use]
+    pub const fn or(self, fallback: Self) -> Self {
+        Self {
+            max_order_quantity: or(self.max_order_quantity, fallback.max_order_quantity),
+            max_order_notional: or(self.max_order_notional, fallback.max_order_notional),
+            max_position: or(self.max_position, fallback.max_position),
+            max_open_orders: or(self.max_open_orders, fallback.max_open_orders),
+            price_band: or(self.price_band, fallback.price_band),
+        }
+    }
+
+    /// Returns `true` if no limits are set.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// `Option::or`, usable in const functions.
+const fn or<T: Copy>(value: Option<T>, fallback: Option<T>) -> Option<T> {
+    match value {
+        Some(value) => Some(value),
+        None => fallback,
+    }
+}
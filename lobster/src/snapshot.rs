@@ -0,0 +1,356 @@
+//! Versioned binary snapshots of an `Exchange`.
+#![allow(clippy::arithmetic_side_effects)]
+
+use crate::{
+    Balance, MarketId, MarketState, Order, OrderId, Position, Price, SelfTradePrevention, Side,
+    Tick, UserId,
+};
+
+/// The snapshot format version. Bump it whenever the encoding changes.
+pub const SNAPSHOT_VERSION: u32 = 7;
+
+const MAGIC: &[u8; 4] = b"LOBS";
+
+/// The state of an exchange after some number of updates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The sequence number of the last update applied to the exchange.
+    pub seq: u64,
+    pub next_order_id: OrderId,
+    /// Every user's balance, sorted by user.
+    pub balances: Vec<(UserId, Balance)>,
+    /// Every position that is non-zero or has a cost basis, with its cost basis,
+    /// sorted by user and market.
+    pub positions: Vec<(UserId, MarketId, Position, Balance)>,
+    /// Every open market, sorted by id.
+    pub books: Vec<BookSnapshot>,
+    /// The unresolved markets of every mutually exclusive event, sorted.
+    pub exclusive_events: Vec<Vec<MarketId>>,
+}
+
+/// The state of one market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub market: MarketId,
+    pub next_tick: Tick,
+    /// The price of the last trade.
+    pub last_price: Option<Price>,
+    pub state: MarketState,
+    /// Resting orders in priority order, with their owner and self-trade prevention.
+    pub orders: Vec<(UserId, Order, Option<SelfTradePrevention>)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SnapshotError {
+    /// Not a snapshot, or holds values the engine can't have produced.
+    InvalidFormat,
+    /// Written with a different `SNAPSHOT_VERSION`.
+    UnsupportedVersion(u32),
+    /// Ends before the snapshot does.
+    Truncated,
+}
+
+impl Snapshot {
+    /// Encodes the snapshot, prefixed with its format version.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Writer(MAGIC.to_vec());
+        out.u32(SNAPSHOT_VERSION);
+        out.u64(self.seq);
+        out.i64(self.next_order_id);
+
+        out.len(self.balances.len());
+        for &(user, balance) in &self.balances {
+            out.u32(user);
+            out.i64(balance);
+        }
+
+        out.len(self.positions.len());
+        for &(user, market, position, cost) in &self.positions {
+            out.u32(user);
+            out.u32(market);
+            out.i32(position);
+            out.i64(cost);
+        }
+
+        out.len(self.books.len());
+        for book in &self.books {
+            out.u32(book.market);
+            out.u32(book.next_tick);
+            // prices are never 0, so 0 means there's no last price
+            out.u16(book.last_price.unwrap_or_default());
+            out.u8(match book.state {
+                MarketState::Open => 0,
+                MarketState::Halted => 1,
+                MarketState::Closed => 2,
+            });
+            out.len(book.orders.len());
+            for &(user, order, stp) in &book.orders {
+                out.u32(user);
+                out.i64(order.id);
+                out.u32(order.quantity);
+                out.u16(order.price);
+                out.u8(u8::from(order.side == Side::Sell));
+                out.u8(u8::from(order.all_or_none));
+                out.u8(u8::from(order.expires_at.is_some()));
+                out.i64(order.expires_at.unwrap_or_default());
+                out.u8(u8::from(order.client_order_id.is_some()));
+                out.i64(order.client_order_id.unwrap_or_default());
+                out.u8(match stp {
+                    None => 0,
+                    Some(SelfTradePrevention::CancelNewest) => 1,
+                    Some(SelfTradePrevention::CancelOldest) => 2,
+                    Some(SelfTradePrevention::CancelBoth) => 3,
+                    Some(SelfTradePrevention::DecrementAndCancel) => 4,
+                });
+            }
+        }
+
+        out.len(self.exclusive_events.len());
+        for markets in &self.exclusive_events {
+            out.len(markets.len());
+            for &market in markets {
+                out.u32(market);
+            }
+        }
+        out.0
+    }
+
+    /// Decodes a snapshot written by `encode`.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Err(SnapshotError::InvalidFormat)` if the bytes are not a snapshot.
+    /// - Returns `Err(SnapshotError::UnsupportedVersion)` if the snapshot has another version.
+    /// - Returns `Err(SnapshotError::Truncated)` if the bytes end early.
+    pub fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut input = Reader(bytes);
+        if input.take::<4>()? != *MAGIC {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        let version = input.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut snapshot = Self {
+            seq: input.u64()?,
+            next_order_id: input.i64()?,
+            ..Self::default()
+        };
+
+        for _ in 0..input.len()? {
+            snapshot.balances.push((input.u32()?, input.i64()?));
+        }
+
+        for _ in 0..input.len()? {
+            snapshot
+                .positions
+                .push((input.u32()?, input.u32()?, input.i32()?, input.i64()?));
+        }
+
+        for _ in 0..input.len()? {
+            let mut book = BookSnapshot {
+                market: input.u32()?,
+                next_tick: input.u32()?,
+                last_price: Some(input.u16()?).filter(|&price| price != 0),
+                state: match input.u8()? {
+                    0 => MarketState::Open,
+                    1 => MarketState::Halted,
+                    2 => MarketState::Closed,
+                    _ => return Err(SnapshotError::InvalidFormat),
+                },
+                orders: Vec::new(),
+            };
+            for _ in 0..input.len()? {
+                let user = input.u32()?;
+                let id = input.i64()?;
+                let quantity = input.u32()?;
+                let price = input.u16()?;
+                let side = match input.u8()? {
+                    0 => Side::Buy,
+                    1 => Side::Sell,
+                    _ => return Err(SnapshotError::InvalidFormat),
+                };
+                let all_or_none = match input.u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(SnapshotError::InvalidFormat),
+                };
+                let has_expiry = match input.u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(SnapshotError::InvalidFormat),
+                };
+                let expires_at = Some(input.i64()?).filter(|_| has_expiry);
+                let has_client_order_id = match input.u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(SnapshotError::InvalidFormat),
+                };
+                let client_order_id = Some(input.i64()?).filter(|_| has_client_order_id);
+                let stp = match input.u8()? {
+                    0 => None,
+                    1 => Some(SelfTradePrevention::CancelNewest),
+                    2 => Some(SelfTradePrevention::CancelOldest),
+                    3 => Some(SelfTradePrevention::CancelBoth),
+                    4 => Some(SelfTradePrevention::DecrementAndCancel),
+                    _ => return Err(SnapshotError::InvalidFormat),
+                };
+                let order = Order::new(id, quantity, price, side)
+                    .with_all_or_none(all_or_none)
+                    .with_expiry(expires_at)
+                    .with_client_order_id(client_order_id);
+                book.orders.push((user, order, stp));
+            }
+            snapshot.books.push(book);
+        }
+
+        for _ in 0..input.len()? {
+            let mut markets = Vec::new();
+            for _ in 0..input.len()? {
+                markets.push(input.u32()?);
+            }
+            snapshot.exclusive_events.push(markets);
+        }
+
+        if !input.0.is_empty() {
+            return Err(SnapshotError::InvalidFormat);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Appends little-endian values.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(u64::try_from(len).expect("Invariant"));
+    }
+}
+
+/// Reads little-endian values.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        if self.0.len() < N {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().expect("Invariant"))
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        self.take().map(u8::from_le_bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, SnapshotError> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        self.take().map(i64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Result<u64, SnapshotError> {
+        self.u64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BookSnapshot, Snapshot, SnapshotError, SNAPSHOT_VERSION};
+    use crate::{MarketState, Order, SelfTradePrevention};
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            seq: 42,
+            next_order_id: 7,
+            balances: vec![(1, 100_000), (2, 0)],
+            positions: vec![(1, 3, -5, 12_000), (2, 3, 0, -500)],
+            books: vec![BookSnapshot {
+                market: 3,
+                next_tick: 9,
+                last_price: Some(5000),
+                state: MarketState::Halted,
+                orders: vec![
+                    (
+                        1,
+                        Order::buy(5, 10, 4000)
+                            .with_all_or_none(true)
+                            .with_client_order_id(Some(42)),
+                        None,
+                    ),
+                    (
+                        2,
+                        Order::sell(6, 1, 6000).with_expiry(Some(1_000)),
+                        Some(SelfTradePrevention::CancelBoth),
+                    ),
+                ],
+            }],
+            exclusive_events: vec![vec![3, 4]],
+        }
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let snapshot = snapshot();
+        assert_eq!(Snapshot::decode(&snapshot.encode()), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_other_versions() {
+        let mut bytes = snapshot().encode();
+        bytes[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Snapshot::decode(&bytes),
+            Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+
+        let bytes = snapshot().encode();
+        assert_eq!(
+            Snapshot::decode(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+        assert_eq!(Snapshot::decode(b"nope"), Err(SnapshotError::InvalidFormat));
+    }
+}
@@ -0,0 +1,2 @@
+-- Exactly one market of a mutually exclusive event resolves to 100%.
+ALTER TABLE event ADD COLUMN mutually_exclusive INTEGER NOT NULL DEFAULT 0 CHECK (mutually_exclusive IN (0, 1));
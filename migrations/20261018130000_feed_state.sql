@@ -0,0 +1,7 @@
+-- The sequence number of the last market data feed update recorded in the database.
+CREATE TABLE IF NOT EXISTS feed_state(
+    id          INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+    seq         INTEGER NOT NULL CHECK (seq >= 0)
+);
+
+INSERT INTO feed_state (id, seq) VALUES (0, 0);
@@ -0,0 +1,17 @@
+-- Keys for authenticating API requests without a password.
+CREATE TABLE IF NOT EXISTS api_key(
+    id              INTEGER NOT NULL PRIMARY KEY,
+    user_id         INTEGER NOT NULL,
+    name            TEXT NOT NULL CHECK (length(name) <= 50),
+    -- the start of the key, to tell keys apart
+    prefix          TEXT NOT NULL,
+    -- hex encoded sha256 of the key
+    key_hash        TEXT NOT NULL UNIQUE,
+    scope           TEXT NOT NULL CHECK (scope IN ('read', 'trade', 'admin')),
+    -- comma separated IP addresses allowed to use the key, or NULL for any
+    ip_allowlist    TEXT,
+    created_at      INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS api_key_user_id ON api_key(user_id);
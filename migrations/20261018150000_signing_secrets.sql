@@ -0,0 +1,2 @@
+-- The secret a user signs API requests with, hex encoded, or NULL if they haven't made one.
+ALTER TABLE user ADD COLUMN signing_secret TEXT;
@@ -0,0 +1,5 @@
+-- What a user may do besides trading. Admins may do everything.
+ALTER TABLE user ADD COLUMN role TEXT NOT NULL DEFAULT 'trader'
+    CHECK (role IN ('trader', 'market_creator', 'resolver', 'admin'));
+
+UPDATE user SET role = 'admin' WHERE username = 'admin';
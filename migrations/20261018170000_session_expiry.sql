@@ -0,0 +1,4 @@
+-- Sessions used to be created without an expiry. Give them the week their cookie lasts.
+UPDATE session SET expires_at = created_at + 7 * 24 * 60 * 60 * 1000000 WHERE expires_at = 0;
+
+CREATE INDEX IF NOT EXISTS session_user_id ON session(user_id);
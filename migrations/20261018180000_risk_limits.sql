@@ -0,0 +1,18 @@
+-- Pre-trade risk limits checked by the matching engine. A row with neither a user nor
+-- a market sets the defaults. Unset limits fall back to the market's, then the defaults.
+CREATE TABLE IF NOT EXISTS risk_limit(
+    id                  INTEGER NOT NULL PRIMARY KEY,
+    user_id             INTEGER,
+    market_id           INTEGER,
+    max_order_quantity  INTEGER,
+    max_order_notional  INTEGER,
+    max_position        INTEGER,
+    max_open_orders     INTEGER,
+    price_band          INTEGER,
+    CHECK (user_id IS NULL OR market_id IS NULL),
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE,
+    FOREIGN KEY (market_id) REFERENCES market(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS risk_limit_scope
+    ON risk_limit(ifnull(user_id, 0), ifnull(market_id, 0));
@@ -0,0 +1,10 @@
+-- Open markets trade. Halted markets are paused, and closed markets are waiting to be resolved.
+ALTER TABLE market ADD COLUMN state TEXT NOT NULL DEFAULT 'open'
+    CHECK (state IN ('open', 'halted', 'closed'));
+
+-- Whether closing the event's markets at `event_time` cancels their resting orders.
+ALTER TABLE event ADD COLUMN cancel_orders_on_close INTEGER NOT NULL DEFAULT 0;
+
+-- Set once the event's markets have been closed at `event_time`, so markets reopened
+-- after it stay open.
+ALTER TABLE event ADD COLUMN closed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,15 @@
+-- Outcomes are proposed first, and settled once the dispute window ends without disputes.
+ALTER TABLE market ADD COLUMN proposed_outcome INTEGER;
+ALTER TABLE market ADD COLUMN dispute_ends_at INTEGER;
+
+-- Objections to a market's proposed outcome. Cleared when the proposal is voided or replaced.
+CREATE TABLE IF NOT EXISTS dispute(
+    id          INTEGER NOT NULL PRIMARY KEY,
+    market_id   INTEGER NOT NULL,
+    user_id     INTEGER NOT NULL,
+    reason      TEXT NOT NULL CHECK (length(reason) <= 500),
+    created_at  INTEGER NOT NULL,
+    UNIQUE (market_id, user_id),
+    FOREIGN KEY (market_id) REFERENCES market(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE
+);
@@ -0,0 +1,28 @@
+-- The net balance each user paid for their trades in a market, refunded if the market is voided.
+ALTER TABLE position ADD COLUMN cost INTEGER NOT NULL DEFAULT 0;
+
+-- Backfill the cost of existing positions from their trades, in the order they happened.
+-- Buying against a short and selling against a long only pay out what the contracts are worth.
+WITH leg AS (
+    SELECT id, 0 AS is_maker, market_id, taker_id AS user_id, quantity, price, is_buy FROM trade
+    UNION ALL
+    SELECT id, 1, market_id, maker_id, quantity, price, 1 - is_buy FROM trade
+),
+running AS (
+    SELECT *, ifnull(SUM(CASE WHEN is_buy THEN quantity ELSE -quantity END) OVER (
+        PARTITION BY user_id, market_id ORDER BY id, is_maker
+        ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+    ), 0) AS before
+    FROM leg
+)
+UPDATE position SET cost = ifnull((
+    SELECT SUM(CASE WHEN is_buy
+        THEN quantity * price - min(quantity, max(0, -before)) * 10000
+        ELSE max(0, quantity - max(0, before)) * 10000 - quantity * price
+    END)
+    FROM running
+    WHERE running.user_id = position.user_id AND running.market_id = position.market_id
+), 0);
+
+-- Voided markets were cancelled without an outcome, and every user refunded their cost.
+ALTER TABLE market ADD COLUMN voided INTEGER NOT NULL DEFAULT 0;
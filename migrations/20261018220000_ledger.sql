@@ -0,0 +1,25 @@
+-- Every change to a user's balance, appended by the writer service.
+-- A user's entries add up to their balance.
+CREATE TABLE IF NOT EXISTS ledger(
+    id          INTEGER NOT NULL PRIMARY KEY,
+    user_id     INTEGER NOT NULL,
+    -- Positive if the balance went up.
+    amount      INTEGER NOT NULL,
+    reason      TEXT NOT NULL CHECK (reason IN
+        ('opening', 'deposit', 'trade', 'create', 'combine', 'merge', 'resolve', 'void')),
+    market_id   INTEGER,
+    order_id    INTEGER,
+    trade_id    INTEGER,
+    created_at  INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user(id),
+    FOREIGN KEY (market_id) REFERENCES market(id),
+    FOREIGN KEY (order_id) REFERENCES 'order'(id),
+    FOREIGN KEY (trade_id) REFERENCES trade(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_user_id ON ledger(user_id, id);
+
+-- Balances from before the ledger.
+INSERT INTO ledger (user_id, amount, reason, created_at)
+    SELECT id, balance, 'opening', CAST(strftime('%s', 'now') AS INTEGER) * 1000000
+    FROM user WHERE balance != 0;
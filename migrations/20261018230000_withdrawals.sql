@@ -0,0 +1,27 @@
+-- Withdrawals and admin adjustments are recorded in the ledger, adjustments with a memo.
+-- SQLite can't change a CHECK constraint, so the table is rebuilt.
+CREATE TABLE ledger_new(
+    id          INTEGER NOT NULL PRIMARY KEY,
+    user_id     INTEGER NOT NULL,
+    -- Positive if the balance went up.
+    amount      INTEGER NOT NULL,
+    reason      TEXT NOT NULL CHECK (reason IN
+        ('opening', 'deposit', 'withdrawal', 'adjustment', 'trade', 'create', 'combine', 'merge',
+        'resolve', 'void')),
+    memo        TEXT CHECK (length(memo) <= 500),
+    market_id   INTEGER,
+    order_id    INTEGER,
+    trade_id    INTEGER,
+    created_at  INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user(id),
+    FOREIGN KEY (market_id) REFERENCES market(id),
+    FOREIGN KEY (order_id) REFERENCES 'order'(id),
+    FOREIGN KEY (trade_id) REFERENCES trade(id)
+);
+
+INSERT INTO ledger_new (id, user_id, amount, reason, market_id, order_id, trade_id, created_at)
+    SELECT id, user_id, amount, reason, market_id, order_id, trade_id, created_at FROM ledger;
+DROP TABLE ledger;
+ALTER TABLE ledger_new RENAME TO ledger;
+
+CREATE INDEX IF NOT EXISTS idx_ledger_user_id ON ledger(user_id, id);
@@ -0,0 +1,2 @@
+-- All-or-none orders only trade for their whole remaining quantity.
+ALTER TABLE "order" ADD COLUMN all_or_none INTEGER NOT NULL DEFAULT 0 CHECK (all_or_none IN (0, 1));
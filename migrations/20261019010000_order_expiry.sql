@@ -0,0 +1,2 @@
+-- When a good-till-time order is cancelled if it is still resting.
+ALTER TABLE "order" ADD COLUMN expires_at INTEGER;
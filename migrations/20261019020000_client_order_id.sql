@@ -0,0 +1,3 @@
+-- The id a user gave their order, unique among their open orders.
+ALTER TABLE "order" ADD COLUMN client_order_id INTEGER;
+CREATE INDEX order_user_client_order_id ON "order" (user_id, client_order_id);
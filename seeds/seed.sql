@@ -1,9 +1,14 @@
 PRAGMA foreign_keys = ON;
 
-INSERT INTO user(id, username, created_at, balance, available, password_hash) VALUES
-    (1, 'admin', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$xiP9HtGCRNu4qIOQVhj/og$cV9Wjt9ytLYtWqOUOJembuR9hQdp2mihcYBQN/I+oC4'),
-    (2, 'account2', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$oY7oDHdkawz7pDgD91BJqw$qdQnWbgzexhJBC23YLJ8M8TJhHi22zf+BMHJAqAL9Rw'),
-    (3, 'account3', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$oY7oDHdkawz7pDgD91BJqw$qdQnWbgzexhJBC23YLJ8M8TJhHi22zf+BMHJAqAL9Rw');
+INSERT INTO user(id, username, created_at, balance, available, password_hash, role) VALUES
+    (1, 'admin', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$xiP9HtGCRNu4qIOQVhj/og$cV9Wjt9ytLYtWqOUOJembuR9hQdp2mihcYBQN/I+oC4', 'admin'),
+    (2, 'account2', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$oY7oDHdkawz7pDgD91BJqw$qdQnWbgzexhJBC23YLJ8M8TJhHi22zf+BMHJAqAL9Rw', 'trader'),
+    (3, 'account3', 1722542400000000, 10000 * 10000, 10000 * 10000, '$argon2id$v=19$m=19456,t=2,p=1$oY7oDHdkawz7pDgD91BJqw$qdQnWbgzexhJBC23YLJ8M8TJhHi22zf+BMHJAqAL9Rw', 'trader');
+
+INSERT INTO ledger(user_id, amount, reason, created_at) VALUES
+    (1, 10000 * 10000, 'opening', 1722542400000000),
+    (2, 10000 * 10000, 'opening', 1722542400000000),
+    (3, 10000 * 10000, 'opening', 1722542400000000);
 
 INSERT INTO invite(code, created_by, created_at) VALUES
     ('MK6H5JI3HM', 1, 1722542400000000),
@@ -24,7 +29,7 @@ INSERT INTO event(id, slug, title, description, created_at, event_time) VALUES
 
 This event will be resolved however I please.',
     1722542400000000,
-    1722542400000000
+    1893456000000000
 );
 
 
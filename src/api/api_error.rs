@@ -28,6 +28,29 @@ pub enum ApiError {
     EventAlreadyExists,
     Authorization,
     UserNotFound,
+    ApiKeyNotFound,
+    /// The API key couldn't be created, with the reason.
+    InvalidApiKey(String),
+    /// A signed request's signature doesn't match its contents.
+    InvalidSignature,
+    /// A signed request arrived outside its receive window.
+    RequestExpired,
+    /// A signed request has already been made.
+    ReplayedRequest,
+    /// The risk limits couldn't be set, with the reason.
+    InvalidRiskLimit(String),
+    /// The outcome couldn't be proposed or disputed, with the reason.
+    InvalidResolution(String),
+    /// The market already has a proposed outcome, which only an admin can replace.
+    ResolutionPending,
+    /// The market has no proposed outcome, or it can no longer be disputed.
+    ResolutionNotFound,
+    /// The user already disputed the proposed outcome.
+    DisputeAlreadyExists,
+    /// The balance adjustment is missing a memo, or it is too long.
+    InvalidAdjustment(String),
+    /// The trading session couldn't be started, with the reason.
+    InvalidSession(String),
 }
 
 impl IntoResponse for ApiError {
@@ -39,26 +62,55 @@ impl IntoResponse for ApiError {
         }
 
         let (status, message) = match self {
-            ApiError::JsonRejection(rejection) => {
+            Self::JsonRejection(rejection) => {
                 // This error is caused by bad user input so don't log it
                 (rejection.status(), rejection.body_text())
             }
-            ApiError::MatcherRequest(reason) => (StatusCode::OK, format!("{reason:?}")),
-            ApiError::Authentication => {
+            Self::MatcherRequest(reason) => (StatusCode::OK, format!("{reason:?}")),
+            Self::Authentication => {
                 (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string())
             }
-            ApiError::InternalServerError => (
+            Self::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal server error".to_string(),
             ),
-            ApiError::EventAlreadyExists => {
+            Self::EventAlreadyExists => {
                 (StatusCode::CONFLICT, "Event already exists".to_string())
             }
-            ApiError::Authorization => (
+            Self::Authorization => (
                 StatusCode::FORBIDDEN,
                 "You are not authorized to perform this action".to_string(),
             ),
-            ApiError::UserNotFound => (StatusCode::BAD_REQUEST, "User not found".to_string()),
+            Self::UserNotFound => (StatusCode::BAD_REQUEST, "User not found".to_string()),
+            Self::ApiKeyNotFound => (StatusCode::NOT_FOUND, "API key not found".to_string()),
+            Self::InvalidApiKey(reason)
+            | Self::InvalidRiskLimit(reason)
+            | Self::InvalidResolution(reason)
+            | Self::InvalidAdjustment(reason)
+            | Self::InvalidSession(reason) => (StatusCode::BAD_REQUEST, reason),
+            Self::ResolutionPending => (
+                StatusCode::CONFLICT,
+                "Market already has a proposed outcome".to_string(),
+            ),
+            Self::ResolutionNotFound => (
+                StatusCode::NOT_FOUND,
+                "Market has no pending proposed outcome".to_string(),
+            ),
+            Self::DisputeAlreadyExists => (
+                StatusCode::CONFLICT,
+                "You already disputed this outcome".to_string(),
+            ),
+            Self::InvalidSignature => {
+                (StatusCode::UNAUTHORIZED, "Invalid signature".to_string())
+            }
+            Self::RequestExpired => (
+                StatusCode::UNAUTHORIZED,
+                "Request is outside its receive window".to_string(),
+            ),
+            Self::ReplayedRequest => (
+                StatusCode::UNAUTHORIZED,
+                "Request has already been made".to_string(),
+            ),
         };
         (status, ApiJson(ErrorResponse { error: message })).into_response()
     }
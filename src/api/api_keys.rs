@@ -0,0 +1,144 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::{current_time_micros, AppState},
+    models::api_key::{self, ApiKey, Scope},
+};
+
+use super::{
+    api_error::{ApiError, ApiJson},
+    auth::{AdminScope, ApiAuth, ReadScope},
+};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ApiKeyPost {
+    /// A name to tell the key apart.
+    name: String,
+    scope: Scope,
+    /// The IP addresses allowed to use the key. Any address may if empty.
+    #[serde(default)]
+    ip_allowlist: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    id: i64,
+    /// Send this in the `X-API-Key` header. It is not stored, so it can't be shown again.
+    key: String,
+}
+
+/// List API keys
+///
+/// Lists the keys of the authenticated user.
+#[utoipa::path(
+    get,
+    path = "/api/v1/api-keys",
+    responses(
+        (status = 200, description = "The user's API keys", body = [ApiKey])
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get(
+    ApiAuth { user, .. }: ApiAuth<ReadScope>,
+    State(state): State<AppState>,
+) -> Response {
+    match ApiKey::get_all_for_user(&state.pool, user.id).await {
+        Ok(keys) => Json(keys).into_response(),
+        Err(err) => {
+            error!("Failed to get API keys: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Create API key
+///
+/// Keys with a scope can do everything the lower scopes can:
+/// `read` < `trade` < `admin`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/api-keys",
+    request_body = ApiKeyPost,
+    responses(
+        (status = 200, description = "API key created", body = ApiKeyResponse)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn post(
+    ApiAuth { user, .. }: ApiAuth<AdminScope>,
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<ApiKeyPost>,
+) -> Response {
+    let name = payload.name.trim();
+    if name.is_empty() || name.len() > 50 {
+        return ApiError::InvalidApiKey("name must be 1 to 50 characters".to_string())
+            .into_response();
+    }
+    let ip_allowlist = match api_key::parse_ip_allowlist(&payload.ip_allowlist.join(",")) {
+        Ok(ip_allowlist) => ip_allowlist,
+        Err(ip) => {
+            return ApiError::InvalidApiKey(format!("{ip} is not an IP address")).into_response();
+        }
+    };
+
+    match ApiKey::create(
+        &state.pool,
+        user.id,
+        name,
+        payload.scope,
+        ip_allowlist.as_deref(),
+        current_time_micros(),
+    )
+    .await
+    {
+        Ok((id, key)) => Json(ApiKeyResponse { id, key }).into_response(),
+        Err(err) => {
+            error!("Failed to create API key: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Revoke API key
+#[utoipa::path(
+    delete,
+    path = "/api/v1/api-keys/:id",
+    params(
+        ("id" = i64, Path, description = "API key ID")
+    ),
+    responses(
+        (status = 200, description = "API key revoked")
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn delete_by_id(
+    ApiAuth { user, .. }: ApiAuth<AdminScope>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Response {
+    match ApiKey::delete(&state.pool, user.id, id).await {
+        Ok(0) => ApiError::ApiKeyNotFound.into_response(),
+        Ok(_) => Json(json!({"deleted": id})).into_response(),
+        Err(err) => {
+            error!("Failed to revoke API key: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
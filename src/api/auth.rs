@@ -1,58 +1,270 @@
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+
 use axum::async_trait;
-use axum::extract::FromRequestParts;
+use axum::extract::{ConnectInfo, FromRequestParts};
 use axum::http::request::Parts;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum_extra::headers::authorization::Basic;
 use axum_extra::headers::Authorization;
 use axum_extra::TypedHeader;
+use tracing::warn;
 
 use crate::app_state::AppState;
 use crate::models;
-use crate::models::user::User;
+use crate::models::api_key::{ApiKey, Scope};
+use crate::models::user::{Role, User};
 
 use super::api_error::ApiError;
+use super::signing::SignedUser;
+
+/// The header API keys are sent in.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// The header reverse proxies put the addresses a request was forwarded from in.
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// The scope an `ApiAuth` extractor requires.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub struct ReadScope;
+pub struct TradeScope;
+pub struct AdminScope;
+
+impl RequiredScope for ReadScope {
+    const SCOPE: Scope = Scope::Read;
+}
 
-pub struct BasicAuthExtractor(pub User);
+impl RequiredScope for TradeScope {
+    const SCOPE: Scope = Scope::Trade;
+}
+
+impl RequiredScope for AdminScope {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+/// Authenticates with an API key in the `X-API-Key` header, a request signature, or Basic auth.
+/// API keys need scope `S` and to be used from an allowed IP address.
+/// Signed requests have the trade scope, and Basic auth is allowed everything.
+pub struct ApiAuth<S> {
+    pub user: User,
+    scope: PhantomData<S>,
+}
 
 #[async_trait]
-impl FromRequestParts<AppState> for BasicAuthExtractor {
+impl<S: RequiredScope> FromRequestParts<AppState> for ApiAuth<S> {
     type Rejection = Response;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let Ok(TypedHeader(Authorization(x))) =
-            TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await
-        else {
-            return Err(ApiError::Authentication.into_response());
-        };
-        match models::user::User::check_login(&state.pool, x.username(), x.password()).await {
-            Some(user) => Ok(Self(user)),
-            None => Err(ApiError::Authentication.into_response()),
+        match authenticate(parts, state).await {
+            Ok(Some((user, scope))) if scope >= S::SCOPE => Ok(Self {
+                user,
+                scope: PhantomData,
+            }),
+            Ok(Some(_)) => Err(ApiError::Authorization.into_response()),
+            Ok(None) => Err(ApiError::Authentication.into_response()),
+            Err(err) => Err(err.into_response()),
         }
     }
 }
 
-pub struct OptionalBasicAuth(pub Option<User>);
+/// The role a `RoleAuth` extractor requires.
+pub trait RequiredRole {
+    const ROLE: Role;
+}
+
+pub struct MarketCreatorRole;
+pub struct ResolverRole;
+pub struct AdminRole;
+
+impl RequiredRole for MarketCreatorRole {
+    const ROLE: Role = Role::MarketCreator;
+}
+
+impl RequiredRole for ResolverRole {
+    const ROLE: Role = Role::Resolver;
+}
+
+impl RequiredRole for AdminRole {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Authenticates like `ApiAuth<AdminScope>`, and requires the user to have role `R`.
+pub struct RoleAuth<R> {
+    pub user: User,
+    role: PhantomData<R>,
+}
 
 #[async_trait]
-impl FromRequestParts<AppState> for OptionalBasicAuth {
+impl<R: RequiredRole> FromRequestParts<AppState> for RoleAuth<R> {
     type Rejection = Response;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let Ok(TypedHeader(Authorization(header))) =
-            TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await
-        else {
-            return Ok(Self(None));
+        let ApiAuth { user, .. } = ApiAuth::<AdminScope>::from_request_parts(parts, state).await?;
+        if !user.role.allows(R::ROLE) {
+            return Err(ApiError::Authorization.into_response());
+        }
+        Ok(Self {
+            user,
+            role: PhantomData,
+        })
+    }
+}
+
+/// Authenticates like `ApiAuth` with any scope. `None` if the credentials are missing or invalid.
+pub struct OptionalApiAuth(pub Option<User>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for OptionalApiAuth {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        match authenticate(parts, state).await {
+            Ok(user) => Ok(Self(user.map(|(user, _)| user))),
+            Err(ApiError::InternalServerError) => {
+                Err(ApiError::InternalServerError.into_response())
+            }
+            Err(_) => Ok(Self(None)),
+        }
+    }
+}
+
+/// Returns the user making the request and the scope they have,
+/// or `None` if there are no credentials.
+async fn authenticate(
+    parts: &mut Parts,
+    state: &AppState,
+) -> Result<Option<(User, Scope)>, ApiError> {
+    if let Some(SignedUser(user)) = parts.extensions.remove::<SignedUser>() {
+        return Ok(Some((user, Scope::Trade)));
+    }
+
+    if let Some(key) = parts.headers.get(API_KEY_HEADER) {
+        let key = key.to_str().map_err(|_| ApiError::Authentication)?;
+        let api_key = match ApiKey::get_by_key(&state.pool, key).await {
+            Ok(Some(api_key)) => api_key,
+            Ok(None) => return Err(ApiError::Authentication),
+            Err(err) => {
+                warn!(?err, "Failed to get API key");
+                return Err(ApiError::InternalServerError);
+            }
+        };
+        let ConnectInfo(addr) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::InternalServerError)?;
+        let ip = client_ip(addr.ip(), &parts.headers, &state.trusted_proxies);
+        if !api_key.allows_ip(ip) {
+            return Err(ApiError::Authorization);
+        }
+        return match User::get_by_id(&state.pool, api_key.user_id).await {
+            Ok(user) => Ok(Some((user, api_key.scope))),
+            Err(err) => {
+                warn!(?err, "Failed to get user");
+                Err(ApiError::InternalServerError)
+            }
         };
-        let user =
-            models::user::User::check_login(&state.pool, header.username(), header.password())
-                .await;
-        Ok(Self(user))
+    }
+
+    let Ok(TypedHeader(Authorization(header))) =
+        TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await
+    else {
+        return Ok(None);
+    };
+    models::user::User::check_login(&state.pool, header.username(), header.password())
+        .await
+        .map(|user| Some((user, Scope::Admin)))
+        .ok_or(ApiError::Authentication)
+}
+
+/// Returns the address of the client that sent a request `peer` connected with.
+///
+/// Proxies append the address they got the request from to `X-Forwarded-For`, so it is
+/// read from the right while the request came from a trusted proxy. Anything further
+/// left was written by the client and can't be believed.
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut ip = peer.to_canonical();
+    let mut forwarded = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .rev();
+    while trusted_proxies.contains(&ip) {
+        let Some(Ok(next)) = forwarded.next().map(|ip| ip.trim().parse::<IpAddr>()) else {
+            break;
+        };
+        ip = next.to_canonical();
+    }
+    ip
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use axum::http::HeaderMap;
+
+    use super::{client_ip, FORWARDED_FOR_HEADER};
+    use crate::models::api_key::{ApiKey, Scope};
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED_FOR_HEADER, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let trusted = [proxy];
+
+        assert_eq!(client_ip(proxy, &HeaderMap::new(), &trusted), proxy);
+        assert_eq!(client_ip(proxy, &forwarded_for(&["203.0.113.7"]), &trusted), client);
+        // a client can't pick its address by sending the header itself
+        let spoofed = forwarded_for(&["198.51.100.1, 203.0.113.7"]);
+        assert_eq!(client_ip(proxy, &spoofed, &trusted), client);
+        let spoofed = forwarded_for(&["198.51.100.1", "203.0.113.7"]);
+        assert_eq!(client_ip(proxy, &spoofed, &trusted), client);
+        assert_eq!(client_ip(client, &forwarded_for(&["198.51.100.1"]), &trusted), client);
+        assert_eq!(client_ip(proxy, &forwarded_for(&["203.0.113.7"]), &[]), proxy);
+        assert_eq!(client_ip(proxy, &forwarded_for(&["garbage"]), &trusted), proxy);
+    }
+
+    #[test]
+    fn test_forwarded_ip_allowlist() {
+        let api_key = ApiKey {
+            id: 1,
+            user_id: 1,
+            name: "bot".to_string(),
+            prefix: "qp_0000000".to_string(),
+            scope: Scope::Trade,
+            ip_allowlist: Some("203.0.113.7".to_string()),
+            created_at: 0,
+        };
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        let trusted = [proxy];
+
+        let listed = forwarded_for(&["203.0.113.7"]);
+        assert!(api_key.allows_ip(client_ip(proxy, &listed, &trusted)));
+        let unlisted = forwarded_for(&["198.51.100.1"]);
+        assert!(!api_key.allows_ip(client_ip(proxy, &unlisted, &trusted)));
+        // the proxy's own address isn't the client's
+        assert!(!api_key.allows_ip(client_ip(proxy, &HeaderMap::new(), &trusted)));
     }
 }
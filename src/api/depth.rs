@@ -0,0 +1,102 @@
+//! Streams aggregated price levels as they change.
+use axum::{
+    extract::{ws::WebSocket, Query, State, WebSocketUpgrade},
+    response::Response,
+};
+use lobster::MarketId;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::feed::FeedControl;
+use crate::app_state::AppState;
+use crate::services::book_service::{self, BookUpdate};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DepthParams {
+    /// The comma separated ids of the markets to get levels for.
+    pub markets: String,
+}
+
+/// The new total quantity of a price level. Zero means the level is gone.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct LevelDelta {
+    pub is_buy: bool,
+    pub price: u16,
+    pub quantity: u32,
+}
+
+impl From<book_service::LevelDelta> for LevelDelta {
+    fn from(level: book_service::LevelDelta) -> Self {
+        Self {
+            is_buy: level.side.is_buy(),
+            price: level.price,
+            quantity: level.quantity,
+        }
+    }
+}
+
+/// The levels of a market changed by the update with sequence number `seq`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename = "depth")]
+pub struct DepthUpdate {
+    pub seq: u64,
+    pub market: u32,
+    pub tick: u32,
+    pub levels: Vec<LevelDelta>,
+    pub best_bid: Option<u16>,
+    pub best_ask: Option<u16>,
+}
+
+impl From<&BookUpdate> for DepthUpdate {
+    fn from(update: &BookUpdate) -> Self {
+        Self {
+            seq: update.seq,
+            market: update.market_id,
+            tick: update.tick,
+            levels: update.levels.iter().copied().map(LevelDelta::from).collect(),
+            best_bid: update.best_bid,
+            best_ask: update.best_ask,
+        }
+    }
+}
+
+/// Subscribe to order book depth.
+///
+/// Sends a `book` snapshot of each market, then a `depth` message for every
+/// update with a greater `seq` that changed the market. The snapshots are sent
+/// again if the connection falls behind.
+#[utoipa::path(
+    get,
+    path = "/api/v1/depth",
+    params(DepthParams),
+    responses(
+        (status = 200, description = "Subscribe to order book depth", body = DepthUpdate)
+    )
+)]
+pub async fn get(
+    State(state): State<AppState>,
+    Query(params): Query<DepthParams>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, socket, params))
+}
+
+async fn handle_socket(state: AppState, socket: WebSocket, params: DepthParams) {
+    let Ok(markets) = params
+        .markets
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<Vec<MarketId>, _>>()
+    else {
+        return;
+    };
+
+    book_service::stream_books(
+        state,
+        socket,
+        markets,
+        |seq, market| serde_json::to_string(&FeedControl::book(seq, market)).unwrap(),
+        |update, _, _| serde_json::to_string(&DepthUpdate::from(update)).unwrap(),
+    )
+    .await;
+}
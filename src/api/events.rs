@@ -4,8 +4,9 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use lobster::RejectReason;
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, info};
 use utoipa::ToSchema;
 
 use crate::{
@@ -14,7 +15,10 @@ use crate::{
     services::matcher_request::MatcherRequest,
 };
 
-use super::{api_error::ApiError, auth::BasicAuthExtractor};
+use super::{
+    api_error::ApiError,
+    auth::{MarketCreatorRole, RoleAuth},
+};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct EventResponse {
@@ -72,10 +76,17 @@ pub struct EventPost {
     description: String,
     /// The time at which the event was created.
     created_at: i64,
-    /// The time at which the event will expire.
+    /// The time at which the event will expire. Its markets stop trading then.
     event_time: i64,
     /// The titles for the markets.
     markets: Vec<String>,
+    /// Whether exactly one of the markets resolves to 100%.
+    /// Complete sets of contracts across the markets then share collateral.
+    #[serde(default)]
+    mutually_exclusive: bool,
+    /// Whether closing the markets at `event_time` cancels their resting orders.
+    #[serde(default)]
+    cancel_orders_on_close: bool,
 }
 
 /// Creates a new event.
@@ -90,14 +101,14 @@ pub struct EventPost {
     )
 )]
 pub async fn post(
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    RoleAuth { user, .. }: RoleAuth<MarketCreatorRole>,
     State(state): State<AppState>,
     Path(slug): Path<String>,
     Json(event): Json<EventPost>,
 ) -> impl IntoResponse {
-    // TODO: make this a transaction, and solve admin check
-    if user.username != "admin" {
-        return ApiError::Authorization.into_response();
+    // TODO: make this a transaction
+    if event.mutually_exclusive && event.markets.len() < 2 {
+        return ApiError::MatcherRequest(RejectReason::InvalidExclusiveEvent).into_response();
     }
 
     let record = Event {
@@ -107,6 +118,8 @@ pub async fn post(
         description: event.description,
         created_at: event.created_at,
         event_time: event.event_time,
+        mutually_exclusive: event.mutually_exclusive,
+        cancel_orders_on_close: event.cancel_orders_on_close,
     };
 
     let event_id = match record.insert(&state.pool).await {
@@ -120,10 +133,21 @@ pub async fn post(
         }
     };
 
+    info!(user = user.username, slug, "Creating event");
+    let mut market_ids = Vec::new();
     for market in event.markets {
         let market_id = Market::new(&state.pool, event_id, market).await.unwrap();
         let req = MatcherRequest::AddMarket { market_id };
         state.cmd_send.send(req).await.unwrap();
+        market_ids.push(market_id);
+    }
+    if event.mutually_exclusive {
+        let (req, recv) = MatcherRequest::add_exclusive_event(market_ids);
+        state.cmd_send.send(req).await.unwrap();
+        if let Err(err) = recv.await.unwrap() {
+            error!("Failed to add exclusive event: {:?}", err);
+            return ApiError::MatcherRequest(err).into_response();
+        }
     }
 
     let event = Event::get_by_slug(&state.pool, &slug).await.unwrap();
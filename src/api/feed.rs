@@ -2,16 +2,125 @@
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        State, WebSocketUpgrade,
+        Query, State, WebSocketUpgrade,
     },
     response::Response,
 };
-use serde::Serialize;
-use utoipa::ToSchema;
+use lobster::{MarketId, Order, Price, Quantity, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
 
 use crate::app_state::AppState;
+use crate::models::{
+    event::Event,
+    feed_state::FeedState,
+    market::{Market, MarketState},
+};
+use crate::services::{
+    book_service::{self, MarketData},
+    matcher::FeedUpdate,
+    writer,
+};
 
-#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+/// A trade against a resting order.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct Fill {
+    /// The id of the maker order.
+    pub id: i64,
+    /// The user that placed the maker order.
+    pub user: u32,
+    pub quantity: u32,
+    pub price: u16,
+    /// Whether the maker order was fully filled.
+    pub done: bool,
+}
+
+impl From<lobster::Fill> for Fill {
+    fn from(fill: lobster::Fill) -> Self {
+        Self {
+            id: fill.id,
+            user: fill.user,
+            quantity: fill.quantity,
+            price: fill.price,
+            done: fill.done,
+        }
+    }
+}
+
+impl From<Fill> for lobster::Fill {
+    fn from(fill: Fill) -> Self {
+        Self::new(fill.id, fill.user, fill.quantity, fill.price, fill.done)
+    }
+}
+
+/// A resting order reduced by self-trade prevention.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct CancelledOrder {
+    pub id: i64,
+    /// The quantity taken off the order. It is removed if nothing is left.
+    pub quantity: u32,
+    pub price: u16,
+    pub is_buy: bool,
+}
+
+impl From<lobster::Order> for CancelledOrder {
+    fn from(order: lobster::Order) -> Self {
+        Self {
+            id: order.id,
+            quantity: order.quantity,
+            price: order.price,
+            is_buy: order.side.is_buy(),
+        }
+    }
+}
+
+/// Why a resting order was removed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    /// The user cancelled it.
+    Requested,
+    /// Its market was closed or halted with orders cancelled.
+    MarketClosed,
+    /// A good-till-time order reached its expiry.
+    Expired,
+    /// The user's cancel-on-disconnect trading session ended.
+    Disconnected,
+}
+
+impl From<lobster::CancelReason> for CancelReason {
+    fn from(reason: lobster::CancelReason) -> Self {
+        match reason {
+            lobster::CancelReason::Requested => Self::Requested,
+            lobster::CancelReason::MarketClosed => Self::MarketClosed,
+            lobster::CancelReason::Expired => Self::Expired,
+            lobster::CancelReason::Disconnected => Self::Disconnected,
+        }
+    }
+}
+
+impl From<CancelReason> for lobster::CancelReason {
+    fn from(reason: CancelReason) -> Self {
+        match reason {
+            CancelReason::Requested => Self::Requested,
+            CancelReason::MarketClosed => Self::MarketClosed,
+            CancelReason::Expired => Self::Expired,
+            CancelReason::Disconnected => Self::Disconnected,
+        }
+    }
+}
+
+impl From<CancelledOrder> for lobster::Order {
+    fn from(order: CancelledOrder) -> Self {
+        Self::new(order.id, order.quantity, order.price, Side::new(order.is_buy))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum MarketUpdate {
@@ -24,6 +133,16 @@ pub enum MarketUpdate {
         quantity: u32,
         price: u16,
         is_buy: bool,
+        /// Whether the order only trades for its whole remaining quantity.
+        all_or_none: bool,
+        /// When a good-till-time order is cancelled if it is still resting.
+        expires_at: Option<i64>,
+        /// The id the user gave the order.
+        client_order_id: Option<i64>,
+        /// The trades the order made against resting orders, in the order they happened.
+        fills: Vec<Fill>,
+        /// The user's resting orders reduced by self-trade prevention before matching.
+        cancelled: Vec<CancelledOrder>,
     },
     RemoveOrder {
         timestamp: i64,
@@ -32,6 +151,24 @@ pub enum MarketUpdate {
         user: u32,
         /// The id of the order to remove
         id: i64,
+        /// The id the user gave the order.
+        client_order_id: Option<i64>,
+        reason: CancelReason,
+    },
+    /// A resting order was amended. `quantity` is the new remaining quantity.
+    AmendOrder {
+        timestamp: i64,
+        tick: u32,
+        market: u32,
+        user: u32,
+        id: i64,
+        quantity: u32,
+        price: u16,
+        is_buy: bool,
+        /// The trades the order made against resting orders, in the order they happened.
+        fills: Vec<Fill>,
+        /// The user's resting orders reduced by self-trade prevention before matching.
+        cancelled: Vec<CancelledOrder>,
     },
     ResolveMarket {
         timestamp: i64,
@@ -40,17 +177,181 @@ pub enum MarketUpdate {
         /// The price the market was resolved to.
         price: u16,
     },
+    /// A market was cancelled. Its orders were removed and every user was
+    /// refunded their cost basis in it.
+    VoidMarket {
+        timestamp: i64,
+        tick: u32,
+        market: u32,
+    },
+    /// A market was opened, halted or closed.
+    SetMarketState {
+        timestamp: i64,
+        tick: u32,
+        market: u32,
+        state: MarketState,
+    },
     /// A new event was added.
     AddMarket {
         timestamp: i64,
         tick: u32,
         market: u32,
     },
+    /// Markets were grouped into an event where exactly one market resolves to 100%.
+    AddExclusiveEvent {
+        timestamp: i64,
+        markets: Vec<u32>,
+    },
     Deposit {
         timestamp: i64,
         user: u32,
         amount: i64,
     },
+    Withdrawal {
+        timestamp: i64,
+        user: u32,
+        amount: i64,
+    },
+    /// An admin corrected a user's balance. The public feed only has the timestamp.
+    Adjustment {
+        timestamp: i64,
+        user: u32,
+        /// Negative if the balance was reduced.
+        amount: i64,
+        /// Why the balance was adjusted.
+        memo: String,
+    },
+}
+
+/// A market update and its sequence number, as sent on the feed and recorded in the
+/// market data feed log.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedRecord {
+    /// Starts at 1 and goes up by one for every update, across all markets.
+    pub seq: u64,
+    #[serde(flatten)]
+    pub update: MarketUpdate,
+}
+
+impl From<FeedUpdate> for FeedRecord {
+    fn from(FeedUpdate { seq, update }: FeedUpdate) -> Self {
+        Self {
+            seq,
+            update: MarketUpdate::from(update),
+        }
+    }
+}
+
+/// A feed record as sent on the public feed. Balance adjustments are private, so
+/// only their timestamp is sent, to keep the sequence numbers contiguous.
+struct PublicRecord(FeedRecord);
+
+impl From<FeedUpdate> for PublicRecord {
+    fn from(update: FeedUpdate) -> Self {
+        Self(FeedRecord::from(update))
+    }
+}
+
+impl Serialize for PublicRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(tag = "type", rename = "adjustment")]
+        struct Adjustment {
+            seq: u64,
+            timestamp: i64,
+        }
+
+        match self.0.update {
+            MarketUpdate::Adjustment { timestamp, .. } => Adjustment {
+                seq: self.0.seq,
+                timestamp,
+            }
+            .serialize(serializer),
+            _ => self.0.serialize(serializer),
+        }
+    }
+}
+
+/// A channel on the feed: a market id, or an event slug for all of the event's markets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Channel {
+    Market(MarketId),
+    Event(String),
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Market(market) => write!(f, "{market}"),
+            Self::Event(slug) => write!(f, "{slug}"),
+        }
+    }
+}
+
+/// Sent by the client to choose which markets it gets updates for.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum FeedRequest {
+    Subscribe { channels: Vec<Channel> },
+    Unsubscribe { channels: Vec<Channel> },
+}
+
+/// The total quantity resting at a price.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct Level {
+    pub price: u16,
+    pub quantity: u32,
+}
+
+fn levels(levels: impl Iterator<Item = (Price, Quantity)>) -> Vec<Level> {
+    levels
+        .map(|(price, quantity)| Level { price, quantity })
+        .collect()
+}
+
+/// Sent on the feed besides market updates.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum FeedControl {
+    /// Updates after `last_seq` were skipped. Reload state, or reconnect with
+    /// `from_seq` set to `last_seq + 1`.
+    Resnapshot { last_seq: u64 },
+    /// A subscribe request was handled. The `book` of each market was sent before this.
+    Subscribed { channel: Channel, markets: Vec<u32> },
+    Unsubscribed { channel: Channel },
+    /// The order book of a subscribed market after the update with sequence number `seq`.
+    /// The market's updates with a greater `seq` follow.
+    Book {
+        seq: u64,
+        market: u32,
+        /// The tick of the market's last update, if it had one.
+        tick: Option<u32>,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    },
+    /// A request couldn't be handled.
+    Error { message: String },
+}
+
+impl FeedControl {
+    pub(super) fn book(seq: u64, market: &MarketData) -> Self {
+        Self::Book {
+            seq,
+            market: market.market_id,
+            tick: market.tick,
+            bids: levels(market.depth.bids()),
+            asks: levels(market.depth.asks()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct FeedParams {
+    /// Send updates from this sequence number before live ones.
+    pub from_seq: Option<u64>,
 }
 
 impl From<lobster::MarketUpdate> for MarketUpdate {
@@ -62,29 +363,27 @@ impl From<lobster::MarketUpdate> for MarketUpdate {
                 market,
                 user,
                 order,
-            } => MarketUpdate::AddOrder {
-                timestamp,
-                tick,
-                market,
-                user,
-                id: order.id,
-                quantity: order.quantity,
-                price: order.price,
-                is_buy: order.side.is_buy(),
-            },
+                fills,
+                cancelled,
+            } => add_order(timestamp, tick, market, user, &order, fills, cancelled),
             lobster::MarketUpdate::RemoveOrder {
                 timestamp,
                 tick,
                 market,
                 user,
                 id,
-            } => MarketUpdate::RemoveOrder {
+                client_order_id,
+                reason,
+            } => remove_order(timestamp, tick, market, user, id, client_order_id, reason),
+            lobster::MarketUpdate::AmendOrder {
                 timestamp,
                 tick,
                 market,
                 user,
-                id,
-            },
+                order,
+                fills,
+                cancelled,
+            } => amend_order(timestamp, tick, market, user, &order, fills, cancelled),
             lobster::MarketUpdate::ResolveMarket {
                 timestamp,
                 tick,
@@ -96,6 +395,21 @@ impl From<lobster::MarketUpdate> for MarketUpdate {
                 market,
                 price,
             },
+            lobster::MarketUpdate::VoidMarket {
+                timestamp,
+                tick,
+                market,
+            } => MarketUpdate::VoidMarket {
+                timestamp,
+                tick,
+                market,
+            },
+            lobster::MarketUpdate::SetMarketState {
+                timestamp,
+                tick,
+                market,
+                state,
+            } => set_market_state(timestamp, tick, market, state),
             lobster::MarketUpdate::AddMarket {
                 timestamp,
                 tick,
@@ -105,6 +419,9 @@ impl From<lobster::MarketUpdate> for MarketUpdate {
                 tick,
                 market,
             },
+            lobster::MarketUpdate::AddExclusiveEvent { timestamp, markets } => {
+                MarketUpdate::AddExclusiveEvent { timestamp, markets }
+            }
             lobster::MarketUpdate::Deposit {
                 timestamp,
                 user,
@@ -114,27 +431,563 @@ impl From<lobster::MarketUpdate> for MarketUpdate {
                 user,
                 amount: amount.into(),
             },
+            lobster::MarketUpdate::Withdrawal {
+                timestamp,
+                user,
+                amount,
+            } => MarketUpdate::Withdrawal {
+                timestamp,
+                user,
+                amount,
+            },
+            lobster::MarketUpdate::Adjustment {
+                timestamp,
+                user,
+                amount,
+                memo,
+            } => MarketUpdate::Adjustment {
+                timestamp,
+                user,
+                amount,
+                memo,
+            },
+        }
+    }
+}
+
+/// Writes an order that was added to the book, with the orders it traded against.
+fn add_order(
+    timestamp: i64,
+    tick: u32,
+    market: u32,
+    user: u32,
+    order: &Order,
+    fills: Vec<lobster::Fill>,
+    cancelled: Vec<Order>,
+) -> MarketUpdate {
+    MarketUpdate::AddOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id: order.id,
+        quantity: order.quantity,
+        price: order.price,
+        is_buy: order.side.is_buy(),
+        all_or_none: order.all_or_none,
+        expires_at: order.expires_at,
+        client_order_id: order.client_order_id,
+        fills: fills.into_iter().map(Fill::from).collect(),
+        cancelled: cancelled.into_iter().map(CancelledOrder::from).collect(),
+    }
+}
+
+/// Writes an order that was taken off the book.
+fn remove_order(
+    timestamp: i64,
+    tick: u32,
+    market: u32,
+    user: u32,
+    id: i64,
+    client_order_id: Option<i64>,
+    reason: lobster::CancelReason,
+) -> MarketUpdate {
+    MarketUpdate::RemoveOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id,
+        client_order_id,
+        reason: reason.into(),
+    }
+}
+
+/// Writes an amended order, with the orders it traded against.
+fn amend_order(
+    timestamp: i64,
+    tick: u32,
+    market: u32,
+    user: u32,
+    order: &Order,
+    fills: Vec<lobster::Fill>,
+    cancelled: Vec<Order>,
+) -> MarketUpdate {
+    MarketUpdate::AmendOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id: order.id,
+        quantity: order.quantity,
+        price: order.price,
+        is_buy: order.side.is_buy(),
+        fills: fills.into_iter().map(Fill::from).collect(),
+        cancelled: cancelled.into_iter().map(CancelledOrder::from).collect(),
+    }
+}
+
+fn set_market_state(
+    timestamp: i64,
+    tick: u32,
+    market: u32,
+    state: lobster::MarketState,
+) -> MarketUpdate {
+    MarketUpdate::SetMarketState {
+        timestamp,
+        tick,
+        market,
+        state: state.into(),
+    }
+}
+
+/// Reads an update back from the market data feed log.
+impl From<MarketUpdate> for lobster::MarketUpdate {
+    fn from(update: MarketUpdate) -> Self {
+        match update {
+            update @ MarketUpdate::AddOrder { .. } => read_add_order(update),
+            update @ MarketUpdate::RemoveOrder { .. } => read_remove_order(&update),
+            update @ MarketUpdate::AmendOrder { .. } => read_amend_order(update),
+            MarketUpdate::ResolveMarket {
+                timestamp,
+                tick,
+                market,
+                price,
+            } => Self::ResolveMarket {
+                timestamp,
+                tick,
+                market,
+                price,
+            },
+            MarketUpdate::VoidMarket {
+                timestamp,
+                tick,
+                market,
+            } => Self::VoidMarket {
+                timestamp,
+                tick,
+                market,
+            },
+            update @ MarketUpdate::SetMarketState { .. } => read_set_market_state(&update),
+            MarketUpdate::AddMarket {
+                timestamp,
+                tick,
+                market,
+            } => Self::AddMarket {
+                timestamp,
+                tick,
+                market,
+            },
+            MarketUpdate::AddExclusiveEvent { timestamp, markets } => {
+                Self::AddExclusiveEvent { timestamp, markets }
+            }
+            MarketUpdate::Deposit {
+                timestamp,
+                user,
+                amount,
+            } => Self::Deposit {
+                timestamp,
+                user,
+                amount,
+            },
+            MarketUpdate::Withdrawal {
+                timestamp,
+                user,
+                amount,
+            } => Self::Withdrawal {
+                timestamp,
+                user,
+                amount,
+            },
+            MarketUpdate::Adjustment {
+                timestamp,
+                user,
+                amount,
+                memo,
+            } => Self::Adjustment {
+                timestamp,
+                user,
+                amount,
+                memo,
+            },
         }
     }
 }
 
+fn read_add_order(update: MarketUpdate) -> lobster::MarketUpdate {
+    let MarketUpdate::AddOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id,
+        quantity,
+        price,
+        is_buy,
+        all_or_none,
+        expires_at,
+        client_order_id,
+        fills,
+        cancelled,
+    } = update
+    else {
+        unreachable!("not an add order update");
+    };
+    lobster::MarketUpdate::AddOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        order: Order::new(id, quantity, price, Side::new(is_buy))
+            .with_all_or_none(all_or_none)
+            .with_expiry(expires_at)
+            .with_client_order_id(client_order_id),
+        fills: fills.into_iter().map(lobster::Fill::from).collect(),
+        cancelled: cancelled.into_iter().map(Order::from).collect(),
+    }
+}
+
+fn read_amend_order(update: MarketUpdate) -> lobster::MarketUpdate {
+    let MarketUpdate::AmendOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id,
+        quantity,
+        price,
+        is_buy,
+        fills,
+        cancelled,
+    } = update
+    else {
+        unreachable!("not an amend order update");
+    };
+    lobster::MarketUpdate::AmendOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        order: Order::new(id, quantity, price, Side::new(is_buy)),
+        fills: fills.into_iter().map(lobster::Fill::from).collect(),
+        cancelled: cancelled.into_iter().map(Order::from).collect(),
+    }
+}
+
+fn read_remove_order(update: &MarketUpdate) -> lobster::MarketUpdate {
+    let &MarketUpdate::RemoveOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id,
+        client_order_id,
+        reason,
+    } = update
+    else {
+        unreachable!("not a remove order update");
+    };
+    lobster::MarketUpdate::RemoveOrder {
+        timestamp,
+        tick,
+        market,
+        user,
+        id,
+        client_order_id,
+        reason: reason.into(),
+    }
+}
+
+fn read_set_market_state(update: &MarketUpdate) -> lobster::MarketUpdate {
+    let &MarketUpdate::SetMarketState {
+        timestamp,
+        tick,
+        market,
+        state,
+    } = update
+    else {
+        unreachable!("not a set market state update");
+    };
+    lobster::MarketUpdate::SetMarketState {
+        timestamp,
+        tick,
+        market,
+        state: state.into(),
+    }
+}
+
+/// The most missed updates backfilled from the feed log. Clients further behind resnapshot.
+const MAX_BACKFILL: u64 = 10_000;
+
 /// Subscribe to event data feed.
+///
+/// Every update has a `seq`. Up to 10000 missed updates are backfilled from the
+/// feed log, and a `resnapshot` message is sent if that isn't possible.
+///
+/// Send a `subscribe` request to get only the updates of some markets. Each market
+/// subscribed to gets a `book` snapshot, then its updates after the snapshot's `seq`.
+/// Until then, every update is sent.
 #[utoipa::path(
     get,
     path = "/api/v1/feed",
+    params(FeedParams),
+    request_body = FeedRequest,
     responses(
-        (status = 200, description = "Subscribe to event data feed")
+        (status = 200, description = "Subscribe to event data feed", body = FeedRecord)
     )
 )]
-pub async fn get(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(|socket| handle_socket(state, socket))
+pub async fn get(
+    State(state): State<AppState>,
+    Query(params): Query<FeedParams>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, socket, params))
+}
+
+/// The markets a client subscribed to.
+#[derive(Debug, Default)]
+struct Subscriptions {
+    channels: HashMap<Channel, Vec<MarketId>>,
+    /// The sequence number of the snapshot sent for each subscribed market.
+    markets: HashMap<MarketId, u64>,
+}
+
+impl Subscriptions {
+    fn wants(&self, update: &FeedUpdate) -> bool {
+        update
+            .update
+            .market_tick()
+            .and_then(|(market, _)| self.markets.get(&market))
+            .is_some_and(|&snapshot| update.seq > snapshot)
+    }
+}
+
+struct Session {
+    state: AppState,
+    socket: WebSocket,
+    /// The sequence number of the last update handled.
+    last_seq: Option<u64>,
+    /// `None` until the first subscribe request, when every update is sent.
+    subscriptions: Option<Subscriptions>,
+}
+
+async fn handle_socket(state: AppState, socket: WebSocket, params: FeedParams) {
+    let mut session = Session {
+        state,
+        socket,
+        last_seq: None,
+        subscriptions: None,
+    };
+    // the receiver was subscribed with the request, so live updates queue up while backfilling
+    if let Some(from_seq) = params.from_seq {
+        let last = from_seq.saturating_sub(1);
+        let head = FeedState::get_seq(&session.state.pool).await.unwrap_or_default();
+        if head.saturating_sub(last) > MAX_BACKFILL {
+            if session.resnapshot(last, head + 1).await.is_err() {
+                return;
+            }
+        } else {
+            let Ok(last) = session.backfill(last, u64::MAX).await else {
+                return;
+            };
+            session.last_seq = Some(last);
+        }
+    }
+    let _ = session.run().await;
 }
 
-async fn handle_socket(mut state: AppState, mut socket: WebSocket) {
-    loop {
-        let update = state.feed_receive.recv().await.expect("Sender dropped");
-        let update = MarketUpdate::from(update);
-        let text = serde_json::to_string(&update).expect("failed to serialize");
-        socket.send(Message::Text(text)).await.unwrap();
+impl Session {
+    fn wants(&self, update: &FeedUpdate) -> bool {
+        self.subscriptions
+            .as_ref()
+            .is_none_or(|subscriptions| subscriptions.wants(update))
     }
+
+    async fn run(&mut self) -> Result<(), axum::Error> {
+        loop {
+            tokio::select! {
+                message = self.socket.recv() => match message {
+                    Some(Ok(Message::Text(text))) => self.on_request(&text).await?,
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => return Ok(()),
+                    Some(Ok(_)) => {}
+                },
+                update = self.state.feed_receive.recv() => match update {
+                    Ok(update) => self.on_update(update).await?,
+                    // the skipped updates are backfilled when the next one arrives
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    async fn on_update(&mut self, update: FeedUpdate) -> Result<(), axum::Error> {
+        if let Some(last) = self.last_seq {
+            if update.seq <= last {
+                return Ok(());
+            }
+            if update.seq > last + 1 + MAX_BACKFILL {
+                self.resnapshot(last, update.seq).await?;
+            } else if update.seq > last + 1 {
+                let last = self.backfill(last, update.seq).await?;
+                if update.seq > last + 1 {
+                    self.resnapshot(last, update.seq).await?;
+                }
+            }
+        }
+
+        self.last_seq = Some(update.seq);
+        if !self.wants(&update) {
+            return Ok(());
+        }
+        send(&mut self.socket, &PublicRecord::from(update)).await
+    }
+
+    /// Sends up to `MAX_BACKFILL` logged updates after `last_seq` and before `until`,
+    /// stopping at any gap. Returns the sequence number of the last update handled.
+    async fn backfill(&mut self, mut last_seq: u64, until: u64) -> Result<u64, axum::Error> {
+        let after = last_seq;
+        let limit = usize::try_from(MAX_BACKFILL).expect("fits in usize");
+        let records = tokio::task::spawn_blocking(move || writer::read_feed_log_after(after, limit))
+            .await
+            .expect("feed log reader panicked");
+        let records = match records {
+            Ok(records) => records,
+            Err(err) => {
+                warn!(?err, "failed to read feed log");
+                return Ok(last_seq);
+            }
+        };
+
+        for record in records {
+            if record.seq <= last_seq {
+                continue;
+            }
+            if record.seq != last_seq + 1 || record.seq >= until {
+                break;
+            }
+            last_seq = record.seq;
+            if self.wants(&record) {
+                send(&mut self.socket, &PublicRecord::from(record)).await?;
+            }
+        }
+        Ok(last_seq)
+    }
+
+    /// Recovers from missing the updates after `last_seq` and before `seq`.
+    /// Subscribed markets get a new snapshot, otherwise the client is told to resnapshot.
+    async fn resnapshot(&mut self, last_seq: u64, seq: u64) -> Result<(), axum::Error> {
+        let Some(subscriptions) = &self.subscriptions else {
+            let control = FeedControl::Resnapshot { last_seq };
+            return send(&mut self.socket, &control).await;
+        };
+        let markets = subscriptions.markets.keys().copied().collect();
+        self.send_books(markets, seq - 1).await?;
+        Ok(())
+    }
+
+    async fn on_request(&mut self, text: &str) -> Result<(), axum::Error> {
+        let request = match serde_json::from_str::<FeedRequest>(text) {
+            Ok(request) => request,
+            Err(err) => {
+                let message = format!("invalid request: {err}");
+                return send(&mut self.socket, &FeedControl::Error { message }).await;
+            }
+        };
+        match request {
+            FeedRequest::Subscribe { channels } => {
+                for channel in channels {
+                    self.subscribe(channel).await?;
+                }
+            }
+            FeedRequest::Unsubscribe { channels } => {
+                for channel in channels {
+                    self.unsubscribe(channel).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn subscribe(&mut self, channel: Channel) -> Result<(), axum::Error> {
+        let markets = match &channel {
+            Channel::Market(market) => Some(vec![*market]),
+            Channel::Event(slug) => match Event::get_by_slug(&self.state.pool, slug).await {
+                Ok(event) => Market::get_all_for_event(&self.state.pool, event.id)
+                    .await
+                    .ok()
+                    .map(|markets| markets.into_iter().map(|market| market.id).collect()),
+                Err(_) => None,
+            },
+        };
+        let Some(markets) = markets else {
+            let message = format!("channel {channel} not found");
+            return send(&mut self.socket, &FeedControl::Error { message }).await;
+        };
+
+        let markets = self
+            .send_books(markets, self.last_seq.unwrap_or_default())
+            .await?;
+        if markets.is_empty() {
+            let message = format!("channel {channel} has no open markets");
+            return send(&mut self.socket, &FeedControl::Error { message }).await;
+        }
+        self.subscriptions
+            .as_mut()
+            .expect("subscribed to the markets")
+            .channels
+            .insert(channel.clone(), markets.clone());
+        send(&mut self.socket, &FeedControl::Subscribed { channel, markets }).await
+    }
+
+    async fn unsubscribe(&mut self, channel: Channel) -> Result<(), axum::Error> {
+        let Some(subscriptions) = self
+            .subscriptions
+            .as_mut()
+            .filter(|subscriptions| subscriptions.channels.contains_key(&channel))
+        else {
+            let message = format!("not subscribed to {channel}");
+            return send(&mut self.socket, &FeedControl::Error { message }).await;
+        };
+        subscriptions.channels.remove(&channel);
+        let channels = &subscriptions.channels;
+        subscriptions
+            .markets
+            .retain(|market, _| channels.values().any(|markets| markets.contains(market)));
+        send(&mut self.socket, &FeedControl::Unsubscribed { channel }).await
+    }
+
+    /// Sends a snapshot of each open market, taken after the update with sequence number
+    /// `min_seq`, and subscribes to their later updates. Returns the open markets.
+    async fn send_books(
+        &mut self,
+        markets: Vec<MarketId>,
+        min_seq: u64,
+    ) -> Result<Vec<MarketId>, axum::Error> {
+        // the book service only stops if it failed, and then the socket is closed
+        let Some(snapshot) = book_service::snapshot(&self.state, markets, min_seq).await else {
+            return Err(axum::Error::new("book service stopped"));
+        };
+
+        // updates at or before the snapshot are already in it
+        if self.last_seq.is_none() {
+            self.last_seq = Some(snapshot.seq);
+        }
+        let markets: Vec<MarketId> = snapshot.markets.iter().map(|market| market.market_id).collect();
+        if !markets.is_empty() {
+            let subscriptions = self.subscriptions.get_or_insert_with(Subscriptions::default);
+            for &market in &markets {
+                subscriptions.markets.insert(market, snapshot.seq);
+            }
+        }
+        for market in &snapshot.markets {
+            send(&mut self.socket, &FeedControl::book(snapshot.seq, market)).await?;
+        }
+        Ok(markets)
+    }
+}
+
+pub(super) async fn send(socket: &mut WebSocket, message: &(impl Serialize + Sync)) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).expect("failed to serialize");
+    socket.send(Message::Text(text)).await
 }
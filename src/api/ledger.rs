@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::error;
+
+use crate::{
+    app_state::AppState,
+    models::{
+        ledger::{LedgerEntry, LedgerParams},
+        user::Role,
+    },
+};
+
+use super::{
+    api_error::ApiError,
+    auth::{ApiAuth, ReadScope},
+};
+
+/// Get ledger
+///
+/// Lists every change to a user's balance, newest first. A user's entries add up
+/// to their balance. To get the next page, pass the id of the last entry as `before`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ledger",
+    params(LedgerParams),
+    responses(
+        (status = 200, description = "The ledger entries", body = [LedgerEntry])
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get(
+    ApiAuth { user, .. }: ApiAuth<ReadScope>,
+    State(state): State<AppState>,
+    Query(params): Query<LedgerParams>,
+) -> Response {
+    let user_id = params.user_id.unwrap_or(user.id);
+    if user_id != user.id && !user.role.allows(Role::Admin) {
+        return ApiError::Authorization.into_response();
+    }
+
+    match LedgerEntry::get(&state.pool, user_id, &params).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(err) => {
+            error!("Failed to get ledger: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
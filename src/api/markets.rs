@@ -4,56 +4,302 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use lobster::MarketId;
+use lobster::{MarketId, RejectReason, RESOLVE_PRICE};
 use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info};
 use utoipa::ToSchema;
 
 use crate::api::feed::MarketUpdate;
-use crate::app_state::AppState;
+use crate::app_state::{current_time_micros, AppState};
+use crate::models::{
+    market::MarketState,
+    resolution::{Dispute, Resolution},
+    user::{Role, User},
+};
 use crate::services::matcher_request::MatcherRequest;
 
-use super::api_error::ApiError;
-use super::auth::BasicAuthExtractor;
+use super::api_error::{ApiError, ApiJson};
+use super::auth::{AdminRole, ApiAuth, ResolverRole, RoleAuth, TradeScope};
+
+/// The longest reason a dispute can give.
+const MAX_REASON_LENGTH: usize = 500;
+/// How long a proposed outcome can be disputed if the proposal doesn't say, in seconds.
+const DEFAULT_DISPUTE_PERIOD: i64 = 24 * 60 * 60;
+/// The longest dispute period allowed, in seconds.
+const MAX_DISPUTE_PERIOD: i64 = 30 * 24 * 60 * 60;
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct MarketPatchPayload {
-    /// If set, resolves the market to the given price.
+    /// If set, proposes resolving the market to the given price and closes it.
+    /// Only admins can replace a pending proposal.
     #[schema(minimum = 0, maximum = 10000)]
     outcome: Option<u16>,
+    /// How long the proposed outcome can be disputed, in seconds. Defaults to a day.
+    #[schema(minimum = 0, maximum = 2592000)]
+    dispute_period: Option<i64>,
+    /// If set, opens, halts or closes the market. Only admins can change the state.
+    state: Option<MarketState>,
+    /// Whether changing the state cancels the market's resting orders.
+    #[serde(default)]
+    cancel_orders: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DisputePost {
+    /// Why the proposed outcome is wrong.
+    reason: String,
 }
 
 /// Modify an market.
+///
+/// The state is changed before an outcome is proposed.
+///
+/// A proposed outcome is settled once the dispute period ends. If users dispute it,
+/// it waits for an admin to replace or void it.
 #[utoipa::path(
     patch,
     path = "/api/v1/markets/:id",
     request_body = MarketPatchPayload,
     responses(
-        (status = 200, description = "Market modified successfully", body = MarketUpdate)
+        (status = 200, description = "Market state changed", body = MarketUpdate),
+        (status = 200, description = "Outcome proposed", body = Resolution)
     )
 )]
 pub async fn patch(
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    RoleAuth { user, .. }: RoleAuth<ResolverRole>,
     State(state): State<AppState>,
     Path(market_id): Path<MarketId>,
     Json(payload): Json<MarketPatchPayload>,
 ) -> impl IntoResponse {
-    if user.username != "admin" {
-        // TODO
-        return StatusCode::FORBIDDEN.into_response();
+    let mut response = None;
+    if let Some(market_state) = payload.state {
+        if !user.role.allows(Role::Admin) {
+            return ApiError::Authorization.into_response();
+        }
+        info!(user = user.username, market_id, state = ?market_state, payload.cancel_orders, "Setting market state");
+        let (cmd, recv) =
+            MatcherRequest::set_market_state(market_id, market_state.into(), payload.cancel_orders);
+        state.cmd_send.send(cmd).await.unwrap();
+        match recv.await.unwrap() {
+            Ok(update) => response = Some(update),
+            Err(err) => return ApiError::MatcherRequest(err).into_response(),
+        }
     }
 
     if let Some(price) = payload.outcome {
-        let (cmd, recv) = MatcherRequest::resolve(market_id, price);
-        state.cmd_send.send(cmd).await.unwrap();
-        let response = recv
+        let dispute_period = payload.dispute_period.unwrap_or(DEFAULT_DISPUTE_PERIOD);
+        return propose(&state, &user, market_id, price, dispute_period)
             .await
-            .unwrap()
-            .map_err(|err| ApiError::MatcherRequest(err));
-        return match response {
-            Ok(market) => Json(MarketUpdate::from(market)).into_response(),
-            Err(err) => err.into_response(),
-        };
+            .map_or_else(IntoResponse::into_response, |resolution| {
+                Json(resolution).into_response()
+            });
+    }
+
+    response.map_or_else(
+        || StatusCode::OK.into_response(),
+        |update| Json(MarketUpdate::from(update)).into_response(),
+    )
+}
+
+/// Closes a market and proposes an outcome for it.
+async fn propose(
+    state: &AppState,
+    user: &User,
+    market_id: MarketId,
+    price: u16,
+    dispute_period: i64,
+) -> Result<Resolution, ApiError> {
+    if price > RESOLVE_PRICE {
+        return Err(ApiError::MatcherRequest(RejectReason::InvalidPrice));
+    }
+    if !(0..=MAX_DISPUTE_PERIOD).contains(&dispute_period) {
+        return Err(ApiError::InvalidResolution(format!(
+            "dispute_period must be between 0 and {MAX_DISPUTE_PERIOD}"
+        )));
+    }
+    let pending = Resolution::get(&state.pool, market_id).await.map_err(|err| {
+        error!("Failed to get resolution: {:?}", err);
+        ApiError::InternalServerError
+    })?;
+    if pending.is_some() && !user.role.allows(Role::Admin) {
+        return Err(ApiError::ResolutionPending);
+    }
+
+    let dispute_ends_at = current_time_micros() + dispute_period * 1_000_000;
+    info!(user = user.username, market_id, price, dispute_ends_at, "Proposing outcome");
+    let internal = |err| {
+        error!("Failed to propose outcome: {:?}", err);
+        ApiError::InternalServerError
+    };
+    let mut tx = state.pool.begin().await.map_err(internal)?;
+    if !Resolution::propose(&mut *tx, market_id, price, dispute_ends_at)
+        .await
+        .map_err(internal)?
+    {
+        return Err(ApiError::MatcherRequest(RejectReason::MarketNotFound));
+    }
+
+    // the market stops trading while its outcome can be disputed,
+    // and the proposal is rolled back if it can't be closed
+    let (cmd, recv) = MatcherRequest::set_market_state(market_id, MarketState::Closed.into(), false);
+    state.cmd_send.send(cmd).await.unwrap();
+    recv.await.unwrap().map_err(ApiError::MatcherRequest)?;
+    tx.commit().await.map_err(internal)?;
+
+    match Resolution::get(&state.pool, market_id).await {
+        Ok(Some(resolution)) => Ok(resolution),
+        Ok(None) => Err(ApiError::MatcherRequest(RejectReason::MarketNotFound)),
+        Err(err) => {
+            error!("Failed to propose outcome: {:?}", err);
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+/// Void proposed outcome
+///
+/// Withdraws a market's proposed outcome before it is settled, and clears its disputes.
+/// The market stays closed.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/markets/:id/resolution",
+    responses(
+        (status = 200, description = "Proposed outcome voided")
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn delete_resolution(
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    State(state): State<AppState>,
+    Path(market_id): Path<MarketId>,
+) -> impl IntoResponse {
+    match Resolution::void(&state.pool, market_id).await {
+        Ok(()) => {
+            info!(admin = admin.username, market_id, "Voided proposed outcome");
+            Json(json!({"deleted": true})).into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => ApiError::ResolutionNotFound.into_response(),
+        Err(err) => {
+            error!("Failed to void resolution: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Void market
+///
+/// Cancels a market without resolving it. Its orders are cancelled, and every user
+/// is refunded what they paid for their trades in it, less what they received.
+/// Markets of mutually exclusive events can't be voided. The void is rejected with
+/// insufficient funds if a user took more out of the market than they have available
+/// to pay back.
+#[utoipa::path(
+    post,
+    path = "/api/v1/markets/:id/void",
+    responses(
+        (status = 200, description = "Market voided", body = MarketUpdate)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn post_void(
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    State(state): State<AppState>,
+    Path(market_id): Path<MarketId>,
+) -> impl IntoResponse {
+    info!(admin = admin.username, market_id, "Voiding market");
+    let (cmd, recv) = MatcherRequest::void(market_id);
+    state.cmd_send.send(cmd).await.unwrap();
+    match recv.await.unwrap() {
+        Ok(update) => Json(MarketUpdate::from(update)).into_response(),
+        Err(err) => ApiError::MatcherRequest(err).into_response(),
     }
+}
 
-    return StatusCode::OK.into_response();
+/// List disputes
+///
+/// Lists the disputes of a market's proposed outcome.
+#[utoipa::path(
+    get,
+    path = "/api/v1/markets/:id/disputes",
+    responses(
+        (status = 200, description = "The disputes", body = [Dispute])
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_disputes(
+    _auth: RoleAuth<ResolverRole>,
+    State(state): State<AppState>,
+    Path(market_id): Path<MarketId>,
+) -> impl IntoResponse {
+    match Dispute::get_for_market(&state.pool, market_id).await {
+        Ok(disputes) => Json(disputes).into_response(),
+        Err(err) => {
+            error!("Failed to get disputes: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Dispute proposed outcome
+///
+/// Objects to a market's proposed outcome while it can be disputed.
+/// A disputed outcome isn't settled until an admin replaces or voids it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/markets/:id/disputes",
+    request_body = DisputePost,
+    responses(
+        (status = 200, description = "Dispute filed", body = Dispute)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn post_dispute(
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
+    State(state): State<AppState>,
+    Path(market_id): Path<MarketId>,
+    ApiJson(payload): ApiJson<DisputePost>,
+) -> impl IntoResponse {
+    if payload.reason.trim().is_empty() || payload.reason.len() > MAX_REASON_LENGTH {
+        return ApiError::InvalidResolution(format!(
+            "reason must be 1 to {MAX_REASON_LENGTH} characters"
+        ))
+        .into_response();
+    }
+
+    let now = current_time_micros();
+    match Resolution::get(&state.pool, market_id).await {
+        Ok(Some(resolution)) if now < resolution.dispute_ends_at => {}
+        Ok(_) => return ApiError::ResolutionNotFound.into_response(),
+        Err(err) => {
+            error!("Failed to get resolution: {:?}", err);
+            return ApiError::InternalServerError.into_response();
+        }
+    }
+
+    match Dispute::insert(&state.pool, market_id, user.id, &payload.reason, now).await {
+        Ok(dispute) => {
+            info!(user = user.username, market_id, "Disputed proposed outcome");
+            Json(dispute).into_response()
+        }
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            ApiError::DisputeAlreadyExists.into_response()
+        }
+        Err(err) => {
+            error!("Failed to insert dispute: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
 }
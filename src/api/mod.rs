@@ -1,5 +1,6 @@
 use axum::{
-    routing::{delete, get, patch, post},
+    middleware,
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
@@ -8,25 +9,31 @@ use utoipa_swagger_ui::SwaggerUi;
 use crate::{app_state::AppState, models};
 
 mod api_error;
+mod api_keys;
 mod auth;
+mod depth;
 mod events;
 mod feed;
+mod ledger;
 mod markets;
 mod order_request;
 mod orders;
 mod positions;
+mod risk_limits;
+mod session;
+mod signing;
 mod trades;
 mod user;
 
 use utoipa::{
     openapi::{
         self,
-        security::{HttpAuthScheme, SecurityScheme},
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, SecurityScheme},
     },
     Modify, OpenApi,
 };
 
-pub use feed::MarketUpdate;
+pub use feed::FeedRecord;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -35,25 +42,74 @@ pub use feed::MarketUpdate;
         orders::post,
         orders::delete,
         orders::delete_by_id,
+        orders::delete_by_client_order_id,
+        orders::patch,
         feed::get,
+        session::get,
+        depth::get,
         trades::get,
         positions::get,
+        ledger::get,
         events::post,
         markets::patch,
+        markets::delete_resolution,
+        markets::post_void,
+        markets::get_disputes,
+        markets::post_dispute,
+        user::put_role,
+        user::withdraw,
+        user::adjust,
+        risk_limits::get,
+        risk_limits::put,
+        api_keys::get,
+        api_keys::post,
+        api_keys::delete_by_id,
+        signing::post,
+        signing::delete,
     ),
     components(
         schemas(
             order_request::OrderRequest,
+            order_request::SelfTradePrevention,
             orders::TimeInForce,
+            orders::AmendRequest,
             events::EventPost,
             events::EventResponse,
             markets::MarketPatchPayload,
+            markets::DisputePost,
+            user::RolePayload,
+            user::WithdrawPayload,
+            user::AdjustmentPayload,
+            models::user::Role,
+            api_keys::ApiKeyPost,
+            api_keys::ApiKeyResponse,
+            signing::SigningSecretResponse,
+            feed::FeedRecord,
+            feed::FeedControl,
+            feed::FeedRequest,
+            feed::Channel,
+            feed::Level,
             feed::MarketUpdate,
+            feed::Fill,
+            feed::CancelledOrder,
+            feed::CancelReason,
+            session::SessionRequest,
+            session::SessionMessage,
+            depth::DepthUpdate,
+            depth::LevelDelta,
             models::order::Order,
             models::event::Event,
             models::market::Market,
+            models::market::MarketState,
+            models::resolution::Resolution,
+            models::resolution::Dispute,
             models::position::Position,
+            models::ledger::LedgerEntry,
+            models::ledger::LedgerReason,
+            models::risk_limit::RiskLimit,
             models::trade::Trade,
+            models::api_key::ApiKey,
+            models::api_key::Scope,
         ),
     ),
     modifiers(&SecurityAddon),
@@ -82,26 +138,68 @@ impl Modify for SecurityAddon {
             components.add_security_scheme(
                 "basic_auth",
                 SecurityScheme::Http(openapi::security::Http::new(HttpAuthScheme::Basic)),
-            )
+            );
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(auth::API_KEY_HEADER))),
+            );
+            components.add_security_scheme(
+                "signature",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                    signing::SIGNATURE_HEADER,
+                    "HMAC-SHA256 request signature, see `POST /api/v1/signing-secret`",
+                ))),
+            );
         }
     }
 }
 
 pub fn router(state: AppState) -> Router {
+    let signed = middleware::from_fn_with_state(state.clone(), signing::verify);
     let apiv1 = Router::new()
         .route("/deposit/:id", post(user::deposit))
+        .route("/withdraw/:id", post(user::withdraw))
+        .route("/adjustments/:id", post(user::adjust))
         .route("/users/:username", get(user::get))
+        .route("/users/:username/role", put(user::put_role))
         .route("/markets/:id", patch(markets::patch))
+        .route("/markets/:id/resolution", delete(markets::delete_resolution))
+        .route("/markets/:id/void", post(markets::post_void))
+        .route(
+            "/markets/:id/disputes",
+            get(markets::get_disputes).post(markets::post_dispute),
+        )
         .route("/feed", get(feed::get))
+        .route("/session", get(session::get))
+        .route("/depth", get(depth::get))
         .route("/events", get(events::get))
         .route("/events/:slug", get(events::get_by_slug))
         .route("/events/:slug", post(events::post))
         .route(
             "/orders",
-            get(orders::get).post(orders::post).delete(orders::delete),
+            get(orders::get).merge(
+                post(orders::post)
+                    .delete(orders::delete)
+                    .layer(signed.clone()),
+            ),
+        )
+        .route(
+            "/orders/:id",
+            patch(orders::patch).merge(delete(orders::delete_by_id).layer(signed.clone())),
+        )
+        .route(
+            "/orders/client/:client_order_id",
+            delete(orders::delete_by_client_order_id).layer(signed),
         )
-        .route("/orders/:id", delete(orders::delete_by_id))
         .route("/positions", get(positions::get))
+        .route("/ledger", get(ledger::get))
+        .route("/risk-limits", get(risk_limits::get).put(risk_limits::put))
+        .route("/api-keys", get(api_keys::get).post(api_keys::post))
+        .route("/api-keys/:id", delete(api_keys::delete_by_id))
+        .route(
+            "/signing-secret",
+            post(signing::post).delete(signing::delete),
+        )
         .route("/trades", get(trades::get));
 
     Router::new()
@@ -19,6 +19,18 @@ pub struct OrderRequest {
     /// The time in force of the order. Defaults to good-till-closed ("GTC")
     #[serde(default = "TimeInForce::gtc")]
     pub tif: TimeInForce,
+    /// What to do if the order would trade against your own resting orders.
+    /// Self-trades are allowed if not present.
+    #[serde(default)]
+    pub stp: Option<SelfTradePrevention>,
+    /// When a "GTT" order is cancelled if it is still resting, in microseconds since the epoch.
+    /// Required for "GTT" orders and not allowed on others.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// An id for the order, unique among your open orders. It is echoed in the
+    /// order's updates, and the order can be found and cancelled by it.
+    #[serde(default)]
+    pub client_order_id: Option<i64>,
 }
 
 impl From<OrderRequest> for lobster::OrderRequest {
@@ -32,7 +44,20 @@ impl From<OrderRequest> for lobster::OrderRequest {
                 TimeInForce::GTC => lobster::TimeInForce::GTC,
                 TimeInForce::IOC => lobster::TimeInForce::IOC,
                 TimeInForce::POST => lobster::TimeInForce::POST,
+                TimeInForce::FOK => lobster::TimeInForce::FOK,
+                TimeInForce::AON => lobster::TimeInForce::AON,
+                TimeInForce::GTT => lobster::TimeInForce::GTT,
             },
+            stp: req.stp.map(|stp| match stp {
+                SelfTradePrevention::CancelNewest => lobster::SelfTradePrevention::CancelNewest,
+                SelfTradePrevention::CancelOldest => lobster::SelfTradePrevention::CancelOldest,
+                SelfTradePrevention::CancelBoth => lobster::SelfTradePrevention::CancelBoth,
+                SelfTradePrevention::DecrementAndCancel => {
+                    lobster::SelfTradePrevention::DecrementAndCancel
+                }
+            }),
+            expires_at: req.expires_at,
+            client_order_id: req.client_order_id,
         }
     }
 }
@@ -48,6 +73,26 @@ pub enum TimeInForce {
     IOC,
     /// Don't take liquidity.
     POST,
+    /// Fill or kill. Rejected unless it can be filled in full immediately.
+    FOK,
+    /// All or none. Only trades for its whole remaining quantity.
+    AON,
+    /// Good till time. Cancelled at `expires_at` if it is still resting.
+    GTT,
+}
+
+/// Self-trade prevention mode of an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+    /// Cancel the remainder of the new order.
+    CancelNewest,
+    /// Cancel the resting order and keep matching.
+    CancelOldest,
+    /// Cancel both the resting order and the remainder of the new order.
+    CancelBoth,
+    /// Reduce both orders by the smaller quantity, cancelling whichever has none left.
+    DecrementAndCancel,
 }
 
 impl TimeInForce {
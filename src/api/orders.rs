@@ -4,7 +4,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use lobster::OrderId;
+use lobster::{ClientOrderId, OrderId};
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::QueryBuilder;
@@ -20,7 +20,7 @@ use crate::{
 
 use super::{
     api_error::{ApiError, ApiJson},
-    auth::{BasicAuthExtractor, OptionalBasicAuth},
+    auth::{ApiAuth, OptionalApiAuth, TradeScope},
     feed::MarketUpdate,
 };
 
@@ -33,6 +33,8 @@ pub struct GetOrderParams {
     pub market_id: Option<u32>,
     pub user_id: Option<u32>,
     pub before: Option<i64>,
+    /// Your open order with this client order id. Requires authentication.
+    pub client_order_id: Option<i64>,
     #[serde(default = "default_limit")]
     pub limit: u32,
 }
@@ -49,11 +51,12 @@ pub struct GetOrderParams {
     ),
     security(
         (),
-        ("basic_auth" = [])
+        ("basic_auth" = []),
+        ("api_key" = [])
     )
 )]
 pub async fn get(
-    OptionalBasicAuth(user): OptionalBasicAuth,
+    OptionalApiAuth(user): OptionalApiAuth,
     State(state): State<AppState>,
     Query(params): Query<GetOrderParams>,
 ) -> Response {
@@ -67,6 +70,15 @@ pub async fn get(
         query.push(" AND user_id = ");
         query.push_bind(user_id);
     }
+    if let Some(client_order_id) = params.client_order_id {
+        let Some(user) = user else {
+            return ApiError::Authentication.into_response();
+        };
+        query.push(" AND user_id = ");
+        query.push_bind(user.id);
+        query.push(" AND client_order_id = ");
+        query.push_bind(client_order_id);
+    }
     if let Some(after) = params.before {
         query.push(" AND created_at < ");
         query.push_bind(after);
@@ -97,6 +109,12 @@ pub enum TimeInForce {
     IOC,
     /// Don't take liquidity.
     POST,
+    /// Fill or kill. Rejected unless it can be filled in full immediately.
+    FOK,
+    /// All or none. Only trades for its whole remaining quantity.
+    AON,
+    /// Good till time. Cancelled at `expires_at` if it is still resting.
+    GTT,
 }
 
 /// Submit order
@@ -110,12 +128,14 @@ pub enum TimeInForce {
         (status = 200, description = "Order successfully submitted", body = MarketUpdate)
     ),
     security(
-        ("basic_auth" = [])
+        ("basic_auth" = []),
+        ("api_key" = []),
+        ("signature" = [])
     )
 )]
 pub async fn post(
     State(state): State<AppState>,
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
     ApiJson(order): ApiJson<OrderRequest>,
 ) -> Response {
     let (req, recv) = MatcherRequest::submit(user.id, order.into());
@@ -123,7 +143,7 @@ pub async fn post(
     let response = recv
         .await
         .expect("Sender dropped")
-        .map_err(|err| ApiError::MatcherRequest(err));
+        .map_err(ApiError::MatcherRequest);
 
     match response {
         Ok(market) => Json(MarketUpdate::from(market)).into_response(),
@@ -143,7 +163,7 @@ pub async fn post(
 )]
 pub async fn delete(
     State(state): State<AppState>,
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
 ) -> impl IntoResponse {
     let Ok(orders) = models::order::Order::get_for_user(&state.pool, user.id).await else {
         return StatusCode::INTERNAL_SERVER_ERROR.into_response();
@@ -176,12 +196,14 @@ pub async fn delete(
         (status = 200, description = "Deleted all orders")
     ),
     security(
-        ("basic_auth" = [])
+        ("basic_auth" = []),
+        ("api_key" = []),
+        ("signature" = [])
     )
 )]
 pub async fn delete_by_id(
     State(state): State<AppState>,
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
     Path(order_id): Path<OrderId>,
 ) -> impl IntoResponse {
     let mut deleted = vec![];
@@ -196,3 +218,86 @@ pub async fn delete_by_id(
 
     Json(json!({"deleted": deleted})).into_response()
 }
+
+/// Cancel order by client order id
+///
+/// Submit cancel request for your open order with the client order id.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/orders/client/:client_order_id",
+    params(
+        ("client_order_id" = i64, Path, description = "Client order ID")
+    ),
+    responses(
+        (status = 200, description = "Deleted the order")
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = []),
+        ("signature" = [])
+    )
+)]
+pub async fn delete_by_client_order_id(
+    State(state): State<AppState>,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
+    Path(client_order_id): Path<ClientOrderId>,
+) -> impl IntoResponse {
+    let mut deleted = vec![];
+
+    let (req, recv) = MatcherRequest::cancel_by_client_order_id(user.id, client_order_id);
+    state.cmd_send.send(req).await.expect("Receiver dropped");
+    let resp = recv.await.expect("Sender dropped");
+
+    if let Ok(lobster::MarketUpdate::RemoveOrder { id, .. }) = resp {
+        deleted.push(id);
+    }
+
+    Json(json!({"deleted": deleted})).into_response()
+}
+
+/// The fields of a resting order to change. Omitted fields are kept.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AmendRequest {
+    /// The new remaining quantity. Zero cancels the order.
+    pub quantity: Option<u32>,
+    /// The new price.
+    pub price: Option<u16>,
+}
+
+/// Amend order
+///
+/// Change the price or remaining quantity of a resting order.
+/// Reducing the quantity at the same price keeps the order's queue priority.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/orders/:id",
+    params(
+        ("id" = i64, Path, description = "Order ID")
+    ),
+    request_body = AmendRequest,
+    responses(
+        (status = 200, description = "Order successfully amended", body = MarketUpdate)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn patch(
+    State(state): State<AppState>,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
+    Path(order_id): Path<OrderId>,
+    ApiJson(amend): ApiJson<AmendRequest>,
+) -> Response {
+    let (req, recv) = MatcherRequest::amend(user.id, order_id, amend.quantity, amend.price);
+    state.cmd_send.send(req).await.expect("Receiver dropped");
+    let response = recv
+        .await
+        .expect("Sender dropped")
+        .map_err(ApiError::MatcherRequest);
+
+    match response {
+        Ok(market) => Json(MarketUpdate::from(market)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
@@ -9,18 +9,19 @@ use crate::{
     models::position::{Position, PositionParams},
 };
 
-use super::auth::OptionalBasicAuth;
+use super::auth::OptionalApiAuth;
 
 /// Get user info.
 #[utoipa::path(
     get,
     path = "/api/v1/positions",
     security(
-        ("basic_auth" = [])
+        ("basic_auth" = []),
+        ("api_key" = [])
     )
 )]
 pub async fn get(
-    OptionalBasicAuth(user): OptionalBasicAuth,
+    _: OptionalApiAuth,
     State(state): State<AppState>,
     Query(params): Query<PositionParams>,
 ) -> impl IntoResponse {
@@ -0,0 +1,94 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::{error, info};
+
+use crate::{
+    app_state::AppState, models::risk_limit::RiskLimit,
+    services::matcher_request::MatcherRequest,
+};
+
+use super::{
+    api_error::{ApiError, ApiJson},
+    auth::{AdminRole, RoleAuth},
+};
+
+/// List risk limits
+///
+/// Lists the default risk limits and every user's and market's overrides.
+#[utoipa::path(
+    get,
+    path = "/api/v1/risk-limits",
+    responses(
+        (status = 200, description = "The risk limits", body = [RiskLimit])
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get(_auth: RoleAuth<AdminRole>, State(state): State<AppState>) -> Response {
+    match RiskLimit::get_all(&state.pool).await {
+        Ok(limits) => Json(limits).into_response(),
+        Err(err) => {
+            error!("Failed to get risk limits: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Set risk limits
+///
+/// Replaces the risk limits of `user_id`, of `market_id`, or the defaults if neither is set.
+/// Orders are checked against the user's limits, then the market's, then the defaults.
+/// Setting no limits removes the override.
+#[utoipa::path(
+    put,
+    path = "/api/v1/risk-limits",
+    request_body = RiskLimit,
+    responses(
+        (status = 200, description = "Risk limits set", body = RiskLimit)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn put(
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    State(state): State<AppState>,
+    ApiJson(limit): ApiJson<RiskLimit>,
+) -> Response {
+    if limit.user_id.is_some() && limit.market_id.is_some() {
+        return ApiError::InvalidRiskLimit("Set limits for a user or a market, not both".to_string())
+            .into_response();
+    }
+    if limit.max_order_notional.is_some_and(|max| max < 0)
+        || limit.max_position.is_some_and(|max| max < 0)
+    {
+        return ApiError::InvalidRiskLimit("Limits can't be negative".to_string()).into_response();
+    }
+
+    match limit.set(&state.pool).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.is_foreign_key_violation() => {
+            return ApiError::InvalidRiskLimit("User or market not found".to_string())
+                .into_response();
+        }
+        Err(err) => {
+            error!("Failed to set risk limits: {:?}", err);
+            return ApiError::InternalServerError.into_response();
+        }
+    }
+    info!(admin = admin.username, ?limit, "Set risk limits");
+
+    let req = MatcherRequest::SetRiskLimits {
+        user: limit.user_id,
+        market: limit.market_id,
+        limits: limit.limits(),
+    };
+    state.cmd_send.send(req).await.unwrap();
+    Json(limit).into_response()
+}
@@ -0,0 +1,319 @@
+//! Authenticated websocket trading sessions.
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Query, State, WebSocketUpgrade,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::time::{sleep_until, Instant};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+use lobster::{ClientOrderId, OrderId, UserId};
+
+use super::{
+    api_error::ApiError,
+    auth::{ApiAuth, TradeScope},
+    feed::{send, MarketUpdate},
+    order_request::OrderRequest,
+};
+use crate::app_state::AppState;
+use crate::services::matcher::FeedUpdate;
+use crate::services::matcher_request::{MatcherRequest, OrderAction};
+
+/// The longest a session can go without a heartbeat, in seconds.
+const MAX_HEARTBEAT_TIMEOUT: u64 = 300;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SessionParams {
+    /// Cancel all of the user's resting orders when the session ends.
+    #[serde(default)]
+    pub cancel_on_disconnect: bool,
+    /// End the session if nothing is received for this many seconds.
+    pub heartbeat_timeout: Option<u64>,
+}
+
+/// Sent by the client.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRequest {
+    /// Keeps the session alive. Answered with a `heartbeat`.
+    Heartbeat,
+    /// Submit an order.
+    NewOrder {
+        /// Echoed in the `ack` or `reject`.
+        req_id: u64,
+        order: OrderRequest,
+    },
+    /// Cancel a resting order.
+    Cancel { req_id: u64, id: OrderId },
+    /// Cancel the resting order with a client order id.
+    CancelByClientOrderId {
+        req_id: u64,
+        client_order_id: ClientOrderId,
+    },
+    /// Change the price or remaining quantity of a resting order. Omitted fields are kept.
+    Amend {
+        req_id: u64,
+        id: OrderId,
+        quantity: Option<u32>,
+        price: Option<u16>,
+    },
+}
+
+/// Sent by the server.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum SessionMessage {
+    Heartbeat,
+    /// The request with `req_id` was accepted, and made the update with sequence number `seq`.
+    Ack {
+        req_id: u64,
+        seq: u64,
+        update: MarketUpdate,
+    },
+    /// The request with `req_id` was rejected by the matching engine.
+    Reject { req_id: u64, reason: String },
+    /// One of the user's resting orders traded in the update with sequence number `seq`.
+    Fill {
+        seq: u64,
+        market: u32,
+        tick: u32,
+        /// The id of the resting order.
+        id: i64,
+        quantity: u32,
+        price: u16,
+        is_buy: bool,
+        /// Whether the order was fully filled.
+        done: bool,
+    },
+    /// A request couldn't be handled.
+    Error { message: String },
+}
+
+/// Start a trading session.
+///
+/// Orders can be submitted, cancelled and amended with client-assigned
+/// `req_id`s. Each request is answered with an `ack` or a `reject`, and a `fill`
+/// is sent whenever one of the user's resting orders trades. These are sent in
+/// the order the matching engine handled them. The session ends if it falls
+/// too far behind.
+///
+/// With `cancel_on_disconnect` set, all of the user's resting orders are
+/// cancelled when the connection closes or drops. With `heartbeat_timeout` set,
+/// the session ends if no message, including a websocket ping, arrives within
+/// that many seconds. Send `heartbeat` requests to keep it alive.
+///
+/// Orders are cancelled whichever session of the user placed them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/session",
+    params(SessionParams),
+    request_body = SessionRequest,
+    responses(
+        (status = 200, description = "Start a trading session", body = SessionMessage)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get(
+    State(state): State<AppState>,
+    ApiAuth { user, .. }: ApiAuth<TradeScope>,
+    Query(params): Query<SessionParams>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if params
+        .heartbeat_timeout
+        .is_some_and(|timeout| timeout == 0 || timeout > MAX_HEARTBEAT_TIMEOUT)
+    {
+        let reason = format!("heartbeat_timeout must be from 1 to {MAX_HEARTBEAT_TIMEOUT}");
+        return ApiError::InvalidSession(reason).into_response();
+    }
+    ws.on_upgrade(move |socket| handle_socket(state, socket, user.id, params))
+}
+
+async fn handle_socket(state: AppState, socket: WebSocket, user: UserId, params: SessionParams) {
+    let mut session = Session {
+        state,
+        socket,
+        user,
+        timeout: params.heartbeat_timeout.map(Duration::from_secs),
+        pending: None,
+    };
+    let _ = session.run().await;
+
+    if params.cancel_on_disconnect {
+        info!("Session of user={user} ended, cancelling their orders");
+        let req = MatcherRequest::CancelUserOrders { user };
+        session.state.cmd_send.send(req).await.expect("Receiver dropped");
+    }
+}
+
+struct Session {
+    state: AppState,
+    socket: WebSocket,
+    user: UserId,
+    /// How long to wait for a message before ending the session.
+    timeout: Option<Duration>,
+    /// A feed update received while catching up to a response, not handled yet.
+    pending: Option<FeedUpdate>,
+}
+
+impl Session {
+    /// Handles requests and feed updates until the connection closes or the heartbeat times out.
+    async fn run(&mut self) -> Result<(), axum::Error> {
+        let mut last_heard = Instant::now();
+        loop {
+            if let Some(update) = self.pending.take() {
+                self.on_update(&update).await?;
+            }
+            let deadline = self.timeout.map(|timeout| last_heard + timeout);
+            tokio::select! {
+                message = self.socket.recv() => {
+                    last_heard = Instant::now();
+                    match message {
+                        Some(Ok(Message::Text(text))) => self.on_request(&text).await?,
+                        Some(Ok(Message::Close(_)) | Err(_)) | None => return Ok(()),
+                        Some(Ok(_)) => {}
+                    }
+                }
+                update = self.state.feed_receive.recv() => match update {
+                    Ok(update) => self.on_update(&update).await?,
+                    Err(RecvError::Lagged(_)) => return self.fell_behind().await,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                () = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Sends a `fill` for each of the user's resting orders that traded in the update.
+    async fn on_update(&mut self, update: &FeedUpdate) -> Result<(), axum::Error> {
+        let (lobster::MarketUpdate::AddOrder {
+            tick,
+            market,
+            order,
+            fills,
+            ..
+        }
+        | lobster::MarketUpdate::AmendOrder {
+            tick,
+            market,
+            order,
+            fills,
+            ..
+        }) = &update.update
+        else {
+            return Ok(());
+        };
+        for fill in fills.iter().filter(|fill| fill.user == self.user) {
+            let message = SessionMessage::Fill {
+                seq: update.seq,
+                market: *market,
+                tick: *tick,
+                id: fill.id,
+                quantity: fill.quantity,
+                price: fill.price,
+                is_buy: !order.side.is_buy(),
+                done: fill.done,
+            };
+            send(&mut self.socket, &message).await?;
+        }
+        Ok(())
+    }
+
+    /// Handles the feed updates published before the update with sequence number `seq`.
+    /// They were all published before the matching engine responded, so none are waited for.
+    async fn catch_up(&mut self, seq: u64) -> Result<(), axum::Error> {
+        loop {
+            match self.state.feed_receive.try_recv() {
+                Ok(update) if update.seq < seq => self.on_update(&update).await?,
+                Ok(update) => {
+                    self.pending = Some(update);
+                    return Ok(());
+                }
+                Err(TryRecvError::Lagged(_)) => return self.fell_behind().await,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    /// Ends the session, since fills were missed.
+    async fn fell_behind(&mut self) -> Result<(), axum::Error> {
+        let message = "session fell behind the feed".to_string();
+        send(&mut self.socket, &SessionMessage::Error { message }).await?;
+        Err(axum::Error::new("session fell behind the feed"))
+    }
+
+    /// Sends an order action to the matching engine, then its `ack` or `reject`
+    /// after the fills that came before it.
+    async fn order_action(&mut self, req_id: u64, action: OrderAction) -> Result<(), axum::Error> {
+        let (req, recv) = MatcherRequest::session_order(self.user, action);
+        self.state.cmd_send.send(req).await.expect("Receiver dropped");
+        let (seq, res) = recv.await.expect("Sender dropped");
+        match res {
+            Ok(update) => {
+                self.catch_up(seq).await?;
+                let update = MarketUpdate::from(update);
+                send(&mut self.socket, &SessionMessage::Ack { req_id, seq, update }).await
+            }
+            Err(reason) => {
+                // rejected after the update with sequence number `seq`
+                self.catch_up(seq + 1).await?;
+                let reason = format!("{reason:?}");
+                send(&mut self.socket, &SessionMessage::Reject { req_id, reason }).await
+            }
+        }
+    }
+
+    async fn on_request(&mut self, text: &str) -> Result<(), axum::Error> {
+        let request = match serde_json::from_str::<SessionRequest>(text) {
+            Ok(request) => request,
+            Err(err) => {
+                let message = format!("invalid request: {err}");
+                return send(&mut self.socket, &SessionMessage::Error { message }).await;
+            }
+        };
+        match request {
+            SessionRequest::Heartbeat => send(&mut self.socket, &SessionMessage::Heartbeat).await,
+            SessionRequest::NewOrder { req_id, order } => {
+                self.order_action(req_id, OrderAction::Submit(order.into()))
+                    .await
+            }
+            SessionRequest::Cancel { req_id, id } => {
+                self.order_action(req_id, OrderAction::Cancel(id)).await
+            }
+            SessionRequest::CancelByClientOrderId {
+                req_id,
+                client_order_id,
+            } => {
+                let action = OrderAction::CancelByClientOrderId(client_order_id);
+                self.order_action(req_id, action).await
+            }
+            SessionRequest::Amend {
+                req_id,
+                id,
+                quantity,
+                price,
+            } => {
+                let action = OrderAction::Amend {
+                    order: id,
+                    quantity,
+                    price,
+                };
+                self.order_action(req_id, action).await
+            }
+        }
+    }
+}
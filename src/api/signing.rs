@@ -0,0 +1,224 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{OriginalUri, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use lobster::Timestamp;
+use serde::Serialize;
+use serde_json::json;
+use sha2::Sha256;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::{
+    app_state::{current_time_micros, AppState},
+    models::user::User,
+};
+
+use super::{
+    api_error::ApiError,
+    auth::{AdminScope, ApiAuth},
+};
+
+/// The username of the user signing the request.
+pub const USERNAME_HEADER: &str = "X-QP-Username";
+/// When the request was made, in milliseconds since the Unix epoch.
+pub const TIMESTAMP_HEADER: &str = "X-QP-Timestamp";
+/// How long after `X-QP-Timestamp` the request is valid for, in milliseconds.
+pub const RECV_WINDOW_HEADER: &str = "X-QP-Recv-Window";
+/// The hex encoded HMAC-SHA256 of the request.
+pub const SIGNATURE_HEADER: &str = "X-QP-Signature";
+
+/// The receive window if the request doesn't set one, in milliseconds.
+const DEFAULT_RECV_WINDOW: i64 = 5_000;
+/// The longest receive window allowed, in milliseconds.
+const MAX_RECV_WINDOW: i64 = 60_000;
+/// How far ahead of the server's clock a timestamp can be, in milliseconds.
+const MAX_CLOCK_SKEW: i64 = 1_000;
+/// The largest body a signed request can have.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// The user who signed the request, put in the request extensions by `verify`.
+#[derive(Clone)]
+pub struct SignedUser(pub User);
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SigningSecretResponse {
+    /// The key to sign requests with. It can't be shown again.
+    secret: String,
+}
+
+/// Returns the bytes a request's signature is made over:
+/// the timestamp, receive window, method and path with query,
+/// each followed by a newline, and then the body.
+fn signed_payload(
+    timestamp: i64,
+    recv_window: i64,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Vec<u8> {
+    let mut payload = format!("{timestamp}\n{recv_window}\n{method}\n{path}\n").into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+/// Returns a header's value, or `None` if it is missing or isn't text.
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Checks the signature of requests with an `X-QP-Signature` header,
+/// and adds the `SignedUser` to the request if it is valid.
+/// Requests without the header are passed through for the other extractors to authenticate.
+pub async fn verify(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !request.headers().contains_key(SIGNATURE_HEADER) {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_BODY_SIZE).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+    // Routes are nested under `/api/v1`, which is stripped from `parts.uri`.
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or_else(|| parts.uri.clone(), |OriginalUri(uri)| uri.clone());
+    let path = uri
+        .path_and_query()
+        .map_or_else(|| uri.path(), |path| path.as_str());
+    match check_signature(&state, &parts.headers, parts.method.as_str(), path, &body).await {
+        Ok(user) => {
+            parts.extensions.insert(SignedUser(user));
+            next.run(Request::from_parts(parts, Body::from(body)))
+                .await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Returns the user who signed the request, if the signature is valid and hasn't been seen before.
+async fn check_signature(
+    state: &AppState,
+    headers: &HeaderMap,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<User, ApiError> {
+    let username = header(headers, USERNAME_HEADER).ok_or(ApiError::Authentication)?;
+    let timestamp: i64 = header(headers, TIMESTAMP_HEADER)
+        .and_then(|timestamp| timestamp.parse().ok())
+        .ok_or(ApiError::Authentication)?;
+    let recv_window = match header(headers, RECV_WINDOW_HEADER) {
+        None => DEFAULT_RECV_WINDOW,
+        Some(window) => window
+            .parse()
+            .ok()
+            .filter(|window| (1..=MAX_RECV_WINDOW).contains(window))
+            .ok_or(ApiError::Authentication)?,
+    };
+    let signature = header(headers, SIGNATURE_HEADER)
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or(ApiError::InvalidSignature)?;
+
+    let user = match User::get_by_username(&state.pool, username).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::Authentication),
+        Err(err) => {
+            error!(?err, "Failed to get user");
+            return Err(ApiError::InternalServerError);
+        }
+    };
+    let secret = user
+        .signing_secret
+        .as_deref()
+        .ok_or(ApiError::Authentication)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Invariant");
+    mac.update(&signed_payload(timestamp, recv_window, method, path, body));
+    mac.verify_slice(&signature)
+        .map_err(|_| ApiError::InvalidSignature)?;
+
+    let now = current_time_micros();
+    let sent = timestamp.saturating_mul(1000);
+    let expires: Timestamp = timestamp.saturating_add(recv_window).saturating_mul(1000);
+    if sent > now.saturating_add(MAX_CLOCK_SKEW * 1000) || now > expires {
+        return Err(ApiError::RequestExpired);
+    }
+
+    let mut seen = state.seen_signatures.lock().expect("Invariant");
+    seen.retain(|_, &mut expiry| expiry >= now);
+    if seen.insert(signature, expires).is_some() {
+        return Err(ApiError::ReplayedRequest);
+    }
+    drop(seen);
+    Ok(user)
+}
+
+/// Create signing secret
+///
+/// Creates a secret for signing requests, replacing any the user had.
+///
+/// Signed requests send the headers `X-QP-Username`, `X-QP-Timestamp` (milliseconds since
+/// the Unix epoch) and `X-QP-Signature`, and optionally `X-QP-Recv-Window`
+/// (milliseconds, default 5000, at most 60000).
+/// The signature is the hex encoded HMAC-SHA256, keyed with the secret, of the timestamp,
+/// receive window, method and path with query, each followed by a newline, and then the body.
+///
+/// Requests are rejected once the receive window has passed, and if they have been seen before.
+/// Placing and cancelling orders accept signed requests.
+#[utoipa::path(
+    post,
+    path = "/api/v1/signing-secret",
+    responses(
+        (status = 200, description = "Signing secret created", body = SigningSecretResponse)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn post(
+    ApiAuth { user, .. }: ApiAuth<AdminScope>,
+    State(state): State<AppState>,
+) -> Response {
+    let secret = hex::encode(rand::random::<[u8; 32]>());
+    match User::set_signing_secret(&state.pool, user.id, Some(&secret)).await {
+        Ok(()) => Json(SigningSecretResponse { secret }).into_response(),
+        Err(err) => {
+            error!("Failed to set signing secret: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
+
+/// Delete signing secret
+///
+/// Deletes the user's signing secret, so signed requests are no longer accepted.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/signing-secret",
+    responses(
+        (status = 200, description = "Signing secret deleted")
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn delete(
+    ApiAuth { user, .. }: ApiAuth<AdminScope>,
+    State(state): State<AppState>,
+) -> Response {
+    match User::set_signing_secret(&state.pool, user.id, None).await {
+        Ok(()) => Json(json!({"deleted": true})).into_response(),
+        Err(err) => {
+            error!("Failed to delete signing secret: {:?}", err);
+            ApiError::InternalServerError.into_response()
+        }
+    }
+}
@@ -3,22 +3,52 @@ use axum::{
     extract::{Path, State},
     Json,
 };
-use lobster::UserId;
+use lobster::{MatcherResult, UserId};
 use serde::Deserialize;
 use serde_json::json;
-use tracing::error;
+use tracing::{error, info};
 use utoipa::ToSchema;
 
-use crate::{app_state::AppState, models::user::User, services::matcher_request::MatcherRequest};
+use crate::{
+    api::feed::MarketUpdate,
+    app_state::AppState,
+    models::user::{Role, User},
+    services::matcher_request::MatcherRequest,
+};
 
-use super::{api_error::ApiError, auth::OptionalBasicAuth};
-use super::auth::BasicAuthExtractor;
+use super::{
+    api_error::{ApiError, ApiJson},
+    auth::OptionalApiAuth,
+};
+use super::auth::{AdminRole, RoleAuth};
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct DepositPayload {
     pub amount: i64,
 }
 
+/// The longest memo an adjustment can have.
+const MAX_MEMO_LENGTH: usize = 500;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WithdrawPayload {
+    #[schema(minimum = 1)]
+    pub amount: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AdjustmentPayload {
+    /// Added to the balance. Negative to reduce it.
+    pub amount: i64,
+    /// Why the balance is adjusted.
+    pub memo: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RolePayload {
+    pub role: Role,
+}
+
 /// Deposit.
 ///
 /// Increase a users balance.
@@ -26,20 +56,17 @@ pub struct DepositPayload {
     post,
     path = "/api/v1/deposit/:user_id",
     security(
-        ("basic_auth" = [])
+        ("basic_auth" = []),
+        ("api_key" = [])
     )
 )]
 pub async fn deposit(
     State(state): State<AppState>,
-    BasicAuthExtractor(user): BasicAuthExtractor,
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
     Path(user_id): Path<UserId>,
     Json(payload): Json<DepositPayload>,
 ) -> impl IntoResponse {
     // this is a post request because it creates a new entry in transactions table.
-    if user.username != "admin" {
-        return ApiError::Authorization.into_response();
-    }
-
     if payload.amount <= 0 {
         return Json(json!({"error": "amount must be positive"})).into_response();
     }
@@ -55,6 +82,7 @@ pub async fn deposit(
         }
     };
 
+    info!(admin = admin.username, user_id, amount = payload.amount, "Depositing");
     let req = MatcherRequest::deposit(user_id, payload.amount);
     state.cmd_send.send(req).await.unwrap();
 
@@ -64,10 +92,101 @@ pub async fn deposit(
     return Json(user).into_response();
 }
 
+/// Returns an error if the user doesn't exist, so the engine doesn't create them.
+async fn check_user_exists(state: &AppState, user_id: UserId) -> Result<(), ApiError> {
+    match User::get_by_id(&state.pool, user_id).await {
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::UserNotFound),
+        Err(e) => {
+            error!("Failed to get user: {:?}", e);
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+fn balance_response(result: MatcherResult) -> axum::response::Response {
+    match result {
+        Ok(update) => Json(MarketUpdate::from(update)).into_response(),
+        Err(err) => ApiError::MatcherRequest(err).into_response(),
+    }
+}
+
+/// Withdraw.
+///
+/// Decrease a user's balance. The amount can't exceed what the user has available,
+/// so it is rejected with `InsufficientFunds` while their orders need it.
+#[utoipa::path(
+    post,
+    path = "/api/v1/withdraw/:user_id",
+    request_body = WithdrawPayload,
+    responses(
+        (status = 200, description = "Balance withdrawn", body = MarketUpdate)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn withdraw(
+    State(state): State<AppState>,
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    Path(user_id): Path<UserId>,
+    ApiJson(payload): ApiJson<WithdrawPayload>,
+) -> impl IntoResponse {
+    if let Err(err) = check_user_exists(&state, user_id).await {
+        return err.into_response();
+    }
+
+    info!(admin = admin.username, user_id, amount = payload.amount, "Withdrawing");
+    let (req, recv) = MatcherRequest::withdraw(user_id, payload.amount);
+    state.cmd_send.send(req).await.unwrap();
+    balance_response(recv.await.unwrap())
+}
+
+/// Adjust balance.
+///
+/// Corrects a user's balance by a signed amount, such as to reverse a mistaken deposit.
+/// The memo is recorded in the user's ledger. A reduction can't exceed what the user
+/// has available.
+#[utoipa::path(
+    post,
+    path = "/api/v1/adjustments/:user_id",
+    request_body = AdjustmentPayload,
+    responses(
+        (status = 200, description = "Balance adjusted", body = MarketUpdate)
+    ),
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn adjust(
+    State(state): State<AppState>,
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    Path(user_id): Path<UserId>,
+    ApiJson(payload): ApiJson<AdjustmentPayload>,
+) -> impl IntoResponse {
+    let memo = payload.memo.trim();
+    if memo.is_empty() || memo.len() > MAX_MEMO_LENGTH {
+        return ApiError::InvalidAdjustment(format!(
+            "memo must be between 1 and {MAX_MEMO_LENGTH} characters"
+        ))
+        .into_response();
+    }
+    if let Err(err) = check_user_exists(&state, user_id).await {
+        return err.into_response();
+    }
+
+    info!(admin = admin.username, user_id, amount = payload.amount, memo, "Adjusting balance");
+    let (req, recv) = MatcherRequest::adjust_balance(user_id, payload.amount, memo.to_string());
+    state.cmd_send.send(req).await.unwrap();
+    balance_response(recv.await.unwrap())
+}
+
 
 pub async fn get(
     State(state): State<AppState>,
-    OptionalBasicAuth(_user): OptionalBasicAuth,
+    OptionalApiAuth(_user): OptionalApiAuth,
     Path(username): Path<String>,
 ) -> impl IntoResponse {
 
@@ -83,4 +202,43 @@ pub async fn get(
     };
 
     return Json(user).into_response();
-}
\ No newline at end of file
+}
+
+/// Set role.
+///
+/// Sets what a user may do besides trading. Only admins can set roles.
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/:username/role",
+    request_body = RolePayload,
+    security(
+        ("basic_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn put_role(
+    State(state): State<AppState>,
+    RoleAuth { user: admin, .. }: RoleAuth<AdminRole>,
+    Path(username): Path<String>,
+    Json(payload): Json<RolePayload>,
+) -> impl IntoResponse {
+    let mut user = match User::get_by_username(&state.pool, &username).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => {
+            return ApiError::UserNotFound.into_response();
+        }
+        Err(e) => {
+            error!("Failed to get user: {:?}", e);
+            return ApiError::InternalServerError.into_response();
+        }
+    };
+
+    if let Err(e) = User::set_role(&state.pool, user.id, payload.role).await {
+        error!("Failed to set role: {:?}", e);
+        return ApiError::InternalServerError.into_response();
+    }
+    info!(admin = admin.username, user = user.username, role = ?payload.role, "Set role");
+    user.role = payload.role;
+
+    Json(user).into_response()
+}
@@ -1,16 +1,31 @@
-use lobster::{MarketUpdate, Timestamp};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use lobster::Timestamp;
 use sqlx::SqlitePool;
 use tokio::sync::{broadcast, mpsc};
 
-use crate::services::{book_service::MarketData, matcher_request::MatcherRequest};
+use crate::services::{
+    book_service::{BookUpdate, SnapshotRequest},
+    matcher::FeedUpdate,
+    matcher_request::MatcherRequest,
+};
 
 pub struct AppState {
     pub pool: SqlitePool,
     /// Sending requests to matching engine.
     pub cmd_send: mpsc::Sender<MatcherRequest>,
     /// Receiving event data markets.
-    pub feed_receive: broadcast::Receiver<MarketUpdate>,
-    pub book_receive: broadcast::Receiver<MarketData>,
+    pub feed_receive: broadcast::Receiver<FeedUpdate>,
+    pub book_receive: broadcast::Receiver<BookUpdate>,
+    /// Requesting order book snapshots from the book service.
+    pub snapshot_send: mpsc::Sender<SnapshotRequest>,
+    /// Signatures of signed API requests still in their receive window,
+    /// with the time they expire, to reject replays.
+    pub seen_signatures: Arc<Mutex<HashMap<Vec<u8>, Timestamp>>>,
+    /// The reverse proxies whose `X-Forwarded-For` headers are believed.
+    pub trusted_proxies: Arc<[IpAddr]>,
 }
 
 impl Clone for AppState {
@@ -20,6 +35,9 @@ impl Clone for AppState {
             cmd_send: self.cmd_send.clone(),
             feed_receive: self.feed_receive.resubscribe(),
             book_receive: self.book_receive.resubscribe(),
+            snapshot_send: self.snapshot_send.clone(),
+            seen_signatures: self.seen_signatures.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
         }
     }
 }
@@ -28,14 +46,19 @@ impl AppState {
     pub fn new(
         pool: SqlitePool,
         cmd_send: mpsc::Sender<MatcherRequest>,
-        feed_receive: broadcast::Receiver<MarketUpdate>,
-        book_receive: broadcast::Receiver<MarketData>,
+        feed_receive: broadcast::Receiver<FeedUpdate>,
+        book_receive: broadcast::Receiver<BookUpdate>,
+        snapshot_send: mpsc::Sender<SnapshotRequest>,
+        trusted_proxies: Vec<IpAddr>,
     ) -> Self {
         Self {
             pool,
             cmd_send,
             feed_receive,
             book_receive,
+            snapshot_send,
+            seen_signatures: Arc::default(),
+            trusted_proxies: trusted_proxies.into(),
         }
     }
 }
@@ -2,20 +2,20 @@
 mod api;
 mod app_state;
 mod models;
+mod replay;
 mod services;
 mod util;
 mod web;
 
-use crate::services::book_service::MarketData;
+use crate::services::{book_service::BookUpdate, matcher::FeedUpdate};
 use app_state::AppState;
-use lobster::MarketUpdate;
 use std::net::SocketAddr;
 use tokio::{
     net::TcpListener,
     sync::{broadcast, mpsc},
 };
 use tracing::info;
-use util::{connect_to_database, register_panic_hook};
+use util::{connect_to_database, register_panic_hook, trusted_proxies};
 
 fn configure_logging() {
     let subscriber = tracing_subscriber::fmt().finish();
@@ -23,17 +23,42 @@ fn configure_logging() {
     tracing::subscriber::set_global_default(subscriber).unwrap();
 }
 
+const USAGE: &str = "usage:
+    qposit                                  start the server
+    qposit replay <live> <target> <log>...  rebuild <target> from market data feed logs
+    qposit diff <rebuilt> <live>            print drift between two databases";
+
 #[tokio::main]
 async fn main() {
     register_panic_hook();
 
     configure_logging();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => serve().await,
+        [command, live, target, logs @ ..] if command == "replay" && !logs.is_empty() => {
+            replay::rebuild(live, target, logs).await;
+        }
+        [command, rebuilt, live] if command == "diff" => {
+            if replay::diff(rebuilt, live).await {
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
+
+async fn serve() {
     let pool = connect_to_database().await;
 
     let (cmd_send, cmd_receive) = mpsc::channel(32);
-    let (feed_send, feed_receive) = broadcast::channel::<MarketUpdate>(32);
-    let (book_send, book_receive) = broadcast::channel::<MarketData>(32);
+    let (feed_send, feed_receive) = broadcast::channel::<FeedUpdate>(32);
+    let (book_send, book_receive) = broadcast::channel::<BookUpdate>(32);
+    let (snapshot_send, snapshot_receive) = mpsc::channel(32);
 
     services::writer::start_writer_service(
         pool.clone(),
@@ -44,13 +69,24 @@ async fn main() {
         cmd_receive,
         feed_send,
     );
+    services::session_purger::start_session_purger(pool.clone());
+    services::market_scheduler::start_market_scheduler(pool.clone(), cmd_send.clone());
     services::book_service::start_book_service(
         pool.clone(),
         feed_receive.resubscribe(),
+        snapshot_receive,
         book_send,
+        cmd_send.clone(),
     );
 
-    let state = AppState::new(pool, cmd_send, feed_receive, book_receive);
+    let state = AppState::new(
+        pool,
+        cmd_send,
+        feed_receive,
+        book_receive,
+        snapshot_send,
+        trusted_proxies(),
+    );
 
     let app = web::router(state.clone()).merge(api::router(state));
 
@@ -0,0 +1,130 @@
+use std::net::IpAddr;
+
+use lobster::{Timestamp, UserId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+/// What an API key is allowed to do. Each scope allows everything the ones before it do.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, sqlx::Type, Serialize, Deserialize, ToSchema,
+)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// View orders, positions and trades.
+    Read,
+    /// Place, amend and cancel orders.
+    Trade,
+    /// Manage API keys, and use admin endpoints if the user is an admin.
+    Admin,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: UserId,
+    pub name: String,
+    /// The start of the key, to tell keys apart.
+    pub prefix: String,
+    pub scope: Scope,
+    /// The comma separated IP addresses allowed to use the key. Any address may if unset.
+    pub ip_allowlist: Option<String>,
+    pub created_at: Timestamp,
+}
+
+/// Hashes a key for storage. Keys are random, so they don't need a slow or salted hash.
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+impl ApiKey {
+    /// Creates a key for the user. Returns the key's id and the key itself,
+    /// which is not stored and can't be shown again.
+    pub async fn create(
+        db: &SqlitePool,
+        user_id: UserId,
+        name: &str,
+        scope: Scope,
+        ip_allowlist: Option<&str>,
+        created_at: Timestamp,
+    ) -> Result<(i64, String), sqlx::Error> {
+        let key = format!("qp_{:032x}", rand::random::<u128>());
+        let prefix = &key[..10];
+        let key_hash = hash_key(&key);
+        let id = sqlx::query!(
+            "INSERT INTO api_key (user_id, name, prefix, key_hash, scope, ip_allowlist, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            user_id,
+            name,
+            prefix,
+            key_hash,
+            scope,
+            ip_allowlist,
+            created_at,
+        )
+        .execute(db)
+        .await?
+        .last_insert_rowid();
+        Ok((id, key))
+    }
+
+    /// Looks a key up by its value.
+    pub async fn get_by_key(db: &SqlitePool, key: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM api_key WHERE key_hash = ?")
+            .bind(hash_key(key))
+            .fetch_optional(db)
+            .await
+    }
+
+    pub async fn get_all_for_user(db: &SqlitePool, user_id: UserId) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM api_key WHERE user_id = ? ORDER BY id")
+            .bind(user_id)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Revokes one of the user's keys. Returns the number of keys revoked.
+    pub async fn delete(db: &SqlitePool, user_id: UserId, id: i64) -> Result<u64, sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM api_key WHERE id = ? AND user_id = ?",
+            id,
+            user_id
+        )
+        .execute(db)
+        .await
+        .map(|row| row.rows_affected())
+    }
+
+    /// Whether a request from `ip` may use the key.
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        let Some(allowlist) = &self.ip_allowlist else {
+            return true;
+        };
+        allowlist
+            .split(',')
+            .filter_map(|allowed| allowed.parse::<IpAddr>().ok())
+            .any(|allowed| allowed == ip.to_canonical())
+    }
+}
+
+/// Parses a comma or whitespace separated list of IP addresses into the stored form.
+/// Returns `Ok(None)` if the list is empty.
+///
+/// # Errors
+///
+/// Returns the first entry that is not an IP address.
+pub fn parse_ip_allowlist(list: &str) -> Result<Option<String>, String> {
+    let ips = list
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|ip| !ip.is_empty())
+        .map(|ip| {
+            ip.parse::<IpAddr>()
+                .map(|ip| ip.to_canonical().to_string())
+                .map_err(|_| ip.to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((!ips.is_empty()).then(|| ips.join(",")))
+}
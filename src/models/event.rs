@@ -1,3 +1,4 @@
+use lobster::MarketId;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 
@@ -12,6 +13,11 @@ pub struct Event {
     pub description: String,
     pub created_at: i64,
     pub event_time: i64,
+    /// Whether exactly one of the markets resolves to 100%.
+    pub mutually_exclusive: bool,
+    /// Whether closing the markets at `event_time` cancels their resting orders.
+    #[serde(default)]
+    pub cancel_orders_on_close: bool,
 }
 
 impl Event {
@@ -27,15 +33,58 @@ impl Event {
             .await
     }
 
+    /// Returns the unresolved markets of each mutually exclusive event.
+    pub async fn get_exclusive_markets(db: &SqlitePool) -> Result<Vec<Vec<MarketId>>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT market.id as "id: MarketId", market.event_id
+            FROM market JOIN event ON market.event_id = event.id
+            WHERE event.mutually_exclusive = 1 AND market.outcome IS NULL
+            ORDER BY market.event_id, market.id"#
+        )
+        .fetch_all(db)
+        .await?;
+
+        let mut events: Vec<Vec<MarketId>> = Vec::new();
+        let mut last_event = None;
+        for row in rows {
+            if last_event != Some(row.event_id) {
+                events.push(Vec::new());
+                last_event = Some(row.event_id);
+            }
+            events.last_mut().expect("pushed above").push(row.id);
+        }
+        Ok(events)
+    }
+
+    /// Returns the events whose `event_time` is at or before `time`
+    /// and whose markets haven't been closed for it yet.
+    pub async fn get_due_for_close(db: &SqlitePool, time: i64) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM event WHERE closed = 0 AND event_time <= ?")
+            .bind(time)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Records that the event's markets were closed at `event_time`.
+    pub async fn set_closed(db: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE event SET closed = 1 WHERE id = ?", id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
     pub async fn insert(&self, db: &SqlitePool) -> Result<i64, sqlx::Error> {
         sqlx::query!(
-            "INSERT INTO event (slug, title, description, created_at, event_time)
-            VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO event (slug, title, description, created_at, event_time, mutually_exclusive,
+                cancel_orders_on_close)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
             self.slug,
             self.title,
             self.description,
             self.created_at,
             self.event_time,
+            self.mutually_exclusive,
+            self.cancel_orders_on_close,
         )
         .execute(db)
         .await
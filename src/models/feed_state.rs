@@ -0,0 +1,27 @@
+use sqlx::{Executor, Sqlite, SqlitePool};
+
+/// How far the database is into the market data feed.
+pub struct FeedState;
+
+impl FeedState {
+    /// Returns the sequence number of the last update recorded.
+    pub async fn get_seq(db: &SqlitePool) -> Result<u64, sqlx::Error> {
+        let seq = sqlx::query_scalar!("SELECT seq FROM feed_state WHERE id = 0")
+            .fetch_one(db)
+            .await?;
+        Ok(u64::try_from(seq).expect("seq is non-negative"))
+    }
+
+    /// Records the update with sequence number `seq`.
+    /// Call in the same transaction as the update.
+    pub async fn set_seq<E>(db: &mut E, seq: u64) -> Result<(), sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        let seq = i64::try_from(seq).expect("seq fits in i64");
+        sqlx::query!("UPDATE feed_state SET seq = ? WHERE id = 0", seq)
+            .execute(db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use lobster::{Balance, MarketId, OrderId, Timestamp, UserId};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, QueryBuilder, Sqlite, SqlitePool};
+use utoipa::{IntoParams, ToSchema};
+
+/// Why a user's balance changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerReason {
+    /// The balance from before the ledger was kept.
+    Opening,
+    Deposit,
+    Withdrawal,
+    /// An admin corrected the balance. The memo says why.
+    Adjustment,
+    /// The price of contracts bought or sold.
+    Trade,
+    /// Collateral for contracts created by selling more than the position.
+    Create,
+    /// Collateral returned by buying back short contracts.
+    Combine,
+    /// Complete sets across a mutually exclusive event converted into balance.
+    Merge,
+    /// The value of a position when its market resolved.
+    Resolve,
+    /// The cost basis refunded when a market was voided.
+    Void,
+}
+
+/// A change to a user's balance. A user's entries add up to their balance.
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub user_id: UserId,
+    /// Positive if the balance went up.
+    pub amount: Balance,
+    pub reason: LedgerReason,
+    /// Why an admin adjusted the balance.
+    pub memo: Option<String>,
+    pub market_id: Option<MarketId>,
+    /// The user's order, for trades.
+    pub order_id: Option<OrderId>,
+    pub trade_id: Option<i64>,
+    pub created_at: Timestamp,
+}
+
+const fn default_limit() -> u32 {
+    100
+}
+
+/// The most entries returned by one request.
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Deserialize, ToSchema, IntoParams)]
+pub struct LedgerParams {
+    /// Only admins can see other users' entries. Defaults to your own.
+    pub user_id: Option<UserId>,
+    pub market_id: Option<MarketId>,
+    /// Only entries older than this id, to get the next page.
+    pub before: Option<i64>,
+    /// At most 1000.
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+impl LedgerEntry {
+    #[must_use]
+    pub const fn new(
+        user_id: UserId,
+        amount: Balance,
+        reason: LedgerReason,
+        created_at: Timestamp,
+    ) -> Self {
+        Self {
+            id: 0,
+            user_id,
+            amount,
+            reason,
+            memo: None,
+            market_id: None,
+            order_id: None,
+            trade_id: None,
+            created_at,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_market(mut self, market_id: MarketId) -> Self {
+        self.market_id = Some(market_id);
+        self
+    }
+
+    #[must_use]
+    pub fn with_memo(mut self, memo: String) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_trade(mut self, trade_id: i64, order_id: OrderId) -> Self {
+        self.trade_id = Some(trade_id);
+        self.order_id = Some(order_id);
+        self
+    }
+
+    /// Appends the entry to the ledger, unless the balance didn't change.
+    pub async fn insert<E>(&self, db: &mut E) -> Result<(), sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        if self.amount == 0 {
+            return Ok(());
+        }
+        sqlx::query!(
+            "INSERT INTO ledger (user_id, amount, reason, memo, market_id, order_id, trade_id,
+                created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            self.user_id,
+            self.amount,
+            self.reason,
+            self.memo,
+            self.market_id,
+            self.order_id,
+            self.trade_id,
+            self.created_at,
+        )
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns a user's entries, newest first.
+    pub async fn get(
+        db: &SqlitePool,
+        user_id: UserId,
+        params: &LedgerParams,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let mut query = QueryBuilder::new("SELECT * FROM ledger WHERE user_id = ");
+        query.push_bind(user_id);
+
+        if let Some(market_id) = params.market_id {
+            query.push(" AND market_id = ");
+            query.push_bind(market_id);
+        }
+        if let Some(before) = params.before {
+            query.push(" AND id < ");
+            query.push_bind(before);
+        }
+        query.push(" ORDER BY id DESC LIMIT ");
+        query.push_bind(params.limit.min(MAX_LIMIT));
+
+        query.build_query_as::<Self>().fetch_all(db).await
+    }
+}
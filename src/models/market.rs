@@ -1,8 +1,42 @@
 use lobster::MarketId;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, sqlite::SqliteQueryResult, Executor, Sqlite, SqlitePool};
 use utoipa::ToSchema;
 
+/// Whether a market is trading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MarketState {
+    /// Orders can be placed and amended.
+    Open,
+    /// Trading is paused. Orders can only be cancelled.
+    Halted,
+    /// Trading has ended and the market is waiting to be resolved.
+    /// Orders can only be cancelled.
+    Closed,
+}
+
+impl From<lobster::MarketState> for MarketState {
+    fn from(state: lobster::MarketState) -> Self {
+        match state {
+            lobster::MarketState::Open => Self::Open,
+            lobster::MarketState::Halted => Self::Halted,
+            lobster::MarketState::Closed => Self::Closed,
+        }
+    }
+}
+
+impl From<MarketState> for lobster::MarketState {
+    fn from(state: MarketState) -> Self {
+        match state {
+            MarketState::Open => Self::Open,
+            MarketState::Halted => Self::Halted,
+            MarketState::Closed => Self::Closed,
+        }
+    }
+}
+
 #[derive(Debug, FromRow, Serialize, ToSchema)]
 pub struct Market {
     pub id: u32,
@@ -10,6 +44,13 @@ pub struct Market {
     pub title: String,
     /// The resolution price of this market, if it has been resolved.
     pub outcome: Option<u16>,
+    pub state: MarketState,
+    /// Whether the market was cancelled without an outcome, refunding every user.
+    pub voided: bool,
+    /// The outcome proposed for the market, settled once the dispute window ends.
+    pub proposed_outcome: Option<u16>,
+    /// When the proposed outcome can no longer be disputed.
+    pub dispute_ends_at: Option<i64>,
     /// The price of the last trade that ocurred in this market.
     pub last_price: Option<u16>,
     /// The best bid price.
@@ -45,6 +86,10 @@ impl Market {
                 market.event_id,
                 market.title,
                 market.outcome,
+                market.state,
+                market.voided,
+                market.proposed_outcome,
+                market.dispute_ends_at,
                 (
                     SELECT trade.price
                     FROM trade
@@ -83,6 +128,10 @@ impl Market {
                 market.event_id,
                 market.title,
                 market.outcome,
+                market.state,
+                market.voided,
+                market.proposed_outcome,
+                market.dispute_ends_at,
                 (
                     SELECT trade.price
                     FROM trade
@@ -104,7 +153,7 @@ impl Market {
                     SELECT SUM(quantity * price) FROM trade WHERE market.id = trade.market_id
                 ) AS volume
             FROM market
-            WHERE market.outcome IS NULL
+            WHERE market.outcome IS NULL AND market.voided = 0
             ",
         )
         .fetch_all(db)
@@ -127,4 +176,51 @@ impl Market {
         .execute(db)
         .await
     }
+
+    /// Marks a market voided, withdrawing any proposed outcome and its disputes.
+    pub async fn void<E>(db: &mut E, market_id: MarketId) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE market SET voided = 1, proposed_outcome = NULL, dispute_ends_at = NULL
+            WHERE id = ?;
+            DELETE FROM dispute WHERE market_id = ?;",
+            market_id,
+            market_id
+        )
+        .execute(db)
+        .await
+    }
+
+    pub async fn set_state<E>(
+        db: &mut E,
+        market_id: MarketId,
+        state: MarketState,
+    ) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE market SET state = ? WHERE id = ?",
+            state,
+            market_id
+        )
+        .execute(db)
+        .await
+    }
+
+    /// Returns the unresolved markets of an event that aren't closed.
+    pub async fn get_unclosed_for_event(
+        db: &SqlitePool,
+        event: i64,
+    ) -> Result<Vec<MarketId>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT id as "id: MarketId" FROM market
+            WHERE event_id = ? AND outcome IS NULL AND voided = 0 AND state != 'closed'"#,
+            event
+        )
+        .fetch_all(db)
+        .await
+    }
 }
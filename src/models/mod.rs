@@ -1,8 +1,13 @@
+pub mod api_key;
 pub mod event;
+pub mod feed_state;
 pub mod invite;
+pub mod ledger;
 pub mod market;
 pub mod order;
 pub mod position;
+pub mod resolution;
+pub mod risk_limit;
 pub mod session;
 pub mod trade;
 pub mod user;
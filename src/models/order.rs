@@ -1,5 +1,5 @@
 use lobster::{MarketId, Timestamp, UserId};
-use lobster::{OrderId, Side};
+use lobster::{ClientOrderId, OrderId, Side};
 use serde::Serialize;
 use sqlx::sqlite::SqliteQueryResult;
 use sqlx::{prelude::FromRow, Executor, Sqlite, SqlitePool};
@@ -16,6 +16,12 @@ pub struct Order {
     pub price: u16,
     pub is_buy: bool,
     pub status: String,
+    /// Only trades for its whole remaining quantity.
+    pub all_or_none: bool,
+    /// When a good-till-time order is cancelled if it is still resting.
+    pub expires_at: Option<Timestamp>,
+    /// The id the user gave the order.
+    pub client_order_id: Option<ClientOrderId>,
 }
 
 impl From<&Order> for lobster::Order {
@@ -26,6 +32,9 @@ impl From<&Order> for lobster::Order {
             order.price,
             Side::new(order.is_buy),
         )
+        .with_all_or_none(order.all_or_none)
+        .with_expiry(order.expires_at)
+        .with_client_order_id(order.client_order_id)
     }
 }
 
@@ -43,8 +52,9 @@ impl Order {
     {
         let is_buy = order.side.is_buy();
         sqlx::query!(
-            "INSERT INTO 'order' (id, created_at, market_id, user_id, quantity, remaining, price, is_buy, status)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'open')",
+            "INSERT INTO 'order' (id, created_at, market_id, user_id, quantity, remaining, price, is_buy, status,
+                all_or_none, expires_at, client_order_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'open', ?, ?, ?)",
             order.id,
             created_at,
             market_id,
@@ -53,12 +63,36 @@ impl Order {
             order.quantity,
             order.price,
             is_buy,
+            order.all_or_none,
+            order.expires_at,
+            order.client_order_id,
         )
         .execute(db)
         .await
         .map(|row| row.last_insert_rowid())
     }
 
+    /// Sets the price and remaining quantity of an amended order.
+    /// The filled quantity is preserved.
+    pub async fn amend<E>(db: &mut E, order: lobster::Order) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE 'order' SET
+                price = ?,
+                quantity = quantity - remaining + ?,
+                remaining = ?
+            WHERE id = ?",
+            order.price,
+            order.quantity,
+            order.quantity,
+            order.id,
+        )
+        .execute(db)
+        .await
+    }
+
     pub async fn get_next_order_id(db: &SqlitePool) -> OrderId {
         let (order_id,): (OrderId,) = sqlx::query_as("SELECT MAX(id) FROM 'order'")
             .fetch_one(db)
@@ -98,6 +132,24 @@ impl Order {
         .await
     }
 
+    /// Takes quantity off an open order without changing its status.
+    pub async fn reduce<E>(
+        db: &mut E,
+        id: OrderId,
+        quantity: u32,
+    ) -> Result<SqliteQueryResult, sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        sqlx::query!(
+            "UPDATE 'order' SET remaining = remaining - ? WHERE id = ?",
+            quantity,
+            id
+        )
+        .execute(db)
+        .await
+    }
+
     /// Sets the status of an order to cancelled.
     pub async fn cancel_by_id<E>(db: &mut E, id: OrderId) -> Result<SqliteQueryResult, sqlx::Error>
     where
@@ -136,13 +188,6 @@ impl Order {
 fn build_from_orders(orders: &[Order]) -> lobster::OrderBook {
     orders
         .into_iter()
-        .map(|order| {
-            lobster::Order::new(
-                order.id,
-                order.remaining,
-                order.price,
-                Side::new(order.is_buy),
-            )
-        })
+        .map(|order| (order.user_id, lobster::Order::from(order)))
         .collect()
 }
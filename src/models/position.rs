@@ -21,11 +21,14 @@ pub struct Position {
     pub market_id: u32,
     /// The position. Positive is long, negative is short.
     pub position: i32,
+    /// The net balance paid for trades in the market, refunded if it is voided.
+    pub cost: i64,
 }
 
 impl Position {
+    /// Returns the positions that are non-zero or have a cost basis.
     pub async fn get_non_zero(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
-        sqlx::query_as::<_, Self>("SELECT * FROM position WHERE position != 0")
+        sqlx::query_as::<_, Self>("SELECT * FROM position WHERE position != 0 OR cost != 0")
             .fetch_all(pool)
             .await
     }
@@ -0,0 +1,156 @@
+use lobster::{MarketId, Timestamp, UserId};
+use serde::Serialize;
+use sqlx::{Executor, Sqlite, SqlitePool};
+use utoipa::ToSchema;
+
+/// A proposed outcome for a market. It is settled once the dispute window ends,
+/// unless it was disputed, which leaves it for an admin to replace or void.
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub struct Resolution {
+    pub market_id: MarketId,
+    /// The price the market is proposed to resolve to.
+    pub outcome: u16,
+    /// When the proposal can no longer be disputed.
+    pub dispute_ends_at: Timestamp,
+    /// The number of users disputing the proposal.
+    pub disputes: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub struct Dispute {
+    pub id: i64,
+    pub market_id: MarketId,
+    pub user_id: UserId,
+    pub reason: String,
+    pub created_at: Timestamp,
+}
+
+impl Resolution {
+    /// Returns the proposed outcome of an unresolved market, if it has one.
+    pub async fn get(db: &SqlitePool, market_id: MarketId) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT
+                market.id AS market_id,
+                market.proposed_outcome AS outcome,
+                market.dispute_ends_at,
+                (SELECT COUNT(*) FROM dispute WHERE dispute.market_id = market.id) AS disputes
+            FROM market
+            WHERE market.id = ? AND market.outcome IS NULL AND market.voided = 0
+                AND market.proposed_outcome IS NOT NULL",
+        )
+        .bind(market_id)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Returns the undisputed proposals whose dispute window ended by `time`.
+    /// Losing outcomes come first, since the winner of a mutually exclusive
+    /// event is resolved after the rest of its markets.
+    pub async fn get_due(db: &SqlitePool, time: Timestamp) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT
+                market.id AS market_id,
+                market.proposed_outcome AS outcome,
+                market.dispute_ends_at,
+                0 AS disputes
+            FROM market
+            WHERE market.outcome IS NULL AND market.voided = 0
+                AND market.proposed_outcome IS NOT NULL
+                AND market.dispute_ends_at <= ?
+                AND NOT EXISTS (SELECT 1 FROM dispute WHERE dispute.market_id = market.id)
+            ORDER BY market.proposed_outcome, market.id",
+        )
+        .bind(time)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Proposes an outcome for an unresolved market, replacing any proposal and its disputes.
+    /// Returns `false` if the market doesn't exist, is resolved or is voided.
+    pub async fn propose<E>(
+        db: &mut E,
+        market_id: MarketId,
+        outcome: u16,
+        dispute_ends_at: Timestamp,
+    ) -> Result<bool, sqlx::Error>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        let updated = sqlx::query!(
+            "UPDATE market SET proposed_outcome = ?, dispute_ends_at = ?
+            WHERE id = ? AND outcome IS NULL AND voided = 0",
+            outcome,
+            dispute_ends_at,
+            market_id
+        )
+        .execute(&mut *db)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(false);
+        }
+        sqlx::query!("DELETE FROM dispute WHERE market_id = ?", market_id)
+            .execute(&mut *db)
+            .await?;
+        Ok(true)
+    }
+
+    /// Withdraws the proposed outcome of an unresolved market, and its disputes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RowNotFound` if there was no proposal, or the market is resolved or voided.
+    pub async fn void(db: &SqlitePool, market_id: MarketId) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+        let updated = sqlx::query!(
+            "UPDATE market SET proposed_outcome = NULL, dispute_ends_at = NULL
+            WHERE id = ? AND outcome IS NULL AND voided = 0 AND proposed_outcome IS NOT NULL",
+            market_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        sqlx::query!("DELETE FROM dispute WHERE market_id = ?", market_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+}
+
+impl Dispute {
+    pub async fn insert(
+        db: &SqlitePool,
+        market_id: MarketId,
+        user_id: UserId,
+        reason: &str,
+        created_at: Timestamp,
+    ) -> Result<Self, sqlx::Error> {
+        let id = sqlx::query!(
+            "INSERT INTO dispute (market_id, user_id, reason, created_at) VALUES (?, ?, ?, ?)",
+            market_id,
+            user_id,
+            reason,
+            created_at
+        )
+        .execute(db)
+        .await?
+        .last_insert_rowid();
+        Ok(Self {
+            id,
+            market_id,
+            user_id,
+            reason: reason.to_string(),
+            created_at,
+        })
+    }
+
+    pub async fn get_for_market(db: &SqlitePool, market_id: MarketId) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM dispute WHERE market_id = ? ORDER BY id")
+            .bind(market_id)
+            .fetch_all(db)
+            .await
+    }
+}
@@ -0,0 +1,70 @@
+use lobster::{MarketId, RiskLimits, UserId};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+/// Pre-trade limits for a user, a market, or the defaults if neither is set.
+/// Unset limits fall back to the market's, then the defaults.
+#[derive(sqlx::FromRow, Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct RiskLimit {
+    pub user_id: Option<UserId>,
+    pub market_id: Option<MarketId>,
+    /// The largest quantity of a single order.
+    pub max_order_quantity: Option<u32>,
+    /// The largest quantity times price of a single order.
+    pub max_order_notional: Option<i64>,
+    /// The largest absolute position in a market, counting resting orders as filled.
+    pub max_position: Option<i32>,
+    /// The most resting orders across all markets.
+    pub max_open_orders: Option<u32>,
+    /// How far an order's price can be from the last trade, or the mid price if there is none.
+    pub price_band: Option<u16>,
+}
+
+impl RiskLimit {
+    pub async fn get_all(db: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM risk_limit ORDER BY user_id, market_id")
+            .fetch_all(db)
+            .await
+    }
+
+    /// Replaces the limits for the row's user or market. Removes them if none are set.
+    pub async fn set(&self, db: &SqlitePool) -> Result<(), sqlx::Error> {
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            "DELETE FROM risk_limit WHERE user_id IS ? AND market_id IS ?",
+            self.user_id,
+            self.market_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if !self.limits().is_empty() {
+            sqlx::query!(
+                "INSERT INTO risk_limit (user_id, market_id, max_order_quantity, max_order_notional,
+                    max_position, max_open_orders, price_band)
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+                self.user_id,
+                self.market_id,
+                self.max_order_quantity,
+                self.max_order_notional,
+                self.max_position,
+                self.max_open_orders,
+                self.price_band,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// The limits in the form the matching engine checks.
+    pub fn limits(&self) -> RiskLimits {
+        RiskLimits {
+            max_order_quantity: self.max_order_quantity,
+            max_order_notional: self.max_order_notional,
+            max_position: self.max_position,
+            max_open_orders: self.max_open_orders.map(|max| max as usize),
+            price_band: self.price_band,
+        }
+    }
+}
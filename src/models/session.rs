@@ -23,6 +23,16 @@ impl Session {
             .await
     }
 
+    /// The user's sessions, newest first.
+    pub async fn get_all_for_user(db: &SqlitePool, user_id: UserId) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM 'session' WHERE user_id = ? ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+    }
+
     /// Don't need to check if correct user because guessing is unlikely.
     pub async fn delete_by_id(db: &SqlitePool, id: &str) -> Result<u64, sqlx::Error> {
         info!("deleting session id {id}");
@@ -32,6 +42,62 @@ impl Session {
             .map(|row| row.rows_affected())
     }
 
+    /// Deletes one of the user's sessions. Returns the number of sessions deleted.
+    pub async fn delete_for_user(
+        db: &SqlitePool,
+        user_id: UserId,
+        id: &str,
+    ) -> Result<u64, sqlx::Error> {
+        info!("deleting session id {id}");
+        sqlx::query!(
+            "DELETE FROM session WHERE id = ? AND user_id = ?",
+            id,
+            user_id
+        )
+        .execute(db)
+        .await
+        .map(|row| row.rows_affected())
+    }
+
+    /// Deletes all of the user's sessions. Returns the number of sessions deleted.
+    pub async fn delete_all_for_user(db: &SqlitePool, user_id: UserId) -> Result<u64, sqlx::Error> {
+        info!("deleting all sessions of user {user_id}");
+        sqlx::query!("DELETE FROM session WHERE user_id = ?", user_id)
+            .execute(db)
+            .await
+            .map(|row| row.rows_affected())
+    }
+
+    /// Deletes sessions that expired before `time`. Returns the number of sessions deleted.
+    pub async fn delete_expired(db: &SqlitePool, time: Timestamp) -> Result<u64, sqlx::Error> {
+        sqlx::query!("DELETE FROM session WHERE expires_at <= ?", time)
+            .execute(db)
+            .await
+            .map(|row| row.rows_affected())
+    }
+
+    /// Pushes the expiry of a live session back to `expires_at`,
+    /// if it currently expires before `refresh_before`.
+    /// Returns whether the session was refreshed.
+    pub async fn refresh(
+        db: &SqlitePool,
+        id: &str,
+        time: Timestamp,
+        refresh_before: Timestamp,
+        expires_at: Timestamp,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            "UPDATE session SET expires_at = ? WHERE id = ? AND expires_at > ? AND expires_at < ?",
+            expires_at,
+            id,
+            time,
+            refresh_before
+        )
+        .execute(db)
+        .await
+        .map(|row| row.rows_affected() > 0)
+    }
+
     pub async fn insert<'c, E: Executor<'c, Database = Sqlite>>(
         &self,
         db: E,
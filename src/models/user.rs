@@ -4,13 +4,35 @@ use argon2::PasswordVerifier;
 use lobster::Balance;
 use lobster::Timestamp;
 use lobster::UserId;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::Executor;
 use sqlx::Sqlite;
 use sqlx::SqlitePool;
 use tracing::error;
+use utoipa::ToSchema;
 
-#[derive(sqlx::FromRow, Debug, Serialize)]
+/// What a user may do besides trading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, ToSchema)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Trader,
+    /// Creates events and their markets.
+    MarketCreator,
+    /// Resolves markets.
+    Resolver,
+    /// May do everything, including deposits and assigning roles.
+    Admin,
+}
+
+impl Role {
+    /// Whether a user with this role may do what `required` may.
+    pub fn allows(self, required: Self) -> bool {
+        self == Self::Admin || self == required
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
 pub struct User {
     pub id: UserId,
     pub username: String,
@@ -19,6 +41,10 @@ pub struct User {
     pub created_at: Timestamp,
     pub balance: Balance,
     pub available: Balance,
+    pub role: Role,
+    /// The hex encoded secret the user signs API requests with.
+    #[serde(skip)]
+    pub signing_secret: Option<String>,
 }
 
 impl User {
@@ -79,4 +105,23 @@ impl User {
             }
         }
     }
+
+    /// Sets the secret the user signs API requests with. `None` removes it.
+    pub async fn set_signing_secret(
+        db: &SqlitePool,
+        id: UserId,
+        secret: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE user SET signing_secret = ? WHERE id = ?", secret, id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn set_role(db: &SqlitePool, id: UserId, role: Role) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE user SET role = ? WHERE id = ?", role, id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
 }
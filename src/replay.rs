@@ -0,0 +1,99 @@
+//! Rebuilds the database from the market data feed log, and finds drift
+//! between a rebuilt database and a live one.
+//!
+//! The feed log only has what goes through the matching engine, so users,
+//! events and markets are copied over from the live database (or a backup of it).
+//! Everything else is derived from the log by the same code the writer service runs.
+use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use tracing::info;
+
+use crate::services::writer;
+
+/// Rows compared between the two databases. `DB` is the schema to read from.
+const CHECKS: [&str; 5] = [
+    "SELECT 'user ' || id || ': balance=' || balance || ' available=' || available
+    FROM DB.user",
+    "SELECT 'position user=' || user_id || ' market=' || market_id || ': ' || position
+        || ' cost=' || cost
+    FROM DB.position WHERE position != 0 OR cost != 0",
+    "SELECT 'order ' || id || ': market=' || market_id || ' user=' || user_id
+        || ' quantity=' || quantity || ' remaining=' || remaining || ' price=' || price
+        || ' is_buy=' || is_buy || ' status=' || status
+    FROM DB.'order'",
+    "SELECT 'trade market=' || market_id || ' tick=' || tick || ' taker_oid=' || taker_oid
+        || ' maker_oid=' || maker_oid || ' quantity=' || quantity || ' price=' || price
+    FROM DB.trade",
+    "SELECT 'market ' || id || ': outcome=' || ifnull(outcome, 'none') || ' state=' || state
+        || ' voided=' || voided
+    FROM DB.market",
+];
+
+/// Opens a database on a single connection, so attached databases stay attached.
+async fn connect(url: &str) -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(url)
+        .await
+        .expect("Failed to connect to database")
+}
+
+/// Replays market data feed logs into `target`.
+///
+/// `target` should be migrated and seeded, holding the state from before the first log.
+pub async fn rebuild(live: &str, target: &str, logs: &[String]) {
+    let updates = writer::read_feed_log(logs).expect("Failed to read log");
+    let db = connect(target).await;
+
+    sqlx::query("ATTACH DATABASE ? AS live")
+        .bind(live.trim_start_matches("sqlite:"))
+        .execute(&db)
+        .await
+        .unwrap();
+    sqlx::query(
+        "
+        INSERT OR IGNORE INTO user (id, username, password_hash, created_at, role)
+            SELECT id, username, password_hash, created_at, role FROM live.user;
+        INSERT OR IGNORE INTO event (id, slug, title, description, created_at, event_time, mutually_exclusive,
+                cancel_orders_on_close, closed)
+            SELECT id, slug, title, description, created_at, event_time, mutually_exclusive,
+                cancel_orders_on_close, closed FROM live.event;
+        INSERT OR IGNORE INTO market (id, event_id, title)
+            SELECT id, event_id, title FROM live.market;
+        ",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+    sqlx::query("DETACH DATABASE live").execute(&db).await.unwrap();
+
+    info!("Replaying {} updates", updates.len());
+    writer::replay(db, updates).await;
+}
+
+/// Prints every row that differs between a rebuilt database and a live one.
+/// Returns `true` if there is any drift.
+pub async fn diff(rebuilt: &str, live: &str) -> bool {
+    let db = connect(rebuilt).await;
+    sqlx::query("ATTACH DATABASE ? AS live")
+        .bind(live.trim_start_matches("sqlite:"))
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let mut drift = false;
+    for check in CHECKS {
+        let rebuilt = check.replace("DB.", "main.");
+        let live = check.replace("DB.", "live.");
+        for (sign, query) in [
+            ('-', format!("{rebuilt} EXCEPT {live}")),
+            ('+', format!("{live} EXCEPT {rebuilt}")),
+        ] {
+            let rows: Vec<String> = sqlx::query_scalar(&query).fetch_all(&db).await.unwrap();
+            for row in rows {
+                println!("{sign} {row}");
+                drift = true;
+            }
+        }
+    }
+    drift
+}
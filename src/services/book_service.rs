@@ -4,29 +4,143 @@
 //! to the front end UI.
 //!
 //! Every BookUpdate represents a change in the order book state that needs to be
-//! broadcast to all clients.
+//! broadcast to all clients. It carries
 //!
 //! - volume
 //! - last price
 //! - best bid, best ask
-//! - order book state
+//! - the new total quantity of each price level that changed
 //!
-//! TODO: update state more efficiently
-//! - track price levels individually instead of updating everything on every market.
-use lobster::Price;
+//! Clients apply updates to a `MarketData` snapshot requested with a `SnapshotRequest`.
+//! The service starts from a snapshot of the matching engine. If it falls behind the
+//! feed, it rebuilds from a new one and publishes the levels that changed.
+use axum::extract::ws::{Message, WebSocket};
 use lobster::{Balance, MarketId, MarketUpdate};
+use lobster::{Price, Quantity, Side, Snapshot, Tick};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
-use tokio::sync::broadcast;
-use tracing::info;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{error, info, warn};
 
+use super::matcher::FeedUpdate;
+use super::matcher_request::MatcherRequest;
+use crate::app_state::AppState;
 use crate::models;
 
+/// The total quantity resting at each price.
+#[derive(Debug, Clone, Default)]
+pub struct Depth {
+    bids: BTreeMap<Price, Quantity>,
+    asks: BTreeMap<Price, Quantity>,
+}
+
+impl Depth {
+    pub fn new(orderbook: &lobster::OrderBook) -> Self {
+        let mut depth = Self::default();
+        for order in orderbook.bids().chain(orderbook.asks()) {
+            *depth.side_mut(order.side).entry(order.price).or_default() += order.quantity;
+        }
+        depth
+    }
+
+    /// Returns the bid levels from best price to worst.
+    pub fn bids(&self) -> impl Iterator<Item = (Price, Quantity)> + '_ {
+        self.bids.iter().rev().map(|(&price, &quantity)| (price, quantity))
+    }
+
+    /// Returns the ask levels from best price to worst.
+    pub fn asks(&self) -> impl Iterator<Item = (Price, Quantity)> + '_ {
+        self.asks.iter().map(|(&price, &quantity)| (price, quantity))
+    }
+
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Returns the total quantity at a price, zero if there is no level.
+    pub fn get(&self, side: Side, price: Price) -> Quantity {
+        self.side(side).get(&price).copied().unwrap_or_default()
+    }
+
+    /// Returns the levels with a different quantity in `other`.
+    fn diff(&self, other: &Self) -> Vec<(Side, Price)> {
+        let mut changed = Vec::new();
+        for side in [Side::Buy, Side::Sell] {
+            let (levels, others) = (self.side(side), other.side(side));
+            for (price, quantity) in levels {
+                if others.get(price) != Some(quantity) {
+                    changed.push((side, *price));
+                }
+            }
+            for price in others.keys() {
+                if !levels.contains_key(price) {
+                    changed.push((side, *price));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Sets the total quantity of a level.
+    pub fn set(&mut self, level: LevelDelta) {
+        if level.quantity == 0 {
+            self.side_mut(level.side).remove(&level.price);
+        } else {
+            self.side_mut(level.side).insert(level.price, level.quantity);
+        }
+    }
+
+    const fn side(&self, side: Side) -> &BTreeMap<Price, Quantity> {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    const fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Price, Quantity> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
+
+/// The new total quantity of a price level. Zero means the level is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelDelta {
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+/// The changes an update made to a market.
+#[derive(Debug, Clone)]
+pub struct BookUpdate {
+    /// The sequence number of the update.
+    pub seq: u64,
+    pub market_id: MarketId,
+    pub tick: Tick,
+    /// The levels that changed, each once.
+    pub levels: Vec<LevelDelta>,
+    pub best_bid: Option<Price>,
+    pub best_ask: Option<Price>,
+    pub last_price: Option<Price>,
+    pub outcome: Option<Price>,
+    pub volume: Balance,
+}
+
 /// Snapshot of the latest order book data to be rendered.
 #[derive(Debug, Clone)]
 pub struct MarketData {
     pub market_id: MarketId,
-    pub book: lobster::OrderBook,
+    /// The tick of the market's last update, if it had one.
+    pub tick: Option<Tick>,
+    pub depth: Depth,
     pub best_bid: Option<Price>,
     pub best_ask: Option<Price>,
     pub last_price: Option<Price>,
@@ -35,12 +149,14 @@ pub struct MarketData {
 }
 
 impl MarketData {
-    pub fn new(market: &models::market::Market, orderbook: lobster::OrderBook) -> Self {
+    pub fn new(market: &models::market::Market, orderbook: &lobster::OrderBook) -> Self {
+        let depth = Depth::new(orderbook);
         Self {
             market_id: market.id,
-            best_bid: orderbook.best_bid().map(|x| x.price),
-            best_ask: orderbook.best_ask().map(|x| x.price),
-            book: orderbook,
+            tick: None,
+            best_bid: depth.best_bid(),
+            best_ask: depth.best_ask(),
+            depth,
             last_price: market.last_price,
             outcome: market.outcome,
             volume: market.volume,
@@ -50,7 +166,8 @@ impl MarketData {
     pub fn new_default(market_id: MarketId) -> Self {
         Self {
             market_id,
-            book: lobster::OrderBook::default(),
+            tick: None,
+            depth: Depth::default(),
             best_bid: None,
             best_ask: None,
             last_price: None,
@@ -59,90 +176,470 @@ impl MarketData {
         }
     }
 
-    fn add_order(&mut self, order: lobster::Order) {
-        let fills = self.book.add(order);
+    /// Applies an update published after this snapshot was taken.
+    pub fn apply(&mut self, update: &BookUpdate) {
+        for &level in &update.levels {
+            self.depth.set(level);
+        }
+        self.tick = Some(update.tick);
+        self.best_bid = update.best_bid;
+        self.best_ask = update.best_ask;
+        self.last_price = update.last_price;
+        self.outcome = update.outcome;
+        self.volume = update.volume;
+    }
+}
+
+/// A market as the book service tracks it.
+struct MarketState {
+    /// Resting orders, to find the level of each order an update refers to.
+    book: lobster::OrderBook,
+    data: MarketData,
+    /// The levels changed by the update being applied.
+    changed: Vec<(Side, Price)>,
+}
+
+impl MarketState {
+    const fn new(data: MarketData, book: lobster::OrderBook) -> Self {
+        Self {
+            book,
+            data,
+            changed: Vec::new(),
+        }
+    }
+
+    fn add_level(&mut self, order: lobster::Order) {
+        let depth = self.data.depth.get(order.side, order.price);
+        self.set_level(order.side, order.price, depth + order.quantity);
+    }
+
+    fn sub_level(&mut self, order: lobster::Order, quantity: Quantity) {
+        let depth = self.data.depth.get(order.side, order.price);
+        self.set_level(order.side, order.price, depth - quantity);
+    }
+
+    fn set_level(&mut self, side: Side, price: Price, quantity: Quantity) {
+        self.data.depth.set(LevelDelta {
+            side,
+            price,
+            quantity,
+        });
+        if !self.changed.contains(&(side, price)) {
+            self.changed.push((side, price));
+        }
+    }
+
+    /// Takes `quantity` off a resting order.
+    fn reduce(&mut self, id: lobster::OrderId, quantity: Quantity) {
+        let order = self.book.reduce(id, quantity).unwrap();
+        self.sub_level(order, quantity);
+    }
+
+    /// Applies self-trade prevention cancellations reported with an order.
+    fn reduce_orders(&mut self, cancelled: &[lobster::Order]) {
+        for order in cancelled {
+            self.reduce(order.id, order.quantity);
+        }
+    }
+
+    /// Takes the fills reported with an order off the makers. Returns the quantity traded.
+    fn apply_fills(&mut self, fills: &[lobster::Fill]) -> Quantity {
+        let mut traded = 0;
         for fill in fills {
-            self.volume += Balance::from(fill.quantity) * Balance::from(fill.price);
-            self.last_price = Some(fill.price);
+            self.reduce(fill.id, fill.quantity);
+            self.data.volume += Balance::from(fill.quantity) * Balance::from(fill.price);
+            self.data.last_price = Some(fill.price);
+            traded += fill.quantity;
+        }
+        traded
+    }
+
+    fn add_order(
+        &mut self,
+        user: lobster::UserId,
+        mut order: lobster::Order,
+        fills: &[lobster::Fill],
+    ) {
+        order.quantity -= self.apply_fills(fills);
+        if order.quantity > 0 {
+            assert!(self.book.add(order, user, None).fills.is_empty());
+            self.add_level(order);
+        }
+    }
+
+    fn amend_order(&mut self, mut order: lobster::Order, fills: &[lobster::Fill]) {
+        order.quantity -= self.apply_fills(fills);
+        let old = self.book.get(order.id).unwrap();
+        self.sub_level(old, old.quantity);
+        assert!(self.book.amend(order).unwrap().fills.is_empty());
+        if let Some(order) = self.book.get(order.id) {
+            self.add_level(order);
         }
-        self.best_bid = self.book.best_bid().map(|x| x.price);
-        self.best_ask = self.book.best_ask().map(|x| x.price);
     }
 
     fn remove_order(&mut self, id: lobster::OrderId) {
-        assert!(self.book.remove(id).is_some());
-        self.best_bid = self.book.best_bid().map(|x| x.price);
-        self.best_ask = self.book.best_ask().map(|x| x.price);
+        let order = self.book.remove(id).unwrap();
+        self.sub_level(order, order.quantity);
     }
 
     fn resolve(&mut self, price: lobster::Price) {
-        self.outcome = Some(price);
+        self.data.outcome = Some(price);
+    }
+
+    /// Takes every resting order off the book.
+    fn void(&mut self) {
+        let ids: Vec<_> = self.book.bids().chain(self.book.asks()).map(|order| order.id).collect();
+        for id in ids {
+            self.remove_order(id);
+        }
+    }
+
+    /// Finishes applying the update with sequence number `seq`, returning what changed.
+    fn publish(&mut self, seq: u64, tick: Tick) -> BookUpdate {
+        let data = &mut self.data;
+        data.tick = Some(tick);
+        data.best_bid = data.depth.best_bid();
+        data.best_ask = data.depth.best_ask();
+        BookUpdate {
+            seq,
+            market_id: data.market_id,
+            tick,
+            levels: self
+                .changed
+                .drain(..)
+                .map(|(side, price)| LevelDelta {
+                    side,
+                    price,
+                    quantity: data.depth.get(side, price),
+                })
+                .collect(),
+            best_bid: data.best_bid,
+            best_ask: data.best_ask,
+            last_price: data.last_price,
+            outcome: data.outcome,
+            volume: data.volume,
+        }
+    }
+}
+
+/// The state of some markets as of one point in the feed.
+#[derive(Debug)]
+pub struct MarketSnapshot {
+    /// The sequence number of the last update applied.
+    pub seq: u64,
+    /// The requested markets that aren't resolved or voided.
+    pub markets: Vec<MarketData>,
+}
+
+/// A request for the current state of some markets.
+#[derive(Debug)]
+pub struct SnapshotRequest {
+    pub markets: Vec<MarketId>,
+    /// The response waits until the update with this sequence number is applied.
+    pub min_seq: u64,
+    pub response: oneshot::Sender<MarketSnapshot>,
+}
+
+impl SnapshotRequest {
+    pub fn new(markets: Vec<MarketId>, min_seq: u64) -> (Self, oneshot::Receiver<MarketSnapshot>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self {
+            markets,
+            min_seq,
+            response,
+        };
+        (req, recv)
+    }
+}
+
+/// Takes a snapshot of the open markets among `markets`, after the update with sequence
+/// number `min_seq` is applied. Returns `None` if the book service stopped.
+pub async fn snapshot(
+    state: &AppState,
+    markets: Vec<MarketId>,
+    min_seq: u64,
+) -> Option<MarketSnapshot> {
+    let (request, response) = SnapshotRequest::new(markets, min_seq);
+    state.snapshot_send.send(request).await.ok()?;
+    response.await.ok()
+}
+
+/// What happened to some markets after a snapshot.
+enum BookEvent {
+    /// An update to one of the markets.
+    Update(BookUpdate),
+    /// A new snapshot, taken since updates were missed.
+    Snapshot(MarketSnapshot),
+}
+
+/// Waits for the next update to one of `markets` after the snapshot with sequence
+/// number `seq`. The missed levels can't be patched in if the receiver lags, so a new
+/// snapshot is taken instead. Returns `None` if the book service stopped.
+async fn next_event(state: &mut AppState, markets: &[MarketId], seq: u64) -> Option<BookEvent> {
+    loop {
+        match state.book_receive.recv().await {
+            Ok(update) if update.seq > seq && markets.contains(&update.market_id) => {
+                return Some(BookEvent::Update(update));
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => {
+                return snapshot(state, markets.to_vec(), 0).await.map(BookEvent::Snapshot);
+            }
+            Err(RecvError::Closed) => return None,
+        }
     }
 }
 
+/// Streams the books of `markets` to a socket until either side closes. Each market of
+/// a snapshot is sent as rendered by `render_book`, then each update after it as
+/// rendered by `render_update` from the update and the market before and after it.
+/// A new snapshot is sent if updates were missed.
+pub async fn stream_books(
+    mut state: AppState,
+    mut socket: WebSocket,
+    markets: Vec<MarketId>,
+    render_book: impl Fn(u64, &MarketData) -> String + Send + Sync,
+    render_update: impl Fn(&BookUpdate, &MarketData, &MarketData) -> String + Send + Sync,
+) {
+    // book updates queue up in the receiver while the snapshot is taken
+    let Some(mut snapshot) = snapshot(&state, markets.clone(), 0).await else {
+        return;
+    };
+    if send_books(&mut socket, &snapshot, &render_book).await.is_err() {
+        return;
+    }
+    while let Some(event) = next_event(&mut state, &markets, snapshot.seq).await {
+        let sent = match event {
+            BookEvent::Update(update) => {
+                let Some(market) = snapshot
+                    .markets
+                    .iter_mut()
+                    .find(|market| market.market_id == update.market_id)
+                else {
+                    continue;
+                };
+                let before = market.clone();
+                market.apply(&update);
+                let text = render_update(&update, &before, market);
+                socket.send(Message::Text(text)).await
+            }
+            BookEvent::Snapshot(new_snapshot) => {
+                snapshot = new_snapshot;
+                send_books(&mut socket, &snapshot, &render_book).await
+            }
+        };
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+async fn send_books(
+    socket: &mut WebSocket,
+    snapshot: &MarketSnapshot,
+    render_book: impl Fn(u64, &MarketData) -> String + Send + Sync,
+) -> Result<(), axum::Error> {
+    for market in &snapshot.markets {
+        let text = render_book(snapshot.seq, market);
+        socket.send(Message::Text(text)).await?;
+    }
+    Ok(())
+}
+
 struct MarketDataService {
-    markets: HashMap<MarketId, MarketData>,
+    markets: HashMap<MarketId, MarketState>,
+    /// The sequence number of the last update applied.
+    seq: u64,
+    /// Snapshot requests for updates that haven't arrived yet.
+    pending: Vec<SnapshotRequest>,
 }
 
 impl MarketDataService {
-    pub async fn new(db: &SqlitePool) -> Self {
+    /// Starts from a snapshot of the matching engine, so the books match the
+    /// sequence number they were taken at. Updates up to it are skipped.
+    pub async fn new(db: &SqlitePool, cmd_send: &mpsc::Sender<MatcherRequest>) -> Self {
+        let mut service = Self {
+            markets: HashMap::new(),
+            seq: 0,
+            pending: Vec::new(),
+        };
+        // nobody is subscribed yet, so the levels aren't published
+        service.rebuild(db, &request_snapshot(cmd_send).await).await;
+        service
+    }
+
+    fn on_request(&mut self, request: SnapshotRequest) {
+        if request.min_seq > self.seq {
+            self.pending.push(request);
+            return;
+        }
+        let markets = request
+            .markets
+            .iter()
+            .filter_map(|market| self.markets.get(market))
+            .map(|market| market.data.clone())
+            .collect();
+        // the client may have gone away
+        let _ = request.response.send(MarketSnapshot {
+            seq: self.seq,
+            markets,
+        });
+    }
+
+    /// Replaces the state of every market with a snapshot of the exchange. Returns
+    /// an update for each market with the levels that changed, so subscribers catch up.
+    async fn rebuild(&mut self, db: &SqlitePool, snapshot: &Snapshot) -> Vec<BookUpdate> {
+        let active = models::market::Market::get_active(db).await;
+        let volumes: HashMap<MarketId, Balance> = match active {
+            Ok(markets) => markets.iter().map(|market| (market.id, market.volume)).collect(),
+            Err(err) => {
+                error!(?err, "Failed to get market volumes");
+                HashMap::new()
+            }
+        };
+
+        let mut updates = Vec::new();
         let mut markets = HashMap::new();
-        for market in models::market::Market::get_active(db).await.unwrap() {
-            let market_id = market.id;
-            let orderbook = models::order::Order::build_orderbook(db, market.id)
-                .await
-                .unwrap();
-            let book_data = MarketData::new(&market, orderbook);
-            markets.insert(market_id, book_data);
+        for book in &snapshot.books {
+            let mut orderbook = lobster::OrderBook::default();
+            for &(user, order, stp) in &book.orders {
+                assert!(orderbook.add(order, user, stp).fills.is_empty());
+            }
+            let old = self.markets.remove(&book.market);
+            let mut data = MarketData::new_default(book.market);
+            data.depth = Depth::new(&orderbook);
+            data.last_price = book.last_price;
+            data.volume = volumes
+                .get(&book.market)
+                .copied()
+                .or_else(|| old.as_ref().map(|market| market.data.volume))
+                .unwrap_or_default();
+
+            let mut market = MarketState::new(data, orderbook);
+            let old_depth = old.map_or_else(Depth::default, |market| market.data.depth);
+            market.changed = old_depth.diff(&market.data.depth);
+            updates.push(market.publish(snapshot.seq, book.next_tick.saturating_sub(1)));
+            market.data.tick = book.next_tick.checked_sub(1);
+            markets.insert(book.market, market);
+        }
+
+        self.markets = markets;
+        self.seq = snapshot.seq;
+        for request in std::mem::take(&mut self.pending) {
+            self.on_request(request);
         }
+        updates
+    }
 
-        Self { markets }
+    fn on_event(&mut self, FeedUpdate { seq, update }: FeedUpdate) -> Option<BookUpdate> {
+        // already in the snapshot rebuilt from
+        if seq <= self.seq {
+            return None;
+        }
+        let book_update = self.apply(seq, update);
+        self.seq = seq;
+        for request in std::mem::take(&mut self.pending) {
+            self.on_request(request);
+        }
+        book_update
     }
 
-    fn on_event(&mut self, update: MarketUpdate) -> Option<MarketData> {
+    fn apply(&mut self, seq: u64, update: MarketUpdate) -> Option<BookUpdate> {
+        let (market_id, tick) = update.market_tick()?;
+        if let MarketUpdate::AddMarket { market, .. } = update {
+            let market_data = MarketData::new_default(market);
+            let market_state = MarketState::new(market_data, lobster::OrderBook::default());
+            self.markets.insert(market, market_state);
+        }
+        let settled = matches!(
+            update,
+            MarketUpdate::ResolveMarket { .. } | MarketUpdate::VoidMarket { .. }
+        );
+        let market = self.markets.get_mut(&market_id).unwrap();
         match update {
-            MarketUpdate::AddOrder { market, order, .. } => {
-                let market = self.markets.get_mut(&market).unwrap();
-                market.add_order(order);
-                return Some(market.clone());
+            MarketUpdate::AddOrder {
+                user,
+                order,
+                fills,
+                cancelled,
+                ..
+            } => {
+                market.reduce_orders(&cancelled);
+                market.add_order(user, order, &fills);
             }
-            MarketUpdate::RemoveOrder { market, id, .. } => {
-                let market = self.markets.get_mut(&market).unwrap();
+            MarketUpdate::RemoveOrder { id, .. } => {
                 market.remove_order(id);
-                return Some(market.clone());
             }
-            MarketUpdate::ResolveMarket { market, price, .. } => {
-                let market = self.markets.get_mut(&market).unwrap();
-                market.resolve(price);
-                return Some(market.clone());
+            MarketUpdate::AmendOrder {
+                order,
+                fills,
+                cancelled,
+                ..
+            } => {
+                market.reduce_orders(&cancelled);
+                market.amend_order(order, &fills);
             }
-            MarketUpdate::AddMarket { market, .. } => {
-                let market_data = MarketData::new_default(market);
-                self.markets.insert(market, market_data.clone());
-                return Some(market_data);
+            MarketUpdate::ResolveMarket { price, .. } => {
+                market.resolve(price);
             }
-            MarketUpdate::Deposit { .. } => {
-                return None;
+            MarketUpdate::VoidMarket { .. } => {
+                market.void();
             }
+            MarketUpdate::SetMarketState { .. }
+            | MarketUpdate::AddMarket { .. }
+            | MarketUpdate::AddExclusiveEvent { .. }
+            | MarketUpdate::Deposit { .. }
+            | MarketUpdate::Withdrawal { .. }
+            | MarketUpdate::Adjustment { .. } => {}
         }
+        let book_update = market.publish(seq, tick);
+        // settled markets get no more updates, and aren't in snapshots
+        if settled {
+            self.markets.remove(&market_id);
+        }
+        Some(book_update)
     }
 }
 
+/// Asks the matching engine for a snapshot of the exchange.
+async fn request_snapshot(cmd_send: &mpsc::Sender<MatcherRequest>) -> Snapshot {
+    let (req, recv) = MatcherRequest::snapshot();
+    cmd_send.send(req).await.expect("Receiver dropped");
+    recv.await.expect("Sender dropped")
+}
+
 pub fn start_book_service(
     db: SqlitePool,
-    mut feed: broadcast::Receiver<MarketUpdate>,
-    book_stream: broadcast::Sender<MarketData>,
+    mut feed: broadcast::Receiver<FeedUpdate>,
+    mut requests: mpsc::Receiver<SnapshotRequest>,
+    book_stream: broadcast::Sender<BookUpdate>,
+    cmd_send: mpsc::Sender<MatcherRequest>,
 ) {
     tokio::spawn({
         async move {
             info!("Starting book service...");
-            let mut state = MarketDataService::new(&db).await;
+            let mut state = MarketDataService::new(&db, &cmd_send).await;
 
-            while let Ok(market) = feed.recv().await {
-                let Some(market_data) = state.on_event(market) else {
-                    continue;
-                };
-                book_stream.send(market_data).unwrap();
+            loop {
+                tokio::select! {
+                    update = feed.recv() => match update {
+                        Ok(update) => {
+                            let Some(book_update) = state.on_event(update) else {
+                                continue;
+                            };
+                            book_stream.send(book_update).unwrap();
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Book service missed {skipped} updates, rebuilding from a snapshot");
+                            let snapshot = request_snapshot(&cmd_send).await;
+                            for book_update in state.rebuild(&db, &snapshot).await {
+                                book_stream.send(book_update).unwrap();
+                            }
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    Some(request) = requests.recv() => state.on_request(request),
+                }
             }
         }
     });
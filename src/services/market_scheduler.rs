@@ -0,0 +1,98 @@
+//! Closes the markets of events when their `event_time` arrives, so they stop
+//! trading once the outcome may be known, and settles proposed outcomes once
+//! they can no longer be disputed.
+use std::time::Duration;
+
+use lobster::{MarketState, RejectReason};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{
+    app_state::current_time_micros,
+    models::{event::Event, market::Market, resolution::Resolution},
+};
+
+use super::matcher_request::MatcherRequest;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Closes the unclosed markets of events that are due, and marks the events closed.
+async fn close_due_events(db: &SqlitePool, cmd_send: &mpsc::Sender<MatcherRequest>) {
+    let events = match Event::get_due_for_close(db, current_time_micros()).await {
+        Ok(events) => events,
+        Err(err) => {
+            error!(?err, "Failed to get events due to close");
+            return;
+        }
+    };
+
+    for event in events {
+        let markets = match Market::get_unclosed_for_event(db, event.id).await {
+            Ok(markets) => markets,
+            Err(err) => {
+                error!(?err, "Failed to get markets of event {}", event.id);
+                continue;
+            }
+        };
+        info!(event = event.slug, ?markets, "Closing markets at event time");
+        for market_id in markets {
+            let (req, recv) = MatcherRequest::set_market_state(
+                market_id,
+                MarketState::Closed,
+                event.cancel_orders_on_close,
+            );
+            cmd_send.send(req).await.unwrap();
+            if let Err(err) = recv.await.unwrap() {
+                // resolved since it was read
+                warn!(?err, "Failed to close market {market_id}");
+            }
+        }
+        if let Err(err) = Event::set_closed(db, event.id).await {
+            error!(?err, "Failed to mark event {} closed", event.id);
+        }
+    }
+}
+
+/// Resolves markets whose proposed outcome wasn't disputed in time.
+/// Proposals the exchange rejects are voided, to be proposed again.
+async fn settle_due_resolutions(db: &SqlitePool, cmd_send: &mpsc::Sender<MatcherRequest>) {
+    let resolutions = match Resolution::get_due(db, current_time_micros()).await {
+        Ok(resolutions) => resolutions,
+        Err(err) => {
+            error!(?err, "Failed to get resolutions due to settle");
+            return;
+        }
+    };
+
+    for resolution in resolutions {
+        let market_id = resolution.market_id;
+        info!(market_id, outcome = resolution.outcome, "Settling proposed outcome");
+        let (req, recv) = MatcherRequest::resolve(market_id, resolution.outcome);
+        cmd_send.send(req).await.unwrap();
+        match recv.await.unwrap() {
+            // already resolved, and not recorded yet
+            Ok(_) | Err(RejectReason::MarketNotFound) => {}
+            Err(err) => {
+                warn!(?err, "Voiding proposed outcome of market {market_id}");
+                if let Err(err) = Resolution::void(db, market_id).await {
+                    error!(?err, "Failed to void resolution of market {market_id}");
+                }
+            }
+        }
+    }
+}
+
+pub fn start_market_scheduler(db: SqlitePool, cmd_send: mpsc::Sender<MatcherRequest>) {
+    tokio::spawn({
+        async move {
+            info!("Starting market scheduler...");
+            let mut interval = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                close_due_events(&db, &cmd_send).await;
+                settle_due_resolutions(&db, &cmd_send).await;
+            }
+        }
+    });
+}
@@ -1,19 +1,114 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use lobster::{Balance, Side, UserId};
+use lobster::{Balance, MarketState, MatcherResult, Price, RiskLimits, Timestamp, UserId};
 use lobster::{Exchange, MarketId, MarketUpdate};
 use sqlx::SqlitePool;
 use tokio::sync::{broadcast, mpsc};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::app_state::current_time_micros;
 
-use super::matcher_request::MatcherRequest;
+use super::matcher_request::{MatcherRequest, OrderAction};
+use super::{snapshots, writer};
 
-use crate::models::{market::Market, order::Order, position::Position, user::User};
+use crate::models::{
+    event::Event, feed_state::FeedState, market::Market, order::Order, position::Position,
+    risk_limit::RiskLimit, user::User,
+};
+
+/// Snapshot the exchange every this many updates.
+const SNAPSHOT_INTERVAL: u64 = 10_000;
+
+/// How often to look for expired orders while there are no requests.
+const EXPIRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A market update and its sequence number in the feed.
+#[derive(Debug, Clone)]
+pub struct FeedUpdate {
+    /// Starts at 1 and goes up by one for every update.
+    pub seq: u64,
+    pub update: MarketUpdate,
+}
+
+/// Numbers updates, publishes them and takes periodic snapshots of the exchange.
+struct Feed {
+    /// The sequence number of the last update published.
+    seq: u64,
+    market_data: broadcast::Sender<FeedUpdate>,
+}
+
+impl Feed {
+    fn publish(&mut self, exchange: &Exchange, update: MarketUpdate) {
+        self.seq += 1;
+        let seq = self.seq;
+        self.market_data.send(FeedUpdate { seq, update }).unwrap();
+
+        if seq % SNAPSHOT_INTERVAL == 0 {
+            let snapshot = exchange.snapshot(seq);
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = snapshots::save(&snapshot) {
+                    warn!(?err, "failed to save snapshot {seq}");
+                }
+            });
+        }
+    }
+}
+
+/// Restores the exchange from the latest snapshot and the feed log after it,
+/// or from the database if they don't reach the last update recorded there.
+/// Returns the exchange and the sequence number of the last update it has.
+///
+/// Every service that keeps state from the feed starts from this, so they agree
+/// on the state at that sequence number.
+pub async fn restore_exchange(db: &SqlitePool) -> (Exchange, u64) {
+    let seq = FeedState::get_seq(db).await.unwrap();
+    if let Some(exchange) = replay_from_snapshot(seq) {
+        return (exchange, seq);
+    }
+
+    info!("Bootstrapping exchange from the database at seq={seq}");
+    (bootstrap_exchange(db).await, seq)
+}
+
+/// Restores the exchange from the latest snapshot and replays the feed log up to `seq`.
+fn replay_from_snapshot(seq: u64) -> Option<Exchange> {
+    let snapshot = snapshots::load_latest(seq)?;
+    let mut exchange = Exchange::from_snapshot(&snapshot);
+    let records = match writer::read_feed_log(&writer::feed_log_paths()) {
+        Ok(records) => records,
+        Err(err) => {
+            warn!(?err, "failed to read feed log");
+            return None;
+        }
+    };
+
+    let mut applied = snapshot.seq;
+    for record in records {
+        if record.seq <= applied || record.seq > seq {
+            continue;
+        }
+        if record.seq != applied + 1 {
+            warn!("feed log skips from seq={applied} to seq={}", record.seq);
+            return None;
+        }
+        if let Err(err) = exchange.apply(&record.update) {
+            warn!(?err, "failed to apply seq={}", record.seq);
+            return None;
+        }
+        applied = record.seq;
+    }
+    if applied != seq {
+        warn!("feed log ends at seq={applied}, database is at seq={seq}");
+        return None;
+    }
+
+    info!("Restored exchange from snapshot seq={} and feed log up to seq={seq}", snapshot.seq);
+    Some(exchange)
+}
 
 /// Initializes the in-memory exchange data from the database.
-async fn bootstrap_exchange(db: &SqlitePool) -> Exchange {
+pub async fn bootstrap_exchange(db: &SqlitePool) -> Exchange {
     let next_order_id = Order::get_next_order_id(db).await;
 
     let mut balances: HashMap<UserId, Balance> = HashMap::new();
@@ -21,53 +116,141 @@ async fn bootstrap_exchange(db: &SqlitePool) -> Exchange {
         balances.insert(user.id, user.balance);
     }
 
-    let mut markets: Vec<MarketId> = Vec::new();
+    let mut markets: Vec<(MarketId, Option<Price>, MarketState)> = Vec::new();
     for market in Market::get_active(db).await.unwrap() {
-        markets.push(market.id);
+        markets.push((market.id, market.last_price, market.state.into()));
     }
 
-    let mut positions: HashMap<(UserId, MarketId), i32> = HashMap::new();
+    let mut positions: HashMap<(UserId, MarketId), (i32, Balance)> = HashMap::new();
     for position in Position::get_non_zero(db).await.unwrap() {
-        positions.insert((position.user_id, position.market_id), position.position);
+        positions.insert(
+            (position.user_id, position.market_id),
+            (position.position, position.cost),
+        );
     }
 
     let mut orders: Vec<(UserId, MarketId, lobster::Order)> = Vec::new();
     for order_record in Order::get_open_orders(db).await.unwrap() {
-        let order = lobster::Order::new(
-            order_record.id,
-            order_record.quantity,
-            order_record.price,
-            Side::new(order_record.is_buy),
-        );
+        let order = lobster::Order::from(&order_record);
         orders.push((order_record.user_id, order_record.market_id, order));
     }
 
-    let engine = lobster::Exchange::from_state(
+    let mut engine = lobster::Exchange::from_state(
         next_order_id,
         &balances,
         &positions,
         orders.as_slice(),
         markets.as_slice(),
     );
+    for markets in Event::get_exclusive_markets(db).await.unwrap() {
+        engine.add_exclusive_event(0, markets).unwrap();
+    }
 
     engine
 }
 
+/// Sets a market's state, then cancels its resting orders if `cancel_orders` is set.
+/// Publishes the state change before the cancellations.
+fn set_market_state(
+    exchange: &mut Exchange,
+    feed: &mut Feed,
+    timestamp: Timestamp,
+    market_id: MarketId,
+    state: MarketState,
+    cancel_orders: bool,
+) -> MatcherResult {
+    let update = exchange.set_market_state(timestamp, market_id, state)?;
+    feed.publish(exchange, update.clone());
+    if cancel_orders {
+        for cancelled in exchange.cancel_market_orders(timestamp, market_id)? {
+            feed.publish(exchange, cancelled);
+        }
+    }
+    Ok(update)
+}
+
+/// Cancels the GTT orders that expire by `timestamp`.
+fn expire_orders(exchange: &mut Exchange, feed: &mut Feed, timestamp: Timestamp) {
+    for update in exchange.expire_orders(timestamp) {
+        feed.publish(exchange, update);
+    }
+}
+
+/// Applies an order action from a trading session, publishing the update if it succeeds.
+/// Returns the sequence number of the last update published with the result.
+fn session_order(
+    exchange: &mut Exchange,
+    feed: &mut Feed,
+    timestamp: Timestamp,
+    user: UserId,
+    action: OrderAction,
+) -> (u64, MatcherResult) {
+    let res = match action {
+        OrderAction::Submit(order) => exchange.submit_order(timestamp, user, order),
+        OrderAction::Cancel(order) => exchange.cancel_order(timestamp, user, order),
+        OrderAction::CancelByClientOrderId(client_order_id) => {
+            exchange.cancel_by_client_order_id(timestamp, user, client_order_id)
+        }
+        OrderAction::Amend {
+            order,
+            quantity,
+            price,
+        } => exchange.amend_order(timestamp, user, order, quantity, price),
+    };
+    if let Ok(update) = res.clone() {
+        feed.publish(exchange, update);
+    }
+    (feed.seq, res)
+}
+
+/// Replaces the risk limits of a user, a market, or the defaults if neither is given.
+fn set_risk_limits(
+    exchange: &mut Exchange,
+    user: Option<UserId>,
+    market: Option<MarketId>,
+    limits: RiskLimits,
+) {
+    match (user, market) {
+        (Some(user), _) => exchange.set_user_limits(user, limits),
+        (None, Some(market)) => exchange.set_market_limits(market, limits),
+        (None, None) => exchange.set_default_limits(limits),
+    }
+}
+
 /// The matching engine takes queued requests and applies them to
 /// the matching engine. If the request is valid, a market update is
 /// emitted. Else an error is returned to the caller.
 pub fn start_matcher_service(
     db: SqlitePool,
     mut recv: mpsc::Receiver<MatcherRequest>,
-    market_data: broadcast::Sender<MarketUpdate>,
+    market_data: broadcast::Sender<FeedUpdate>,
 ) {
     tokio::spawn({
         async move {
             info!("Starting matching engine...");
-            let mut exchange = bootstrap_exchange(&db).await;
+            let (mut exchange, seq) = restore_exchange(&db).await;
+            snapshots::remove_after(seq);
+            // Limits only decide which requests are accepted, so they aren't in the feed.
+            for limit in RiskLimit::get_all(&db).await.unwrap() {
+                set_risk_limits(&mut exchange, limit.user_id, limit.market_id, limit.limits());
+            }
+            let mut feed = Feed { seq, market_data };
+            let mut expiry = tokio::time::interval(EXPIRY_INTERVAL);
 
-            while let Some(msg) = recv.recv().await {
+            loop {
+                let msg = tokio::select! {
+                    msg = recv.recv() => msg,
+                    _ = expiry.tick() => {
+                        expire_orders(&mut exchange, &mut feed, current_time_micros());
+                        continue;
+                    }
+                };
+                let Some(msg) = msg else {
+                    break;
+                };
                 let timestamp = current_time_micros();
+                // so expired orders never trade
+                expire_orders(&mut exchange, &mut feed, timestamp);
                 match msg {
                     MatcherRequest::SubmitOrder {
                         user,
@@ -77,7 +260,7 @@ pub fn start_matcher_service(
                         info!("REQUEST time={timestamp} user={user} post order={order:?}");
                         let res = exchange.submit_order(timestamp, user, order);
                         if let Ok(market) = res.clone() {
-                            market_data.send(market).unwrap();
+                            feed.publish(&exchange, market);
                         }
                         response.send(res).unwrap();
                     }
@@ -89,19 +272,94 @@ pub fn start_matcher_service(
                         info!("REQUEST time={timestamp} user={user} delete order={order:?}");
                         let res = exchange.cancel_order(timestamp, user, order);
                         if let Ok(market) = res.clone() {
-                            market_data.send(market).unwrap();
+                            feed.publish(&exchange, market);
                         }
                         response.send(res).unwrap();
                     }
+                    MatcherRequest::CancelByClientOrderId {
+                        user,
+                        client_order_id,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} user={user} delete client_order_id={client_order_id}");
+                        let res = exchange.cancel_by_client_order_id(timestamp, user, client_order_id);
+                        if let Ok(market) = res.clone() {
+                            feed.publish(&exchange, market);
+                        }
+                        response.send(res).unwrap();
+                    }
+                    MatcherRequest::AmendOrder {
+                        user,
+                        order,
+                        quantity,
+                        price,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} user={user} amend order={order:?} quantity={quantity:?} price={price:?}");
+                        let res = exchange.amend_order(timestamp, user, order, quantity, price);
+                        if let Ok(market) = res.clone() {
+                            feed.publish(&exchange, market);
+                        }
+                        response.send(res).unwrap();
+                    }
+                    MatcherRequest::SessionOrder {
+                        user,
+                        action,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} user={user} session action={action:?}");
+                        let res = session_order(&mut exchange, &mut feed, timestamp, user, action);
+                        // the session may have disconnected while waiting
+                        let _ = response.send(res);
+                    }
+                    MatcherRequest::CancelUserOrders { user } => {
+                        info!("REQUEST time={timestamp} user={user} cancel orders on disconnect");
+                        for update in exchange.cancel_user_orders(timestamp, user) {
+                            feed.publish(&exchange, update);
+                        }
+                    }
                     MatcherRequest::AddMarket { market_id } => {
                         info!("REQUEST time={timestamp} add market={market_id:?}");
                         let market = exchange.add_event(timestamp, market_id).unwrap();
-                        market_data.send(market).unwrap();
+                        feed.publish(&exchange, market);
+                    }
+                    MatcherRequest::AddExclusiveEvent { markets, response } => {
+                        info!("REQUEST time={timestamp} add exclusive event={markets:?}");
+                        let res = exchange.add_exclusive_event(timestamp, markets);
+                        if let Ok(update) = res.clone() {
+                            feed.publish(&exchange, update);
+                        }
+                        response.send(res).unwrap();
                     }
                     MatcherRequest::Deposit { user, amount } => {
                         info!("REQUEST time={timestamp} deposit={amount} to user={user}");
                         let market_update = exchange.deposit(timestamp, user, amount).unwrap();
-                        market_data.send(market_update).unwrap();
+                        feed.publish(&exchange, market_update);
+                    }
+                    MatcherRequest::Withdraw {
+                        user,
+                        amount,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} withdraw={amount} from user={user}");
+                        let res = exchange.withdraw(timestamp, user, amount);
+                        if let Ok(update) = res.clone() {
+                            feed.publish(&exchange, update);
+                        }
+                        response.send(res).unwrap();
+                    }
+                    MatcherRequest::AdjustBalance {
+                        user,
+                        amount,
+                        memo,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} adjust balance={amount} of user={user} memo={memo:?}");
+                        let res = exchange.adjust_balance(timestamp, user, amount, memo);
+                        if let Ok(update) = res.clone() {
+                            feed.publish(&exchange, update);
+                        }
+                        response.send(res).unwrap();
                     }
                     MatcherRequest::Resolve {
                         market_id,
@@ -111,10 +369,51 @@ pub fn start_matcher_service(
                         info!("REQUEST time={timestamp} resolve={market_id:?} to price={price}");
                         let market = exchange.resolve(timestamp, market_id, price);
                         if let Ok(market) = market.clone() {
-                            market_data.send(market).unwrap();
+                            feed.publish(&exchange, market);
+                        }
+                        response.send(market).unwrap();
+                    }
+                    MatcherRequest::Void {
+                        market_id,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} void={market_id:?}");
+                        let market = exchange.void_market(timestamp, market_id);
+                        if let Ok(market) = market.clone() {
+                            feed.publish(&exchange, market);
                         }
                         response.send(market).unwrap();
                     }
+                    MatcherRequest::SetMarketState {
+                        market_id,
+                        state,
+                        cancel_orders,
+                        response,
+                    } => {
+                        info!("REQUEST time={timestamp} set market={market_id} state={state:?} cancel_orders={cancel_orders}");
+                        let res = set_market_state(
+                            &mut exchange,
+                            &mut feed,
+                            timestamp,
+                            market_id,
+                            state,
+                            cancel_orders,
+                        );
+                        response.send(res).unwrap();
+                    }
+                    MatcherRequest::SetRiskLimits {
+                        user,
+                        market,
+                        limits,
+                    } => {
+                        info!("REQUEST time={timestamp} set risk limits user={user:?} market={market:?} limits={limits:?}");
+                        set_risk_limits(&mut exchange, user, market, limits);
+                    }
+                    MatcherRequest::Snapshot { response } => {
+                        info!("REQUEST time={timestamp} snapshot at seq={}", feed.seq);
+                        // the requester may have stopped waiting
+                        let _ = response.send(exchange.snapshot(feed.seq));
+                    }
                 }
             }
         }
@@ -1,7 +1,20 @@
-use lobster::{Balance, MarketId, MatcherResult, OrderRequest, UserId};
-use lobster::{OrderId, Price};
+use lobster::{Balance, MarketId, MarketState, MatcherResult, OrderRequest, RiskLimits, UserId};
+use lobster::{ClientOrderId, OrderId, Price, Quantity, Snapshot};
 use tokio::sync::oneshot;
 
+/// What a trading session asks the matching engine to do with an order.
+#[derive(Debug, Clone, Copy)]
+pub enum OrderAction {
+    Submit(OrderRequest),
+    Cancel(OrderId),
+    CancelByClientOrderId(ClientOrderId),
+    Amend {
+        order: OrderId,
+        quantity: Option<Quantity>,
+        price: Option<Price>,
+    },
+}
+
 /// A message sent from a controller to the matching engine service.
 #[derive(Debug)]
 pub enum MatcherRequest {
@@ -17,18 +30,82 @@ pub enum MatcherRequest {
         /// Response to the client
         response: oneshot::Sender<MatcherResult>,
     },
+    CancelByClientOrderId {
+        user: UserId,
+        client_order_id: ClientOrderId,
+        /// Response to the client
+        response: oneshot::Sender<MatcherResult>,
+    },
+    AmendOrder {
+        user: UserId,
+        order: OrderId,
+        quantity: Option<Quantity>,
+        price: Option<Price>,
+        /// Response to the client
+        response: oneshot::Sender<MatcherResult>,
+    },
+    /// An order action from a trading session. The response also has the sequence
+    /// number of the last update published when it was handled.
+    SessionOrder {
+        user: UserId,
+        action: OrderAction,
+        response: oneshot::Sender<(u64, MatcherResult)>,
+    },
+    /// Cancels every resting order of a user whose trading session ended.
+    CancelUserOrders {
+        user: UserId,
+    },
     AddMarket {
         market_id: MarketId,
     },
+    /// Groups markets into an event where exactly one market resolves to 100%.
+    AddExclusiveEvent {
+        markets: Vec<MarketId>,
+        response: oneshot::Sender<MatcherResult>,
+    },
     Deposit {
         user: UserId,
         amount: Balance,
     },
+    Withdraw {
+        user: UserId,
+        amount: Balance,
+        response: oneshot::Sender<MatcherResult>,
+    },
+    /// Adds a signed amount to a user's balance, recording why.
+    AdjustBalance {
+        user: UserId,
+        amount: Balance,
+        memo: String,
+        response: oneshot::Sender<MatcherResult>,
+    },
     Resolve {
         market_id: MarketId,
         price: Price,
         response: oneshot::Sender<MatcherResult>,
     },
+    /// Cancels a market, refunding every user their cost basis in it.
+    Void {
+        market_id: MarketId,
+        response: oneshot::Sender<MatcherResult>,
+    },
+    /// Opens, halts or closes a market, cancelling its resting orders if `cancel_orders` is set.
+    SetMarketState {
+        market_id: MarketId,
+        state: MarketState,
+        cancel_orders: bool,
+        response: oneshot::Sender<MatcherResult>,
+    },
+    /// Replaces the risk limits of a user, a market, or the defaults if neither is set.
+    SetRiskLimits {
+        user: Option<UserId>,
+        market: Option<MarketId>,
+        limits: RiskLimits,
+    },
+    /// Captures the state of the exchange after the last update published.
+    Snapshot {
+        response: oneshot::Sender<Snapshot>,
+    },
 }
 
 impl MatcherRequest {
@@ -52,11 +129,85 @@ impl MatcherRequest {
         (req, recv)
     }
 
+    pub fn cancel_by_client_order_id(
+        user: UserId,
+        client_order_id: ClientOrderId,
+    ) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::CancelByClientOrderId {
+            user,
+            client_order_id,
+            response,
+        };
+        (req, recv)
+    }
+
+    pub fn amend(
+        user: UserId,
+        order: OrderId,
+        quantity: Option<Quantity>,
+        price: Option<Price>,
+    ) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::AmendOrder {
+            user,
+            order,
+            quantity,
+            price,
+            response,
+        };
+        (req, recv)
+    }
+
+    pub fn session_order(
+        user: UserId,
+        action: OrderAction,
+    ) -> (Self, oneshot::Receiver<(u64, MatcherResult)>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::SessionOrder {
+            user,
+            action,
+            response,
+        };
+        (req, recv)
+    }
+
     pub fn deposit(user: UserId, amount: Balance) -> Self {
         let req = Self::Deposit { user, amount };
         req
     }
 
+    pub fn add_exclusive_event(markets: Vec<MarketId>) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::AddExclusiveEvent { markets, response };
+        (req, recv)
+    }
+
+    pub fn withdraw(user: UserId, amount: Balance) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::Withdraw {
+            user,
+            amount,
+            response,
+        };
+        (req, recv)
+    }
+
+    pub fn adjust_balance(
+        user: UserId,
+        amount: Balance,
+        memo: String,
+    ) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::AdjustBalance {
+            user,
+            amount,
+            memo,
+            response,
+        };
+        (req, recv)
+    }
+
     pub fn resolve(market_id: MarketId, price: Price) -> (Self, oneshot::Receiver<MatcherResult>) {
         let (response, recv) = oneshot::channel();
         let req = Self::Resolve {
@@ -66,4 +217,33 @@ impl MatcherRequest {
         };
         (req, recv)
     }
+
+    pub fn void(market_id: MarketId) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::Void {
+            market_id,
+            response,
+        };
+        (req, recv)
+    }
+
+    pub fn set_market_state(
+        market_id: MarketId,
+        state: MarketState,
+        cancel_orders: bool,
+    ) -> (Self, oneshot::Receiver<MatcherResult>) {
+        let (response, recv) = oneshot::channel();
+        let req = Self::SetMarketState {
+            market_id,
+            state,
+            cancel_orders,
+            response,
+        };
+        (req, recv)
+    }
+
+    pub fn snapshot() -> (Self, oneshot::Receiver<Snapshot>) {
+        let (response, recv) = oneshot::channel();
+        (Self::Snapshot { response }, recv)
+    }
 }
@@ -1,4 +1,7 @@
 pub mod book_service;
+pub mod market_scheduler;
 pub mod matcher;
 pub mod matcher_request;
+pub mod session_purger;
+pub mod snapshots;
 pub mod writer;
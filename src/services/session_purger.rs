@@ -0,0 +1,26 @@
+//! Deletes expired sessions, which are otherwise only rejected when used.
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+use crate::{app_state::current_time_micros, models::session::Session};
+
+const PURGE_INTERVAL: Duration = Duration::from_hours(1);
+
+pub fn start_session_purger(db: SqlitePool) {
+    tokio::spawn({
+        async move {
+            info!("Starting session purger...");
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match Session::delete_expired(&db, current_time_micros()).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Purged {count} expired sessions"),
+                    Err(err) => error!(?err, "Failed to purge expired sessions"),
+                }
+            }
+        }
+    });
+}
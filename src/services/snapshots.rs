@@ -0,0 +1,72 @@
+//! Stores exchange snapshots on disk, named by the sequence number they were taken at.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lobster::Snapshot;
+use tracing::warn;
+
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// The number of snapshots to keep, in case the latest is unreadable.
+const KEEP: usize = 3;
+
+fn path(seq: u64) -> PathBuf {
+    Path::new(SNAPSHOT_DIR).join(format!("exchange-{seq:020}.snapshot"))
+}
+
+/// Returns the sequence numbers of the stored snapshots, in ascending order.
+fn list() -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(SNAPSHOT_DIR) else {
+        return Vec::new();
+    };
+    let mut seqs: Vec<u64> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix("exchange-")?
+                .strip_suffix(".snapshot")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    seqs.sort_unstable();
+    seqs
+}
+
+/// Writes a snapshot, and removes all but the latest few.
+pub fn save(snapshot: &Snapshot) -> io::Result<()> {
+    fs::create_dir_all(SNAPSHOT_DIR)?;
+    let path = path(snapshot.seq);
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, snapshot.encode())?;
+    fs::rename(tmp, path)?;
+
+    let seqs = list();
+    for &seq in &seqs[..seqs.len().saturating_sub(KEEP)] {
+        fs::remove_file(self::path(seq))?;
+    }
+    Ok(())
+}
+
+/// Returns the latest readable snapshot taken at or before `seq`.
+pub fn load_latest(seq: u64) -> Option<Snapshot> {
+    for seq in list().into_iter().rev().filter(|&snapshot| snapshot <= seq) {
+        let snapshot = fs::read(path(seq))
+            .map_err(|err| format!("{err}"))
+            .and_then(|bytes| Snapshot::decode(&bytes).map_err(|err| format!("{err:?}")));
+        match snapshot {
+            Ok(snapshot) => return Some(snapshot),
+            Err(err) => warn!(err, "failed to load snapshot {seq}"),
+        }
+    }
+    None
+}
+
+/// Removes snapshots taken after `seq`. They have updates the database never recorded.
+pub fn remove_after(seq: u64) {
+    for snapshot in list().into_iter().filter(|&snapshot| snapshot > seq) {
+        if let Err(err) = fs::remove_file(path(snapshot)) {
+            warn!(?err, "failed to remove snapshot {snapshot}");
+        }
+    }
+}
@@ -5,20 +5,30 @@
 //! Gets to do less work than the matching engine because all feed markets
 //! are validated.
 use lobster::{
-    Balance, MarketId, MarketUpdate, Order, OrderBook, PortfolioManager, Side, Tick, Timestamp,
-    UserId,
+    contracts_combined, contracts_created, Balance, Fill, MarketId, MarketUpdate, Order, OrderBook,
+    PortfolioManager, Position, Quantity, Side, Snapshot, Tick, Timestamp, UserId, RESOLVE_PRICE,
 };
 use lobster::{OrderId, Price};
 use sqlx::{Executor, Sqlite, SqlitePool};
 use std::collections::HashMap;
-use std::io::Write;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use tokio::sync::broadcast;
 use tracing::info;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
-use crate::models::trade::Trade;
+use super::matcher::{self, FeedUpdate};
+use crate::models::{
+    feed_state::FeedState,
+    ledger::{LedgerEntry, LedgerReason},
+    trade::Trade,
+};
 use crate::{api, models};
 
+const LOG_DIR: &str = "logs";
+const LOG_PREFIX: &str = "market_data_feed.log";
+
 #[derive(Debug)]
 struct OrderOwner {
     pub user_id: UserId,
@@ -30,52 +40,41 @@ struct State {
     orderbooks: HashMap<MarketId, OrderBook>,
     order_owner: HashMap<OrderId, OrderOwner>,
     manager: PortfolioManager,
-    log: RollingFileAppender,
+    /// The market data feed log. `None` when replaying the log itself.
+    log: Option<RollingFileAppender>,
 }
 
 impl State {
-    pub async fn new(db: SqlitePool) -> Self {
-        let file_appender =
-            RollingFileAppender::new(Rotation::DAILY, "logs", "market_data_feed.log");
-
-        let mut balances: HashMap<UserId, Balance> = HashMap::new();
-        for user in models::user::User::get_with_nonzero_balances(&db)
-            .await
-            .unwrap()
-        {
-            balances.insert(user.id, user.balance);
-        }
-
-        let mut positions: HashMap<(UserId, MarketId), i32> = HashMap::new();
-        for position in models::position::Position::get_non_zero(&db).await.unwrap() {
-            positions.insert((position.user_id, position.market_id), position.position);
-        }
-
+    /// Starts from the state of the exchange in a snapshot.
+    pub fn new(db: SqlitePool, log: Option<RollingFileAppender>, snapshot: &Snapshot) -> Self {
+        let balances = snapshot.balances.iter().copied().collect();
+        let positions = snapshot
+            .positions
+            .iter()
+            .map(|&(user, market, position, cost)| ((user, market), (position, cost)))
+            .collect();
         let mut manager = PortfolioManager::new(&balances, &positions);
 
         let mut orderbooks: HashMap<MarketId, OrderBook> = HashMap::new();
-        for book in models::market::Market::get_active(&db).await.unwrap() {
-            orderbooks.insert(book.id, OrderBook::default());
+        let mut order_owner = HashMap::new();
+        for book in &snapshot.books {
+            let mut orderbook = OrderBook::default();
+            for &(user_id, order, stp) in &book.orders {
+                order_owner.insert(
+                    order.id,
+                    OrderOwner {
+                        user_id,
+                        market_id: book.market,
+                    },
+                );
+                manager.add_resting_order(user_id, book.market, order);
+                assert!(orderbook.add(order, user_id, stp).fills.is_empty());
+            }
+            orderbooks.insert(book.market, orderbook);
         }
 
-        let mut order_owner = HashMap::new();
-        for order_record in models::order::Order::get_open_orders(&db).await.unwrap() {
-            let order = lobster::Order::new(
-                order_record.id,
-                order_record.quantity,
-                order_record.price,
-                Side::new(order_record.is_buy),
-            );
-            order_owner.insert(
-                order_record.id,
-                OrderOwner {
-                    user_id: order_record.user_id,
-                    market_id: order_record.market_id,
-                },
-            );
-            manager.add_resting_order(order_record.user_id, order_record.market_id, order);
-            let book = orderbooks.get_mut(&order_record.market_id).unwrap();
-            assert!(book.add(order).is_empty());
+        for markets in &snapshot.exclusive_events {
+            manager.add_exclusive(markets);
         }
 
         Self {
@@ -83,14 +82,20 @@ impl State {
             orderbooks,
             order_owner,
             manager,
-            log: file_appender,
+            log,
         }
     }
 
-    async fn on_event(&mut self, update: MarketUpdate) {
-        info!(?update);
+    async fn on_event(&mut self, FeedUpdate { seq, update }: FeedUpdate) {
+        info!(seq, ?update);
+        let record = api::FeedRecord::from(FeedUpdate {
+            seq,
+            update: update.clone(),
+        });
+        let msg = serde_json::to_string(&record).unwrap();
 
         let mut tx = self.db.begin().await.unwrap();
+        FeedState::set_seq(&mut *tx, seq).await.unwrap();
 
         match update {
             MarketUpdate::AddOrder {
@@ -99,33 +104,89 @@ impl State {
                 market,
                 user,
                 order,
+                fills,
+                cancelled,
             } => {
-                self.on_add(&mut *tx, timestamp, tick, user, market, order)
-                    .await
+                self.on_self_trade(&mut *tx, user, market, &cancelled)
+                    .await;
+                self.on_add(&mut *tx, timestamp, tick, user, market, order, &fills)
+                    .await;
+                self.on_merge(&mut *tx, timestamp, user, market, &fills)
+                    .await;
             }
-            MarketUpdate::RemoveOrder { market, id, .. } => {
-                self.on_remove(&mut *tx, market, id).await
+            MarketUpdate::RemoveOrder {
+                timestamp,
+                market,
+                user,
+                id,
+                ..
+            } => {
+                self.on_remove(&mut *tx, market, id).await;
+                self.on_merge(&mut *tx, timestamp, user, market, &[]).await;
             }
-            MarketUpdate::ResolveMarket { market, price, .. } => {
-                self.on_resolve(&mut *tx, market, price).await
+            MarketUpdate::AmendOrder {
+                timestamp,
+                tick,
+                market,
+                user,
+                order,
+                fills,
+                cancelled,
+            } => {
+                self.on_self_trade(&mut *tx, user, market, &cancelled)
+                    .await;
+                self.on_amend(&mut *tx, timestamp, tick, user, market, order, &fills)
+                    .await;
+                self.on_merge(&mut *tx, timestamp, user, market, &fills)
+                    .await;
+            }
+            MarketUpdate::ResolveMarket {
+                timestamp,
+                market,
+                price,
+                ..
+            } => self.on_resolve(&mut *tx, timestamp, market, price).await,
+            MarketUpdate::VoidMarket {
+                timestamp, market, ..
+            } => self.on_void(&mut *tx, timestamp, market).await,
+            MarketUpdate::SetMarketState { market, state, .. } => {
+                models::market::Market::set_state(&mut *tx, market, state.into())
+                    .await
+                    .unwrap();
             }
             MarketUpdate::AddMarket { market, .. } => {
                 self.orderbooks.insert(market, OrderBook::default());
             }
-            MarketUpdate::Deposit { user, amount, .. } => {
-                self.on_deposit(&mut *tx, user, amount).await;
+            MarketUpdate::AddExclusiveEvent { markets, .. } => {
+                self.manager.add_exclusive(&markets);
+            }
+            MarketUpdate::Deposit {
+                timestamp,
+                user,
+                amount,
+            } => {
+                self.on_deposit(&mut *tx, timestamp, user, amount).await;
+            }
+            update @ (MarketUpdate::Withdrawal { .. } | MarketUpdate::Adjustment { .. }) => {
+                self.on_balance_change(&mut *tx, update).await;
             }
         }
 
         tx.commit().await.unwrap();
 
-        let msg = serde_json::to_string(&api::MarketUpdate::from(update)).unwrap();
-        self.log.write(msg.as_bytes()).unwrap();
-        self.log.write(b"\n").unwrap();
+        if let Some(log) = &mut self.log {
+            log.write(msg.as_bytes()).unwrap();
+            log.write(b"\n").unwrap();
+        }
     }
 
-    async fn on_deposit<E>(&mut self, transaction: &mut E, user_id: UserId, amount: Balance)
-    where
+    async fn on_deposit<E>(
+        &mut self,
+        transaction: &mut E,
+        time: Timestamp,
+        user_id: UserId,
+        amount: Balance,
+    ) where
         for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
     {
         self.manager.deposit(user_id, amount);
@@ -144,6 +205,52 @@ impl State {
         if result.rows_affected() == 0 {
             panic!("User not found for deposit: {user_id}");
         };
+
+        LedgerEntry::new(user_id, amount, LedgerReason::Deposit, time)
+            .insert(transaction)
+            .await
+            .unwrap();
+    }
+
+    /// Records a user's new balance after a withdrawal or adjustment, and the entry for it.
+    async fn on_balance_change<E>(&mut self, transaction: &mut E, update: MarketUpdate)
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        let entry = match update {
+            MarketUpdate::Withdrawal {
+                timestamp,
+                user,
+                amount,
+            } => {
+                self.manager.withdraw(user, amount);
+                LedgerEntry::new(user, -amount, LedgerReason::Withdrawal, timestamp)
+            }
+            MarketUpdate::Adjustment {
+                timestamp,
+                user,
+                amount,
+                memo,
+            } => {
+                self.manager.deposit(user, amount);
+                LedgerEntry::new(user, amount, LedgerReason::Adjustment, timestamp).with_memo(memo)
+            }
+            _ => unreachable!("Not a balance change: {update:?}"),
+        };
+
+        let balance = self.manager.get_balance(entry.user_id);
+        let available = self.manager.get_available(entry.user_id);
+        sqlx::query!(
+            "UPDATE user SET balance = ?, available = ? WHERE id = ?",
+            balance,
+            available,
+            entry.user_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        entry.insert(transaction).await.unwrap();
     }
 
     /// This logic is mostly copy-pasted from the matching engine.
@@ -151,6 +258,15 @@ impl State {
     where
         for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
     {
+        let taker_side = Side::new(trade.is_buy);
+        let taker_before = self.manager.get_position(trade.taker_id, trade.market_id);
+        let mut maker_before = self.manager.get_position(trade.maker_id, trade.market_id);
+        if trade.maker_id == trade.taker_id {
+            // the taker's side of a self-trade is settled first
+            let quantity = Position::try_from(trade.quantity).unwrap();
+            maker_before += if trade.is_buy { quantity } else { -quantity };
+        }
+
         self.manager.on_trade(
             trade.taker_id,
             trade.maker_id,
@@ -166,17 +282,21 @@ impl State {
         let maker_available = self.manager.get_available(trade.maker_id);
         let taker_position = self.manager.get_position(trade.taker_id, trade.market_id);
         let maker_position = self.manager.get_position(trade.maker_id, trade.market_id);
+        let taker_cost = self.manager.get_cost(trade.taker_id, trade.market_id);
+        let maker_cost = self.manager.get_cost(trade.maker_id, trade.market_id);
 
         sqlx::query!(
             "
             UPDATE user SET balance = ?, available = ? WHERE id = ?;
             UPDATE user SET balance = ?, available = ? WHERE id = ?;
 
-            INSERT INTO position (user_id, market_id, position)
+            INSERT INTO position (user_id, market_id, position, cost)
             VALUES 
-                (?, ?, ?),
-                (?, ?, ?)
-            ON CONFLICT (user_id, market_id) DO UPDATE SET position = excluded.position;
+                (?, ?, ?, ?),
+                (?, ?, ?, ?)
+            ON CONFLICT (user_id, market_id) DO UPDATE SET
+                position = excluded.position,
+                cost = excluded.cost;
             ",
             taker_balance,
             taker_available,
@@ -188,16 +308,32 @@ impl State {
             trade.taker_id,
             trade.market_id,
             taker_position,
+            taker_cost,
             // update maker position params
             trade.maker_id,
             trade.market_id,
             maker_position,
+            maker_cost,
         )
         .execute(&mut *executor)
         .await
         .unwrap();
 
-        trade.insert(executor).await.unwrap();
+        let trade_id = trade.insert(executor).await.unwrap();
+        let legs = [
+            (trade.taker_id, trade.taker_oid, taker_before, taker_side),
+            (trade.maker_id, trade.maker_oid, maker_before, !taker_side),
+        ];
+        for (user_id, order_id, position, side) in legs {
+            for entry in trade_entries(&trade, user_id, position, side) {
+                entry
+                    .with_market(trade.market_id)
+                    .with_trade(trade_id, order_id)
+                    .insert(executor)
+                    .await
+                    .unwrap();
+            }
+        }
 
         sqlx::query!(
             "
@@ -217,6 +353,7 @@ impl State {
         .unwrap();
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn on_add<E>(
         &mut self,
         transaction: &mut E,
@@ -225,6 +362,7 @@ impl State {
         user_id: UserId,
         market_id: MarketId,
         mut order: Order,
+        fills: &[Fill],
     ) where
         for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
     {
@@ -232,11 +370,45 @@ impl State {
             .await
             .unwrap();
 
-        self.order_owner
-            .insert(order.id, OrderOwner { user_id, market_id });
+        order.quantity -= self
+            .on_fills(&mut *transaction, time, tick, user_id, market_id, order, fills)
+            .await;
+        if order.quantity > 0 {
+            let book = self.orderbooks.get_mut(&market_id).unwrap();
+            assert!(book.add(order, user_id, None).fills.is_empty());
+            self.manager.add_resting_order(user_id, market_id, order);
+            self.order_owner
+                .insert(order.id, OrderOwner { user_id, market_id });
 
-        let book = self.orderbooks.get_mut(&market_id).unwrap();
-        let fills = book.add(order);
+            let available = self.manager.get_available(user_id);
+            sqlx::query!(
+                "UPDATE user SET available = ? WHERE id = ?",
+                available,
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+        }
+    }
+
+    /// Records the trades of a taker order and takes them off the makers.
+    /// Returns the total quantity traded.
+    #[allow(clippy::too_many_arguments)]
+    async fn on_fills<E>(
+        &mut self,
+        transaction: &mut E,
+        time: Timestamp,
+        tick: Tick,
+        user_id: UserId,
+        market_id: MarketId,
+        order: Order,
+        fills: &[Fill],
+    ) -> Quantity
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        let mut traded = 0;
         for fill in fills {
             let trade = Trade {
                 id: 0,
@@ -244,7 +416,7 @@ impl State {
                 tick,
                 market_id,
                 taker_id: user_id,
-                maker_id: self.order_owner[&fill.id].user_id,
+                maker_id: fill.user,
                 taker_oid: order.id,
                 maker_oid: fill.id,
                 quantity: fill.quantity,
@@ -252,26 +424,98 @@ impl State {
                 is_buy: order.side.is_buy(),
             };
             self.on_trade(&mut *transaction, trade).await;
-            order.quantity -= fill.quantity;
+            let book = self.orderbooks.get_mut(&market_id).unwrap();
+            book.reduce(fill.id, fill.quantity).unwrap();
+            traded += fill.quantity;
             if fill.done {
                 self.order_owner.remove(&fill.id);
             }
         }
-        if order.quantity > 0 {
-            self.manager.add_resting_order(user_id, market_id, order);
-            self.order_owner
-                .insert(order.id, OrderOwner { user_id, market_id });
+        traded
+    }
 
-            let available = self.manager.get_available(user_id);
-            sqlx::query!(
-                "UPDATE user SET available = ? WHERE id = ?",
-                available,
-                user_id
-            )
-            .execute(&mut *transaction)
+    #[allow(clippy::too_many_arguments)]
+    async fn on_amend<E>(
+        &mut self,
+        transaction: &mut E,
+        time: Timestamp,
+        tick: Tick,
+        user_id: UserId,
+        market_id: MarketId,
+        mut order: Order,
+        fills: &[Fill],
+    ) where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        models::order::Order::amend(&mut *transaction, order)
             .await
             .unwrap();
+
+        let book = self.orderbooks.get_mut(&market_id).unwrap();
+        let old = book.get(order.id).unwrap();
+        self.manager.remove_order(user_id, market_id, old);
+
+        order.quantity -= self
+            .on_fills(&mut *transaction, time, tick, user_id, market_id, order, fills)
+            .await;
+        let book = self.orderbooks.get_mut(&market_id).unwrap();
+        assert!(book.amend(order).unwrap().fills.is_empty());
+        if order.quantity > 0 {
+            self.manager.add_resting_order(user_id, market_id, order);
+        } else {
+            self.order_owner.remove(&order.id);
+        }
+
+        let available = self.manager.get_available(user_id);
+        sqlx::query!(
+            "UPDATE user SET available = ? WHERE id = ?",
+            available,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+    }
+
+    /// Takes quantity off a user's resting orders cancelled by self-trade prevention.
+    async fn on_self_trade<E>(
+        &mut self,
+        transaction: &mut E,
+        user_id: UserId,
+        market_id: MarketId,
+        cancelled: &[Order],
+    ) where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        if cancelled.is_empty() {
+            return;
         }
+
+        let book = self.orderbooks.get_mut(&market_id).unwrap();
+        for &order in cancelled {
+            let left = book.reduce(order.id, order.quantity).unwrap();
+            if left.quantity == 0 {
+                models::order::Order::cancel_by_id(&mut *transaction, order.id)
+                    .await
+                    .unwrap();
+                self.order_owner.remove(&order.id);
+            } else {
+                models::order::Order::reduce(&mut *transaction, order.id, order.quantity)
+                    .await
+                    .unwrap();
+            }
+            self.manager.remove_order(user_id, market_id, order);
+        }
+
+        let available = self.manager.get_available(user_id);
+        sqlx::query!(
+            "UPDATE user SET available = ? WHERE id = ?",
+            available,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
     }
 
     async fn on_remove<E>(&mut self, transaction: &mut E, market_id: MarketId, id: OrderId)
@@ -300,8 +544,67 @@ impl State {
         .unwrap();
     }
 
-    async fn on_resolve<E>(&mut self, transaction: &mut E, market_id: MarketId, price: Price)
-    where
+    /// Merges complete sets in a mutually exclusive event for the taker and makers
+    /// of an update, and records the new balances and positions.
+    async fn on_merge<E>(
+        &mut self,
+        transaction: &mut E,
+        time: Timestamp,
+        taker_id: UserId,
+        market_id: MarketId,
+        fills: &[Fill],
+    ) where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        let Some(markets) = self.manager.exclusive_markets(market_id) else {
+            return;
+        };
+        let markets = markets.to_vec();
+
+        let users = std::iter::once(taker_id).chain(fills.iter().map(|fill| fill.user));
+        for user_id in users {
+            let before = self.manager.get_balance(user_id);
+            self.manager.merge_complete_sets(user_id, market_id);
+
+            let balance = self.manager.get_balance(user_id);
+            LedgerEntry::new(user_id, balance - before, LedgerReason::Merge, time)
+                .with_market(market_id)
+                .insert(transaction)
+                .await
+                .unwrap();
+            let available = self.manager.get_available(user_id);
+            sqlx::query!(
+                "UPDATE user SET balance = ?, available = ? WHERE id = ?",
+                balance,
+                available,
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+
+            for &market_id in &markets {
+                let position = self.manager.get_position(user_id, market_id);
+                sqlx::query!(
+                    "UPDATE position SET position = ? WHERE user_id = ? AND market_id = ?",
+                    position,
+                    user_id,
+                    market_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .unwrap();
+            }
+        }
+    }
+
+    async fn on_resolve<E>(
+        &mut self,
+        transaction: &mut E,
+        time: Timestamp,
+        market_id: MarketId,
+        price: Price,
+    ) where
         for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
     {
         models::market::Market::resolve(transaction, market_id, price)
@@ -320,7 +623,52 @@ impl State {
             .await
             .unwrap();
 
-        for user_id in self.manager.resolve(market_id, price) {
+        for (user_id, winnings) in self.manager.resolve(market_id, price) {
+            LedgerEntry::new(user_id, winnings, LedgerReason::Resolve, time)
+                .with_market(market_id)
+                .insert(transaction)
+                .await
+                .unwrap();
+            let balance = self.manager.get_balance(user_id);
+            let available = self.manager.get_available(user_id);
+            sqlx::query!(
+                "UPDATE user SET balance = ?, available = ? WHERE id = ?",
+                balance,
+                available,
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+        }
+    }
+
+    async fn on_void<E>(&mut self, transaction: &mut E, time: Timestamp, market_id: MarketId)
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Sqlite>,
+    {
+        models::market::Market::void(transaction, market_id)
+            .await
+            .unwrap();
+
+        self.orderbooks.remove(&market_id).unwrap();
+        self.order_owner
+            .retain(|_, order| order.market_id != market_id);
+
+        models::order::Order::cancel_for_event(transaction, market_id)
+            .await
+            .unwrap();
+
+        models::position::Position::delete_for_event(transaction, market_id)
+            .await
+            .unwrap();
+
+        for (user_id, refund) in self.manager.void(market_id) {
+            LedgerEntry::new(user_id, refund, LedgerReason::Void, time)
+                .with_market(market_id)
+                .insert(transaction)
+                .await
+                .unwrap();
             let balance = self.manager.get_balance(user_id);
             let available = self.manager.get_available(user_id);
             sqlx::query!(
@@ -336,14 +684,203 @@ impl State {
     }
 }
 
-pub fn start_writer_service(db: SqlitePool, mut feed: broadcast::Receiver<MarketUpdate>) {
+/// Splits what a trade cost one side into the price of the contracts, and the
+/// collateral put up for contracts created or returned for contracts combined.
+/// `position` is the user's position before the trade.
+fn trade_entries(trade: &Trade, user_id: UserId, position: Position, side: Side) -> [LedgerEntry; 2] {
+    let value = Balance::from(trade.quantity) * Balance::from(trade.price);
+    let (value, collateral, reason) = match side {
+        Side::Buy => {
+            let combined = contracts_combined(position, trade.quantity);
+            (-value, Balance::from(combined), LedgerReason::Combine)
+        }
+        Side::Sell => {
+            let created = contracts_created(position, trade.quantity);
+            (value, -Balance::from(created), LedgerReason::Create)
+        }
+    };
+    let collateral = collateral * Balance::from(RESOLVE_PRICE);
+    [
+        LedgerEntry::new(user_id, value, LedgerReason::Trade, trade.created_at),
+        LedgerEntry::new(user_id, collateral, reason, trade.created_at),
+    ]
+}
+
+pub fn start_writer_service(db: SqlitePool, mut feed: broadcast::Receiver<FeedUpdate>) {
     tokio::spawn({
         async move {
             info!("Starting writer service...");
-            let mut state = State::new(db).await;
+            let log = RollingFileAppender::new(Rotation::DAILY, LOG_DIR, LOG_PREFIX);
+            let (exchange, seq) = matcher::restore_exchange(&db).await;
+            let mut state = State::new(db, Some(log), &exchange.snapshot(seq));
             while let Ok(market) = feed.recv().await {
                 state.on_event(market).await;
             }
         }
     });
 }
+
+/// Returns the market data feed log files, oldest first.
+pub fn feed_log_paths() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(LOG_DIR) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_PREFIX))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Reads the updates from market data feed log files, in the order given.
+pub fn read_feed_log(paths: &[impl AsRef<Path>]) -> io::Result<Vec<FeedUpdate>> {
+    let mut updates = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let record: api::FeedRecord = serde_json::from_str(line).map_err(|err| {
+                let msg = format!("{}:{}: {err}", path.display(), number + 1);
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            })?;
+            updates.push(FeedUpdate {
+                seq: record.seq,
+                update: MarketUpdate::from(record.update),
+            });
+        }
+    }
+    Ok(updates)
+}
+
+/// Reads at most `limit` logged updates after the update with sequence number `after`.
+///
+/// Only the newest log files that can hold them are read, and the first of those is
+/// binary searched for where to start, so recent updates are cheap to read.
+pub fn read_feed_log_after(after: u64, limit: usize) -> io::Result<Vec<FeedUpdate>> {
+    let paths = feed_log_paths();
+    let mut start = paths.len();
+    while start > 0 {
+        start -= 1;
+        let mut file = BufReader::new(fs::File::open(&paths[start])?);
+        if next_record(&mut file, &paths[start], 0)?.is_some_and(|(_, seq)| seq <= after + 1) {
+            break;
+        }
+    }
+
+    let mut updates = Vec::new();
+    for (i, path) in paths.iter().enumerate().skip(start) {
+        let mut file = BufReader::new(fs::File::open(path)?);
+        let offset = if i == start {
+            seek_after(&mut file, path, after)?
+        } else {
+            0
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        for line in file.lines() {
+            if updates.len() >= limit {
+                return Ok(updates);
+            }
+            let record = parse_record(path, &line?)?;
+            if record.seq > after {
+                updates.push(record);
+            }
+        }
+    }
+    Ok(updates)
+}
+
+/// Returns the offset of the first record in a log file with a sequence number after `after`,
+/// or the end of the file if there is none.
+fn seek_after(file: &mut BufReader<fs::File>, path: &Path, after: u64) -> io::Result<u64> {
+    let len = file.get_ref().metadata()?.len();
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match next_record(file, path, mid)? {
+            Some((start, seq)) if seq <= after => lo = start + 1,
+            _ => hi = mid,
+        }
+    }
+    Ok(next_record(file, path, lo)?.map_or(len, |(start, _)| start))
+}
+
+/// Returns the offset and sequence number of the first record starting at or after `offset`.
+fn next_record(
+    file: &mut BufReader<fs::File>,
+    path: &Path,
+    offset: u64,
+) -> io::Result<Option<(u64, u64)>> {
+    #[derive(serde::Deserialize)]
+    struct Seq {
+        seq: u64,
+    }
+
+    let mut start = offset;
+    let mut line = String::new();
+    if offset > 0 {
+        // skip the rest of the record the offset is in
+        file.seek(SeekFrom::Start(offset - 1))?;
+        start = offset - 1 + file.read_line(&mut line)? as u64;
+        line.clear();
+    } else {
+        file.seek(SeekFrom::Start(0))?;
+    }
+    if file.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let record: Seq = serde_json::from_str(&line).map_err(|err| {
+        let msg = format!("{} at byte {start}: {err}", path.display());
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    })?;
+    Ok(Some((start, record.seq)))
+}
+
+fn parse_record(path: &Path, line: &str) -> io::Result<FeedUpdate> {
+    let record: api::FeedRecord = serde_json::from_str(line).map_err(|err| {
+        let msg = format!("{}: {err}", path.display());
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    })?;
+    Ok(FeedUpdate {
+        seq: record.seq,
+        update: MarketUpdate::from(record.update),
+    })
+}
+
+/// Records updates read back from the market data feed log, in order.
+/// The database should hold the state from before the first update.
+pub async fn replay(db: SqlitePool, updates: impl IntoIterator<Item = FeedUpdate>) {
+    let exchange = matcher::bootstrap_exchange(&db).await;
+    let mut state = State::new(db, None, &exchange.snapshot(0));
+    for update in updates {
+        state.on_event(update).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::BufReader;
+
+    use super::seek_after;
+
+    #[test]
+    fn test_seek_after() {
+        let path = std::env::temp_dir().join(format!("qposit_seek_after_{}.log", std::process::id()));
+        let lines: Vec<String> = (1..=50)
+            .map(|seq| format!("{{\"seq\":{seq},\"padding\":\"{}\"}}\n", "x".repeat(seq % 7)))
+            .collect();
+        fs::write(&path, lines.concat()).unwrap();
+
+        let mut file = BufReader::new(fs::File::open(&path).unwrap());
+        for after in 0..=50 {
+            let offset: usize = lines.iter().take(after).map(String::len).sum();
+            assert_eq!(seek_after(&mut file, &path, after as u64).unwrap(), offset as u64);
+        }
+        assert_eq!(seek_after(&mut file, &path, 100).unwrap(), fs::metadata(&path).unwrap().len());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
 
 pub async fn connect_to_database() -> SqlitePool {
@@ -8,6 +10,24 @@ pub async fn connect_to_database() -> SqlitePool {
         .expect("Failed to connect to database")
 }
 
+/// The comma separated addresses in `TRUSTED_PROXIES` of the reverse proxies in front
+/// of the server, whose `X-Forwarded-For` headers are believed. None if unset.
+pub fn trusted_proxies() -> Vec<IpAddr> {
+    let Ok(list) = dotenvy::var("TRUSTED_PROXIES") else {
+        return Vec::new();
+    };
+    list.split(',')
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .map(|ip| {
+            ip.parse::<IpAddr>().map_or_else(
+                |_| panic!("Invalid address in TRUSTED_PROXIES: {ip}"),
+                |ip| ip.to_canonical(),
+            )
+        })
+        .collect()
+}
+
 /// Crashes the whole application if any task panics.
 pub fn register_panic_hook() {
     let default_panic = std::panic::take_hook();
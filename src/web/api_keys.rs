@@ -0,0 +1,74 @@
+//! Creating and revoking API keys from the profile page.
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Form,
+};
+use serde::Deserialize;
+
+use crate::{
+    app_state::{current_time_micros, AppState},
+    models::api_key::{self, ApiKey, Scope},
+};
+
+use super::{auth::SessionExtractor, templates::api_keys::ApiKeys};
+
+#[derive(Debug, Deserialize)]
+pub struct PostApiKey {
+    name: String,
+    scope: Scope,
+    #[serde(default)]
+    ip_allowlist: String,
+}
+
+pub async fn post(
+    State(state): State<AppState>,
+    SessionExtractor(user): SessionExtractor,
+    Form(form): Form<PostApiKey>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let keys = ApiKeys::build(&state.pool, user.id).await;
+
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > 50 {
+        let message = "Error: name must be 1 to 50 characters.".to_string();
+        return keys.with_message(message).into_response();
+    }
+    let ip_allowlist = match api_key::parse_ip_allowlist(&form.ip_allowlist) {
+        Ok(ip_allowlist) => ip_allowlist,
+        Err(ip) => {
+            let message = format!("Error: {ip} is not an IP address.");
+            return keys.with_message(message).into_response();
+        }
+    };
+
+    let (_, key) = ApiKey::create(
+        &state.pool,
+        user.id,
+        name,
+        form.scope,
+        ip_allowlist.as_deref(),
+        current_time_micros(),
+    )
+    .await
+    .unwrap();
+    ApiKeys::build(&state.pool, user.id)
+        .await
+        .with_new_key(key)
+        .into_response()
+}
+
+pub async fn delete_by_id(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    SessionExtractor(user): SessionExtractor,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    ApiKey::delete(&state.pool, user.id, id).await.unwrap();
+    Html("").into_response()
+}
@@ -1,7 +1,8 @@
 use axum::async_trait;
-use axum::extract::FromRequestParts;
+use axum::extract::{FromRequestParts, Request, State};
 use axum::http::request::Parts;
 use axum::http::StatusCode;
+use axum::middleware::Next;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use axum_extra::extract::{cookie::Cookie, cookie::SameSite, CookieJar};
@@ -9,13 +10,18 @@ use axum_extra::headers::UserAgent;
 use sqlx::{Executor, Sqlite, SqlitePool};
 use tracing::warn;
 
-use crate::app_state::AppState;
+use crate::app_state::{current_time_micros, AppState};
 use crate::models;
 use crate::models::session::Session;
 use crate::models::user::User;
 use lobster::Timestamp;
 use lobster::UserId;
 
+/// How long a session lasts without being used, in microseconds.
+const SESSION_LIFETIME: Timestamp = 7 * 24 * 60 * 60 * 1_000_000;
+/// How often the expiry of a session in use is pushed back, in microseconds.
+const SESSION_REFRESH_INTERVAL: Timestamp = 24 * 60 * 60 * 1_000_000;
+
 /// Generates a random 128-bit hex string.
 fn generate_session_id() -> String {
     format!("{:#018x}", rand::random::<u128>())
@@ -28,7 +34,7 @@ fn build_session_cookie(session_id: &str) -> Cookie<'static> {
         .same_site(SameSite::Strict)
         .secure(IS_RELEASE)
         .http_only(true)
-        .max_age(time::Duration::WEEK)
+        .max_age(time::Duration::microseconds(SESSION_LIFETIME))
         .build()
         .into_owned()
 }
@@ -48,7 +54,7 @@ pub async fn create_session<'c, E: Executor<'c, Database = Sqlite>>(
         ip_address,
         user_agent: user_agent.to_string(),
         created_at: time,
-        expires_at: 0, // todo
+        expires_at: time + SESSION_LIFETIME,
     };
     session.insert(pool).await.unwrap();
     build_session_cookie(&id)
@@ -69,7 +75,46 @@ pub async fn login(
     Some(cookie)
 }
 
-/// None if the session is invalid.
+/// Deletes the session the request's cookie is for, if any.
+pub async fn end_session(db: &SqlitePool, jar: &CookieJar) {
+    if let Some(cookie) = jar.get("session_id") {
+        Session::delete_by_id(db, cookie.value())
+            .await
+            .expect("failed to delete session id from database");
+    }
+}
+
+/// Pushes back the expiry of the request's session, and of its cookie, while it is in use.
+pub async fn refresh_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let Some(session_id) = jar.get("session_id").map(|cookie| cookie.value().to_string()) else {
+        return response;
+    };
+    let time = current_time_micros();
+    let refreshed = Session::refresh(
+        &state.pool,
+        &session_id,
+        time,
+        time + SESSION_LIFETIME - SESSION_REFRESH_INTERVAL,
+        time + SESSION_LIFETIME,
+    )
+    .await;
+    match refreshed {
+        Ok(true) => (jar.add(build_session_cookie(&session_id)), response).into_response(),
+        Ok(false) => response,
+        Err(err) => {
+            warn!(err = ?err, "Failed to refresh session");
+            response
+        }
+    }
+}
+
+/// None if the session is invalid or expired.
 pub struct SessionExtractor(pub Option<User>);
 
 #[async_trait]
@@ -86,8 +131,8 @@ impl FromRequestParts<AppState> for SessionExtractor {
             return Ok(Self(None));
         };
         let session = match Session::get_by_id(&state.pool, session_id).await {
-            Ok(Some(session)) => session,
-            Ok(None) => return Ok(Self(None)),
+            Ok(Some(session)) if session.expires_at > current_time_micros() => session,
+            Ok(_) => return Ok(Self(None)),
             Err(err) => {
                 warn!(err = ?err, "Failed to get session");
                 return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
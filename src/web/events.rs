@@ -33,7 +33,7 @@ pub async fn get(
         let orderbook = models::order::Order::build_orderbook(&state.pool, market.id)
             .await
             .unwrap();
-        let book_data = MarketData::new(&market, orderbook);
+        let book_data = MarketData::new(&market, &orderbook);
         new_things.push((market, book_data));
     }
 
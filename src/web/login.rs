@@ -2,6 +2,7 @@ use std::net::SocketAddr;
 
 use axum::{
     extract::{ConnectInfo, Path, State},
+    http::StatusCode,
     response::{Html, IntoResponse, Redirect},
     Form,
 };
@@ -57,6 +58,8 @@ pub async fn post(
     {
         Some(cookie) => {
             info!("User {} logged in", form.username);
+            // The new session replaces any the browser had, so its id changes on login.
+            auth::end_session(&state.pool, &jar).await;
             ([("HX-Redirect", "/")], jar.add(cookie)).into_response()
         }
         None => login::LoginForm {
@@ -68,11 +71,7 @@ pub async fn post(
 
 pub async fn delete(jar: CookieJar, State(state): State<AppState>) -> impl IntoResponse {
     info!("DELETE /login");
-    if let Some(cookie) = jar.get("session_id") {
-        Session::delete_by_id(&state.pool, cookie.value())
-            .await
-            .expect("failed to delete session id from database");
-    }
+    auth::end_session(&state.pool, &jar).await;
     (
         [("HX-Redirect", "/")],
         jar.remove(Cookie::build("session_id")),
@@ -80,12 +79,40 @@ pub async fn delete(jar: CookieJar, State(state): State<AppState>) -> impl IntoR
         .into_response()
 }
 
+/// Revokes one of the user's sessions. Revoking the current one logs out.
 pub async fn delete_by_id(
+    jar: CookieJar,
+    SessionExtractor(user): SessionExtractor,
     Path(session_id): Path<String>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    Session::delete_by_id(&state.pool, &session_id)
+    let Some(user) = user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if jar.get("session_id").map(Cookie::value) == Some(session_id.as_str()) {
+        return delete(jar, State(state)).await.into_response();
+    }
+    Session::delete_for_user(&state.pool, user.id, &session_id)
         .await
         .unwrap();
     Html("").into_response()
 }
+
+/// Revokes all of the user's sessions, logging them out everywhere.
+pub async fn delete_all(
+    jar: CookieJar,
+    SessionExtractor(user): SessionExtractor,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let Some(user) = user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    Session::delete_all_for_user(&state.pool, user.id)
+        .await
+        .unwrap();
+    (
+        [("HX-Redirect", "/")],
+        jar.remove(Cookie::build("session_id")),
+    )
+        .into_response()
+}
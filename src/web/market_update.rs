@@ -1,16 +1,15 @@
-use std::collections::HashSet;
-
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::WebSocket;
 use axum::extract::{Query, State, WebSocketUpgrade};
 use lobster::MarketId;
 use serde::Deserialize;
 use utoipa::ToSchema;
 
 use crate::app_state::AppState;
+use crate::services::book_service;
 
-use super::templates::market_update::MarketUpdate;
+use super::templates::market_update::{MarketDelta, MarketUpdate};
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct EventParams {
@@ -26,26 +25,22 @@ pub async fn get(
     ws.on_upgrade(|socket| handle_socket(state, socket, params))
 }
 
-async fn handle_socket(mut state: AppState, mut socket: WebSocket, params: EventParams) {
+async fn handle_socket(state: AppState, socket: WebSocket, params: EventParams) {
     let Ok(markets) = params
         .markets
         .split(',')
         .map(|x| x.parse())
-        .collect::<Result<HashSet<MarketId>, _>>()
+        .collect::<Result<Vec<MarketId>, _>>()
     else {
         return;
     };
 
-    loop {
-        let market = state.book_receive.recv().await.expect("Sender dropped");
-
-        if !markets.contains(&market.market_id) {
-            continue;
-        }
-
-        let text = MarketUpdate::from(&market).render().unwrap();
-        if socket.send(Message::Text(text)).await.is_err() {
-            return;
-        }
-    }
+    book_service::stream_books(
+        state,
+        socket,
+        markets,
+        |_, market| MarketUpdate::from(market).render().unwrap(),
+        |_, before, after| MarketDelta::new(before, after).render().unwrap(),
+    )
+    .await;
 }
@@ -1,4 +1,5 @@
 mod about;
+mod api_keys;
 mod auth;
 mod events;
 mod home;
@@ -13,6 +14,7 @@ pub use templates::market_update::MarketUpdate;
 
 use axum::{
     http::header,
+    middleware,
     response::IntoResponse,
     routing::{delete, get, post},
     Router,
@@ -53,11 +55,6 @@ async fn get_htmx_ws() -> impl IntoResponse {
 
 pub fn router(state: AppState) -> Router {
     Router::new()
-        .route("/pico.min.css", get(get_pico_css))
-        .route("/pico.colors.min.css", get(get_pico_colors))
-        .route("/main.css", get(get_main_css))
-        .route("/htmx.min.js", get(get_htmx))
-        .route("/htmx.ws.js", get(get_htmx_ws))
         .route("/", get(home::get))
         .route("/about", get(about::get))
         // .route("/profile", get(profile::get))
@@ -67,10 +64,22 @@ pub fn router(state: AppState) -> Router {
             get(login::get).post(login::post).delete(login::delete),
         )
         .route("/login/:session_id", delete(login::delete_by_id))
+        .route("/sessions", delete(login::delete_all))
         .route("/signup", get(signup::get).post(signup::post))
         .route("/events/:slug", get(events::get))
         .route("/orders", post(orders::post))
         .route("/orders/:order_id", delete(orders::delete_by_id))
+        .route("/api-keys", post(api_keys::post))
+        .route("/api-keys/:id", delete(api_keys::delete_by_id))
         .route("/orderbook", get(market_update::get))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::refresh_session,
+        ))
+        .route("/pico.min.css", get(get_pico_css))
+        .route("/pico.colors.min.css", get(get_pico_colors))
+        .route("/main.css", get(get_main_css))
+        .route("/htmx.min.js", get(get_htmx))
+        .route("/htmx.ws.js", get(get_htmx_ws))
         .with_state(state)
 }
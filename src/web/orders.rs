@@ -20,6 +20,8 @@ use super::auth::SessionExtractor;
 pub enum OrderType {
     Limit,
     Market,
+    FillOrKill,
+    AllOrNone,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +35,31 @@ pub struct PostOrder {
     order_type: OrderType,
 }
 
+/// The message shown on the order form when an order is rejected.
+const fn reject_message(err: RejectReason) -> &'static str {
+    match err {
+        RejectReason::InvalidPrice => "Error: Invalid price",
+        RejectReason::MarketNotFound => "Error: Invalid market",
+        RejectReason::IOCNotMarketable => "Error: Order not marketable",
+        RejectReason::FOKNotFillable => "Error: Not enough liquidity to fill the order",
+        RejectReason::InvalidExpiry => "Error: Invalid expiry",
+        RejectReason::DuplicateClientOrderId => "Error: Duplicate client order id",
+        RejectReason::InvalidQuantity => "Error: Invalid quantity",
+        RejectReason::InsufficientFunds => "Error: Insufficient funds",
+        RejectReason::InvalidAmount => "Error: Invalid amount",
+        RejectReason::OrderNotFound => "Error: Order not found",
+        RejectReason::MarketAlreadyExists => "Error: Market already exists",
+        RejectReason::InvalidExclusiveEvent => "Error: Invalid exclusive event",
+        RejectReason::InvalidResolution => "Error: Invalid resolution",
+        RejectReason::MaxOrderQuantityExceeded => "Error: Order quantity above limit",
+        RejectReason::MaxOrderNotionalExceeded => "Error: Order value above limit",
+        RejectReason::MaxPositionExceeded => "Error: Position limit reached",
+        RejectReason::MaxOpenOrdersExceeded => "Error: Too many open orders",
+        RejectReason::PriceOutsideBand => "Error: Price too far from last trade",
+        RejectReason::MarketHalted => "Error: Market is not open for trading",
+    }
+}
+
 pub async fn post(
     State(state): State<AppState>,
     SessionExtractor(user): SessionExtractor,
@@ -87,7 +114,12 @@ pub async fn post(
         tif: match form.order_type {
             OrderType::Market => lobster::TimeInForce::IOC,
             OrderType::Limit => lobster::TimeInForce::GTC,
+            OrderType::FillOrKill => lobster::TimeInForce::FOK,
+            OrderType::AllOrNone => lobster::TimeInForce::AON,
         },
+        stp: None,
+        expires_at: None,
+        client_order_id: None,
     };
 
     let (req, recv) = MatcherRequest::submit(user.id, req);
@@ -105,15 +137,7 @@ pub async fn post(
         )
         .into_response(),
         Err(err) => {
-            let msg = match err {
-                RejectReason::InvalidPrice => "Error: Invalid price",
-                RejectReason::MarketNotFound => "Error: Invalid market",
-                RejectReason::IOCNotMarketable => "Error: Order not marketable",
-                RejectReason::InvalidQuantity => "Error: Invalid quantity",
-                RejectReason::InsufficientFunds => "Error: Insufficient funds",
-                RejectReason::OrderNotFound => "Error: Order not found",
-                RejectReason::MarketAlreadyExists => "Error: Market already exists",
-            };
+            let msg = reject_message(err);
             OrderForm::with_messages(
                 market_id,
                 form.quantity,
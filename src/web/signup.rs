@@ -73,6 +73,7 @@ pub async fn post(
     match Invite::check_and_claim(&mut *tx, &form.invite_code, user_id).await {
         Ok(Some(_)) => {
             tx.commit().await.unwrap();
+            auth::end_session(&state.pool, &jar).await;
             let cookie = auth::create_session(
                 &state.pool,
                 user_id,
@@ -0,0 +1,65 @@
+use askama::Template;
+use lobster::UserId;
+use sqlx::SqlitePool;
+
+use crate::models::api_key::{ApiKey, Scope};
+
+use super::format_timestamp_as_string;
+
+struct ApiKeyAsHtml {
+    id: i64,
+    name: String,
+    prefix: String,
+    scope: String,
+    ip_allowlist: String,
+    created_at: String,
+}
+
+impl From<ApiKey> for ApiKeyAsHtml {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            scope: match key.scope {
+                Scope::Read => "Read",
+                Scope::Trade => "Trade",
+                Scope::Admin => "Admin",
+            }
+            .to_string(),
+            ip_allowlist: key.ip_allowlist.unwrap_or_else(|| "Any".to_string()),
+            created_at: format_timestamp_as_string(key.created_at),
+        }
+    }
+}
+
+/// The user's API keys, with a form to create more.
+#[derive(Template)]
+#[template(path = "api_keys.html")]
+pub struct ApiKeys {
+    keys: Vec<ApiKeyAsHtml>,
+    /// A key that was just created.
+    new_key: String,
+    message: String,
+}
+
+impl ApiKeys {
+    pub async fn build(db: &SqlitePool, user: UserId) -> Self {
+        let keys = ApiKey::get_all_for_user(db, user).await.unwrap();
+        Self {
+            keys: keys.into_iter().map(ApiKeyAsHtml::from).collect(),
+            new_key: String::new(),
+            message: String::new(),
+        }
+    }
+
+    pub fn with_new_key(mut self, key: String) -> Self {
+        self.new_key = key;
+        self
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+}
@@ -1,9 +1,10 @@
 use askama::Template;
+use std::collections::HashMap;
 use lobster::MarketId;
 
 use crate::services::book_service::MarketData;
 
-use super::orderbook::OrderBook;
+use super::orderbook::{OrderBook, PriceLevel};
 use super::{display_price, format_balance_to_dollars};
 
 /// Book data delivered over websocket feed.
@@ -30,7 +31,70 @@ impl From<&MarketData> for MarketUpdate {
             market_id: market.market_id,
             display_price,
             volume,
-            orderbook: OrderBook::new(market.market_id, &market.book),
+            orderbook: OrderBook::new(market.market_id, &market.depth),
+        }
+    }
+}
+
+/// The parts of a rendered market that changed between two states of it.
+#[derive(Template, Debug, Clone)]
+#[template(path = "market_delta.html")]
+pub struct MarketDelta {
+    pub market_id: MarketId,
+    /// Set if the displayed price changed.
+    pub display_price: Option<String>,
+    /// Set if the volume changed.
+    pub volume: Option<String>,
+    /// The ids of the rows of levels that are gone.
+    pub removed: Vec<String>,
+    /// The rows whose quantity or total changed.
+    pub changed: Vec<PriceLevel>,
+    /// The rows of new levels, each with the swap that inserts it in price order.
+    pub added: Vec<(String, PriceLevel)>,
+}
+
+impl MarketDelta {
+    pub fn new(before: &MarketData, after: &MarketData) -> Self {
+        let (old, new) = (MarketUpdate::from(before), MarketUpdate::from(after));
+        let old_rows: HashMap<&str, &PriceLevel> = old
+            .orderbook
+            .rows()
+            .map(|level| (level.id.as_str(), level))
+            .collect();
+        let new_rows: Vec<&PriceLevel> = new.orderbook.rows().collect();
+
+        let removed = old_rows
+            .keys()
+            .filter(|id| !new_rows.iter().any(|level| level.id == **id))
+            .map(|id| (*id).to_string())
+            .collect();
+        let mut changed = Vec::new();
+        let mut added = Vec::new();
+        // new rows are inserted before the next row, so the lowest go first
+        for (i, level) in new_rows.iter().enumerate().rev() {
+            match old_rows.get(level.id.as_str()) {
+                Some(&old_level) if old_level == *level => {}
+                Some(_) => changed.push(PriceLevel {
+                    oob: true,
+                    ..(*level).clone()
+                }),
+                None => {
+                    let swap = new_rows.get(i + 1).map_or_else(
+                        || format!("beforeend:#orderbook-{} tbody", after.market_id),
+                        |next| format!("beforebegin:#{}", next.id),
+                    );
+                    added.push((swap, (*level).clone()));
+                }
+            }
+        }
+
+        Self {
+            market_id: after.market_id,
+            display_price: (new.display_price != old.display_price).then_some(new.display_price),
+            volume: (new.volume != old.volume).then_some(new.volume),
+            removed,
+            changed,
+            added,
         }
     }
 }
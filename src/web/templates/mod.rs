@@ -5,6 +5,7 @@ use time::{
 };
 
 pub mod about_page;
+pub mod api_keys;
 pub mod event;
 pub mod home_page;
 pub mod login;
@@ -15,6 +16,7 @@ pub mod order_form;
 pub mod orderbook;
 pub mod positions;
 pub mod profile;
+pub mod sessions;
 pub mod signup;
 
 /// Formats a price to a string with two decimals.
@@ -1,55 +1,67 @@
 use askama::Template;
 use lobster::{Balance, MarketId};
-use lobster::{Order, Price, Quantity};
+use lobster::{Price, Quantity, Side};
+
+use crate::services::book_service::Depth;
 
 use super::{format_balance_to_dollars, format_price_to_string};
 
-#[derive(Debug, Clone)]
+/// A row of an order book table.
+#[derive(Template, Debug, Clone, PartialEq, Eq)]
+#[template(path = "orderbook_level.html")]
 pub struct PriceLevel {
+    pub id: String,
+    pub is_buy: bool,
     pub price: String,
     pub quantity: String,
     pub value: String,
+    /// Whether the row replaces the rendered row with the same id.
+    pub oob: bool,
 }
 
 impl PriceLevel {
-    pub fn new(price: Price, quantity: Quantity, cumulative_value: Balance) -> Self {
+    pub fn new(
+        market_id: MarketId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        cumulative_value: Balance,
+    ) -> Self {
         Self {
+            id: level_id(market_id, side, price),
+            is_buy: side.is_buy(),
             price: format_price_to_string(price),
             quantity: quantity.to_string(),
             value: format_balance_to_dollars(cumulative_value),
+            oob: false,
         }
     }
 }
 
+/// The element id of a level's row, e.g. `bid-4900-1`.
+fn level_id(market_id: MarketId, side: Side, price: Price) -> String {
+    let side = if side.is_buy() { "bid" } else { "ask" };
+    format!("{side}-{price}-{market_id}")
+}
+
 /// Computes the price levels for a side of an order book.
 ///
 /// # Arguments
 ///
-/// - `orders`: iterable of orders sorted from best price to worst price.
-pub fn do_side(orders: impl IntoIterator<Item = Order>) -> Vec<PriceLevel> {
-    let mut price_levels: Vec<PriceLevel> = Vec::new();
-    let mut current_price: Option<Price> = None;
-    let mut level_quantity = 0;
+/// - `levels`: iterable of prices and their total quantity, sorted from best price to worst price.
+pub fn do_side(
+    market_id: MarketId,
+    side: Side,
+    levels: impl IntoIterator<Item = (Price, Quantity)>,
+) -> Vec<PriceLevel> {
     let mut cumulative_value: Balance = 0;
-
-    for order in orders {
-        if current_price == Some(order.price) {
-            level_quantity += order.quantity;
-            cumulative_value += Balance::from(order.quantity) * Balance::from(order.price);
-        } else {
-            if let Some(price) = current_price {
-                price_levels.push(PriceLevel::new(price, level_quantity, cumulative_value));
-            }
-            level_quantity = order.quantity;
-            cumulative_value += Balance::from(order.quantity) * Balance::from(order.price);
-            current_price = Some(order.price);
-        }
-    }
-
-    if let Some(price) = current_price {
-        price_levels.push(PriceLevel::new(price, level_quantity, cumulative_value));
-    }
-    price_levels
+    levels
+        .into_iter()
+        .map(|(price, quantity)| {
+            cumulative_value += Balance::from(quantity) * Balance::from(price);
+            PriceLevel::new(market_id, side, price, quantity, cumulative_value)
+        })
+        .collect()
 }
 
 /// Book data delivered over websocket feed.
@@ -63,11 +75,16 @@ pub struct OrderBook {
 }
 
 impl OrderBook {
-    pub fn new(market_id: MarketId, orderbook: &lobster::OrderBook) -> Self {
+    pub fn new(market_id: MarketId, depth: &Depth) -> Self {
         Self {
             market_id,
-            bids: do_side(orderbook.bids()),
-            asks: do_side(orderbook.asks()),
+            bids: do_side(market_id, Side::Buy, depth.bids()),
+            asks: do_side(market_id, Side::Sell, depth.asks()),
         }
     }
+
+    /// Returns the rows in the order they are rendered, from the highest price to the lowest.
+    pub fn rows(&self) -> impl Iterator<Item = &PriceLevel> {
+        self.asks.iter().rev().chain(&self.bids)
+    }
 }
@@ -2,7 +2,10 @@ use askama::Template;
 
 use crate::models::user::User;
 
-use super::{format_balance_to_dollars, format_timestamp_as_string, open_orders, positions};
+use super::{
+    api_keys, format_balance_to_dollars, format_timestamp_as_string, open_orders, positions,
+    sessions,
+};
 
 #[derive(Template)]
 #[template(path = "profile.html")]
@@ -14,6 +17,10 @@ pub struct Profile {
     available: String,
     positions: positions::Positions,
     open_orders: open_orders::OpenOrders,
+    /// Only shown to the user themselves.
+    api_keys: Option<api_keys::ApiKeys>,
+    /// Only shown to the user themselves.
+    sessions: Option<sessions::Sessions>,
 }
 
 impl Profile {
@@ -22,6 +29,8 @@ impl Profile {
         user: User,
         positions: positions::Positions,
         open_orders: open_orders::OpenOrders,
+        api_keys: Option<api_keys::ApiKeys>,
+        sessions: Option<sessions::Sessions>,
     ) -> Self {
         Self {
             username: logged_in_user.map(|u| u.username).unwrap_or_default(),
@@ -31,6 +40,8 @@ impl Profile {
             available: format_balance_to_dollars(user.available),
             positions,
             open_orders,
+            api_keys,
+            sessions,
         }
     }
 }
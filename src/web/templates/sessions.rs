@@ -0,0 +1,43 @@
+use askama::Template;
+use lobster::UserId;
+use sqlx::SqlitePool;
+
+use crate::models::session::Session;
+
+use super::format_timestamp_as_string;
+
+struct SessionAsHtml {
+    id: String,
+    ip_address: String,
+    user_agent: String,
+    created_at: String,
+    expires_at: String,
+    /// Whether this is the session viewing the page.
+    current: bool,
+}
+
+/// The user's sessions, with links to revoke them.
+#[derive(Template)]
+#[template(path = "sessions.html")]
+pub struct Sessions {
+    sessions: Vec<SessionAsHtml>,
+}
+
+impl Sessions {
+    pub async fn build(db: &SqlitePool, user: UserId, current: Option<&str>) -> Self {
+        let sessions = Session::get_all_for_user(db, user).await.unwrap();
+        Self {
+            sessions: sessions
+                .into_iter()
+                .map(|session| SessionAsHtml {
+                    current: current == Some(session.id.as_str()),
+                    id: session.id,
+                    ip_address: session.ip_address,
+                    user_agent: session.user_agent,
+                    created_at: format_timestamp_as_string(session.created_at),
+                    expires_at: format_timestamp_as_string(session.expires_at),
+                })
+                .collect(),
+        }
+    }
+}
@@ -1,14 +1,16 @@
 use super::{
     auth::SessionExtractor,
-    templates::{open_orders, positions, profile},
+    templates::{api_keys, open_orders, positions, profile, sessions},
 };
 use crate::{app_state::AppState, models};
 use axum::{
     extract::{Path, State},
     response::IntoResponse,
 };
+use axum_extra::extract::{cookie::Cookie, CookieJar};
 
 pub async fn get(
+    jar: CookieJar,
     SessionExtractor(logged_in_user): SessionExtractor,
     Path(username): Path<String>,
     State(state): State<AppState>,
@@ -18,12 +20,24 @@ pub async fn get(
         .unwrap();
 
     let user_id = user.id;
+    let (api_keys, sessions) = match &logged_in_user {
+        Some(logged_in_user) if logged_in_user.id == user_id => {
+            let current = jar.get("session_id").map(Cookie::value);
+            (
+                Some(api_keys::ApiKeys::build(&state.pool, user_id).await),
+                Some(sessions::Sessions::build(&state.pool, user_id, current).await),
+            )
+        }
+        _ => (None, None),
+    };
 
     profile::Profile::new(
         logged_in_user,
         user,
         positions::Positions::build(&state.pool, user_id).await,
         open_orders::OpenOrders::build(&state.pool, user_id).await,
+        api_keys,
+        sessions,
     )
     .into_response()
 }